@@ -0,0 +1,42 @@
+use crate::parser::Statement;
+use crate::{statement_size, AnalysisResult};
+
+/// The code one source line assembled to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineEntry {
+    /// `<root>` for the assembled file, else the path of the include.
+    pub file: String,
+    /// 0-based line in `file`.
+    pub line: usize,
+    pub addr: u16,
+    pub size: u16,
+}
+
+/// Maps every line that emits instructions to its address range, in program
+/// order. Lines of a macro expansion all map to the line that invoked it, and
+/// consecutive statements from one line are merged into a single entry.
+pub fn line_table(analysis: &AnalysisResult) -> Vec<LineEntry> {
+    let mut table: Vec<LineEntry> = Vec::new();
+    for (idx, stmt) in analysis.statements.iter().enumerate() {
+        if !matches!(stmt, Statement::Inst(_) | Statement::Pseudo { .. }) {
+            continue;
+        }
+        let (Some(&addr), Some(&expanded_line)) = (analysis.addresses.get(idx), analysis.statement_lines.get(idx))
+        else {
+            continue;
+        };
+        let size = statement_size(stmt, addr);
+        if size == 0 {
+            continue;
+        }
+        let (file, line) = analysis.source_map.resolve(expanded_line);
+        if let Some(last) = table.last_mut() {
+            if last.file == file && last.line == line && last.addr + last.size == addr {
+                last.size += size;
+                continue;
+            }
+        }
+        table.push(LineEntry { file: file.to_string(), line, addr, size });
+    }
+    table
+}
@@ -113,6 +113,65 @@ impl Instr {
         op.to_be_bytes()
     }
 
+    /// The instruction an opcode encodes, or `None` for data that isn't one.
+    /// `8XY6`/`8XYE` decode to `SHR`/`SHL` of VX whatever Y is.
+    pub fn decode(op: u16) -> Option<Instr> {
+        let x = ((op >> 8) & 0xF) as u8;
+        let y = ((op >> 4) & 0xF) as u8;
+        let n = (op & 0xF) as u8;
+        let kk = (op & 0xFF) as u8;
+        let addr = op & 0x0FFF;
+        Some(match op >> 12 {
+            0x0 => match op {
+                0x00E0 => Instr::Cls,
+                0x00EE => Instr::Ret,
+                _ => return None,
+            },
+            0x1 => Instr::Jp(addr),
+            0x2 => Instr::Call(addr),
+            0x3 => Instr::SeVb(x, kk),
+            0x4 => Instr::SneVb(x, kk),
+            0x5 if n == 0 => Instr::SeVV(x, y),
+            0x6 => Instr::LdVb(x, kk),
+            0x7 => Instr::AddVb(x, kk),
+            0x8 => match n {
+                0x0 => Instr::LdVV(x, y),
+                0x1 => Instr::Or(x, y),
+                0x2 => Instr::And(x, y),
+                0x3 => Instr::Xor(x, y),
+                0x4 => Instr::AddVV(x, y),
+                0x5 => Instr::Sub(x, y),
+                0x6 => Instr::Shr(x),
+                0x7 => Instr::Subn(x, y),
+                0xE => Instr::Shl(x),
+                _ => return None,
+            },
+            0x9 if n == 0 => Instr::SneVV(x, y),
+            0xA => Instr::LdI(addr),
+            0xB => Instr::JpV0(addr),
+            0xC => Instr::Rnd(x, kk),
+            0xD => Instr::Drw(x, y, n),
+            0xE => match kk {
+                0x9E => Instr::Skp(x),
+                0xA1 => Instr::Sknp(x),
+                _ => return None,
+            },
+            0xF => match kk {
+                0x07 => Instr::LdVdt(x),
+                0x0A => Instr::LdK(x),
+                0x15 => Instr::LdDt(x),
+                0x18 => Instr::LdSt(x),
+                0x1E => Instr::AddI(x),
+                0x29 => Instr::LdF(x),
+                0x33 => Instr::LdB(x),
+                0x55 => Instr::LdIV(x),
+                0x65 => Instr::LdVI(x),
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+
     pub fn mnemonic(&self) -> &'static str {
         match self {
             Instr::Cls => "CLS",
@@ -151,6 +210,44 @@ impl Instr {
             Instr::LdVI(_) => "LD",
         }
     }
+
+    /// Assembly text with resolved operands, e.g. `DRW V0, V1, 5`.
+    pub fn disassemble(&self) -> String {
+        let m = self.mnemonic();
+        match *self {
+            Instr::Cls | Instr::Ret => m.to_string(),
+            Instr::Jp(a) | Instr::Call(a) => format!("{} 0x{:03X}", m, a),
+            Instr::JpV0(a) => format!("{} V0, 0x{:03X}", m, a),
+            Instr::LdI(a) => format!("{} I, 0x{:03X}", m, a),
+            Instr::SeVb(x, kk)
+            | Instr::SneVb(x, kk)
+            | Instr::LdVb(x, kk)
+            | Instr::AddVb(x, kk)
+            | Instr::Rnd(x, kk) => format!("{} V{:X}, 0x{:02X}", m, x, kk),
+            Instr::SeVV(x, y)
+            | Instr::LdVV(x, y)
+            | Instr::Or(x, y)
+            | Instr::And(x, y)
+            | Instr::Xor(x, y)
+            | Instr::AddVV(x, y)
+            | Instr::Sub(x, y)
+            | Instr::Subn(x, y)
+            | Instr::SneVV(x, y) => format!("{} V{:X}, V{:X}", m, x, y),
+            Instr::Shr(x) | Instr::Shl(x) | Instr::Skp(x) | Instr::Sknp(x) => {
+                format!("{} V{:X}", m, x)
+            }
+            Instr::Drw(x, y, n) => format!("{} V{:X}, V{:X}, {}", m, x, y, n),
+            Instr::LdVdt(x) => format!("{} V{:X}, DT", m, x),
+            Instr::LdK(x) => format!("{} V{:X}, K", m, x),
+            Instr::LdDt(x) => format!("{} DT, V{:X}", m, x),
+            Instr::LdSt(x) => format!("{} ST, V{:X}", m, x),
+            Instr::AddI(x) => format!("{} I, V{:X}", m, x),
+            Instr::LdF(x) => format!("{} F, V{:X}", m, x),
+            Instr::LdB(x) => format!("{} B, V{:X}", m, x),
+            Instr::LdIV(x) => format!("{} [I], V{:X}", m, x),
+            Instr::LdVI(x) => format!("{} V{:X}, [I]", m, x),
+        }
+    }
 }
 
 impl fmt::Display for Instr {
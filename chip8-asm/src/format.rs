@@ -0,0 +1,360 @@
+// Source formatter for `chip8-asm fmt` and LSP document formatting.
+//
+// Works line by line over `lexer::tokenize` and never joins or splits lines,
+// so line N of the output is always line N of the input. Consecutive lines
+// at the same indentation start their operands in the same column, and
+// trailing comments line up within a paragraph (a run of non-blank lines).
+// Numbers keep their original spelling; lines the lexer can't read
+// (macro-local `%%` labels, `.for` expressions) keep their text and are only
+// re-indented.
+
+use crate::lexer::{span, tokenize, Token};
+use crate::parser::MNEMONICS;
+
+/// Spaces per indentation level.
+pub const INDENT: usize = 4;
+
+/// Keywords that start a line but take no aligned operands.
+const KEYWORDS: &[&str] = &["macro", "MACRO", "include", "struct"];
+
+/// Structured control flow keywords, matched in any case as the parser does.
+const FLOW_KEYWORDS: &[&str] = &["if", "while", "loop", "else", "break", "continue"];
+
+/// Formats a whole source file.
+pub fn format(source: &str) -> String {
+    let texts: Vec<&str> = source.lines().collect();
+    let mut by_line: Vec<Vec<(Token, usize)>> = vec![Vec::new(); texts.len()];
+    for (tok, line, col) in tokenize(source) {
+        if !matches!(tok, Token::Newline | Token::Eof) && line < texts.len() {
+            by_line[line].push((tok, col));
+        }
+    }
+    let macros = macro_names(&texts);
+
+    let mut cx = Layout {
+        blocks: Vec::new(),
+        pending: None,
+        macros: &macros,
+    };
+    let lines: Vec<Line> = texts
+        .iter()
+        .zip(&by_line)
+        .map(|(text, tokens)| cx.line(text, tokens))
+        .collect();
+
+    let mut out = Vec::with_capacity(lines.len());
+    for para in lines.split(|l| l.is_blank()) {
+        // Operands line up within each run of lines at the same indentation
+        let mut codes = Vec::with_capacity(para.len());
+        for run in para.chunk_by(|a, b| a.indent == b.indent) {
+            let head_width = run
+                .iter()
+                .filter(|l| !l.rest.is_empty())
+                .filter_map(|l| l.head.as_ref().map(String::len))
+                .max()
+                .unwrap_or(0);
+            codes.extend(run.iter().map(|l| l.code(head_width)));
+        }
+        let comment_col = para
+            .iter()
+            .zip(&codes)
+            .filter(|(l, code)| l.comment.is_some() && !code.is_empty())
+            .map(|(_, code)| code.len() + 2)
+            .max()
+            .unwrap_or(0);
+        for (l, code) in para.iter().zip(codes) {
+            out.push(match &l.comment {
+                Some(c) if code.is_empty() => format!("{}{}", " ".repeat(l.indent), c),
+                Some(c) => format!("{:width$}{}", code, c, width = comment_col),
+                None => code,
+            });
+        }
+        // `split` drops the blank separator; put it back unless this was the tail
+        if out.len() < lines.len() {
+            out.push(String::new());
+        }
+    }
+    out.truncate(lines.len());
+
+    let mut result = out.join("\n");
+    if source.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+/// One formatted line before paragraph alignment is applied.
+struct Line {
+    /// Column of the code, or of the comment on comment-only lines.
+    indent: usize,
+    label: Option<(usize, String)>,
+    /// Mnemonic, directive or macro name whose operands get aligned.
+    head: Option<String>,
+    /// Operands after `head`, or the whole statement when there is no head.
+    rest: String,
+    comment: Option<String>,
+}
+
+impl Line {
+    fn is_blank(&self) -> bool {
+        self.label.is_none() && self.head.is_none() && self.rest.is_empty() && self.comment.is_none()
+    }
+
+    fn code(&self, head_width: usize) -> String {
+        let body = match &self.head {
+            Some(h) if self.rest.is_empty() => h.clone(),
+            Some(h) => format!("{:width$} {}", h, self.rest, width = head_width),
+            None => self.rest.clone(),
+        };
+        match &self.label {
+            Some((col, label)) if body.is_empty() => format!("{}{}:", " ".repeat(*col), label),
+            Some((col, label)) => {
+                let prefix = format!("{}{}:", " ".repeat(*col), label);
+                format!("{:width$} {}", prefix, body, width = self.indent.saturating_sub(1))
+            }
+            None if body.is_empty() => String::new(),
+            None => format!("{}{}", " ".repeat(self.indent), body),
+        }
+    }
+}
+
+struct Block {
+    /// Column of the line that opened the block; its body is one level in.
+    opener: usize,
+    /// Macro parameters, left exactly as written.
+    params: Vec<String>,
+    is_struct: bool,
+}
+
+/// Tracks open blocks while walking the file.
+struct Layout<'a> {
+    blocks: Vec<Block>,
+    /// A `macro` or `struct` header whose `{` is on a later line.
+    pending: Option<Block>,
+    macros: &'a [String],
+}
+
+impl Layout<'_> {
+    fn code_indent(&self) -> usize {
+        self.blocks.last().map_or(INDENT, |b| b.opener + INDENT)
+    }
+
+    fn label_indent(&self) -> usize {
+        self.blocks.last().map_or(0, |b| b.opener)
+    }
+
+    fn is_param(&self, word: &str) -> bool {
+        self.blocks.iter().any(|b| b.params.iter().any(|p| p == word))
+    }
+
+    fn line(&mut self, text: &str, tokens: &[(Token, usize)]) -> Line {
+        let mut line = Line {
+            indent: self.code_indent(),
+            label: None,
+            head: None,
+            rest: String::new(),
+            comment: None,
+        };
+
+        let spans: Vec<(usize, usize)> = tokens.iter().map(|(t, c)| span(text, t, *c)).collect();
+        let code_end = spans.last().map_or(0, |s| s.1);
+        let tail = text.get(code_end..).unwrap_or("").trim();
+        if !tail.is_empty() {
+            line.comment = Some(tail.to_string());
+        }
+        if tokens.is_empty() {
+            if text.starts_with(|c: char| !c.is_whitespace()) {
+                line.indent = 0;
+            }
+            return line;
+        }
+
+        let raw = tokens.iter().any(|(t, _)| matches!(t, Token::Error(_)));
+        let mut body = tokens;
+        if let [(Token::Word(name), _), (Token::Colon, _), ..] = tokens {
+            line.label = Some((self.label_indent(), name.clone()));
+            body = &tokens[2..];
+        }
+
+        let closes_first = match body {
+            [(Token::RBrace, _), ..] => true,
+            [(Token::Dot, _), (Token::Word(w), _), ..] => matches!(w.to_lowercase().as_str(), "endr" | "endfor"),
+            _ => false,
+        };
+        if closes_first {
+            if let Some(b) = self.blocks.last() {
+                line.indent = b.opener;
+            }
+        }
+
+        let first = match body.first() {
+            Some((Token::Word(w), _)) => Some(w.as_str()),
+            _ => None,
+        };
+        let top_level = self.blocks.is_empty() && line.label.is_none();
+        let declaration = match body {
+            [(Token::Word(w), _), ..] => matches!(w.as_str(), "include" | "macro" | "MACRO" | "struct"),
+            [(Token::Dot, _), (Token::Word(w), _), ..] => matches!(w.to_lowercase().as_str(), "const" | "org"),
+            _ => false,
+        };
+        if top_level && declaration {
+            line.indent = 0;
+        }
+        if let (Some(pending), [(Token::LBrace, _), ..]) = (&self.pending, body) {
+            line.indent = pending.opener;
+        }
+
+        if raw {
+            // Keep everything from the first token on, comment included
+            let start = spans.first().map_or(0, |s| s.0);
+            let kept = text.get(start..).unwrap_or(text.trim()).trim_end();
+            line.comment = None;
+            match line.label.take() {
+                Some((col, _)) if kept.ends_with(':') && body.is_empty() => {
+                    line.indent = col;
+                    line.rest = kept.to_string();
+                }
+                _ => line.rest = kept.to_string(),
+            }
+            self.track_blocks(body, line.indent, first);
+            return line;
+        }
+
+        let in_struct = self.blocks.last().is_some_and(|b| b.is_struct);
+        let body_spans = &spans[spans.len() - body.len()..];
+        match body {
+            [] => {}
+            [(Token::Dot, _), (Token::Word(w), _), ..] => {
+                line.head = Some(format!(".{}", w.to_lowercase()));
+                line.rest = self.render(text, &body[2..], &body_spans[2..], false);
+            }
+            [(Token::Word(w), _), ..] if in_struct || !is_keyword(w) => {
+                let upper = w.to_uppercase();
+                let mnemonic = !in_struct
+                    && MNEMONICS.contains(&upper.as_str())
+                    && !self.macros.contains(w);
+                line.head = Some(if mnemonic { upper } else { w.clone() });
+                line.rest = self.render(text, &body[1..], &body_spans[1..], mnemonic);
+            }
+            _ => {
+                let conditional = first.is_some_and(|w| ["if", "while"].iter().any(|k| k.eq_ignore_ascii_case(w)));
+                line.rest = self.render(text, body, body_spans, conditional);
+            }
+        }
+
+        self.track_blocks(body, line.indent, first);
+        line
+    }
+
+    /// Updates the block stack for the braces and loop directives on a line
+    /// whose code starts at column `indent`.
+    fn track_blocks(&mut self, body: &[(Token, usize)], indent: usize, first: Option<&str>) {
+        let params = match (first, body) {
+            (Some("macro" | "MACRO"), [_, _, rest @ ..]) => rest
+                .iter()
+                .take_while(|(t, _)| *t != Token::LBrace)
+                .filter_map(|(t, _)| match t {
+                    Token::Word(w) => Some(w.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let is_struct = first == Some("struct");
+        let header = first.is_some_and(|w| matches!(w, "macro" | "MACRO" | "struct"));
+
+        let mut opened = false;
+        for (idx, (tok, _)) in body.iter().enumerate() {
+            match tok {
+                Token::LBrace => {
+                    let block = match self.pending.take() {
+                        Some(b) if idx == 0 => b,
+                        _ => Block {
+                            opener: indent,
+                            params: if header { params.clone() } else { Vec::new() },
+                            is_struct,
+                        },
+                    };
+                    self.blocks.push(block);
+                    opened = true;
+                }
+                Token::RBrace => {
+                    self.blocks.pop();
+                }
+                Token::Word(w) if idx == 1 && body[0].0 == Token::Dot => match w.to_lowercase().as_str() {
+                    "rept" | "for" => self.blocks.push(Block {
+                        opener: indent,
+                        params: Vec::new(),
+                        is_struct: false,
+                    }),
+                    "endr" | "endfor" => {
+                        self.blocks.pop();
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if header && !opened {
+            self.pending = Some(Block {
+                opener: indent,
+                params,
+                is_struct,
+            });
+        }
+    }
+
+    /// Joins tokens with normalized spacing. With `registers`, register names
+    /// are upper-cased (they are case-insensitive everywhere they appear).
+    fn render(&self, text: &str, tokens: &[(Token, usize)], spans: &[(usize, usize)], registers: bool) -> String {
+        let mut out = String::new();
+        let mut prev: Option<&Token> = None;
+        let mut in_bracket = false;
+        for ((tok, _), &(start, end)) in tokens.iter().zip(spans) {
+            let glued = prev.is_none_or(|p| matches!(p, Token::LBracket | Token::Dot))
+                || matches!(tok, Token::Comma | Token::Colon | Token::RBracket)
+                || (*tok == Token::Dot && matches!(prev, Some(Token::Word(w)) if is_register(&w.to_uppercase())));
+            if !glued {
+                out.push(' ');
+            }
+            let piece = match tok {
+                Token::Word(w) if registers && !self.is_param(w) => {
+                    let upper = w.to_uppercase();
+                    let reg = if in_bracket { upper == "I" } else { is_register(&upper) };
+                    if reg { upper } else { w.clone() }
+                }
+                _ => text.get(start..end).unwrap_or("").to_string(),
+            };
+            out.push_str(&piece);
+            match tok {
+                Token::LBracket => in_bracket = true,
+                Token::RBracket => in_bracket = false,
+                _ => {}
+            }
+            prev = Some(tok);
+        }
+        out
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS.contains(&word) || FLOW_KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word))
+}
+
+fn is_register(upper: &str) -> bool {
+    match upper {
+        "I" | "DT" | "ST" | "K" | "F" | "B" => true,
+        r => r.len() == 2 && r.starts_with('V') && r[1..].chars().all(|c| c.is_ascii_hexdigit()),
+    }
+}
+
+/// Names of macros defined in the file; invocations keep their case.
+fn macro_names(lines: &[&str]) -> Vec<String> {
+    lines
+        .iter()
+        .filter_map(|l| {
+            let rest = l.trim().strip_prefix("macro ").or_else(|| l.trim().strip_prefix("MACRO "))?;
+            rest.split(|c: char| c.is_whitespace() || c == '{').next().map(str::to_string)
+        })
+        .collect()
+}
@@ -0,0 +1,209 @@
+// Netpbm image decoding for the `.image` directive.
+//
+// Supports PBM (P1 ASCII, P4 binary) and PGM (P2 ASCII, P5 binary). In PBM a
+// `1` pixel is black and becomes a lit sprite pixel. In PGM only the two
+// extremes are accepted: `0` (black) is lit and `maxval` (white) is dark; any
+// grey in between is rejected so that anti-aliased exports don't silently
+// turn into noise.
+
+/// Most pixels an image may have: 4 KiB of memory at one bit per pixel.
+const MAX_PIXELS: usize = 4096 * 8;
+
+/// A decoded monochrome image, one `bool` per pixel (true = lit), row-major.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Slices the image into `w`×`h` sprites, left to right then top to
+    /// bottom, and returns their rows concatenated. Each sprite row is `w / 8`
+    /// bytes, most significant bit leftmost.
+    pub fn sprite_rows(&self, w: usize, h: usize) -> Result<Vec<u8>, String> {
+        if w == 0 || !w.is_multiple_of(8) {
+            return Err(format!("sprite width {} is not a multiple of 8", w));
+        }
+        if h == 0 {
+            return Err("sprite height must be at least 1".to_string());
+        }
+        if !self.width.is_multiple_of(w) || !self.height.is_multiple_of(h) {
+            return Err(format!(
+                "image size {}x{} is not a multiple of the sprite size {}x{}",
+                self.width, self.height, w, h
+            ));
+        }
+
+        let mut bytes = Vec::with_capacity(self.width * self.height / 8);
+        for tile_y in (0..self.height).step_by(h) {
+            for tile_x in (0..self.width).step_by(w) {
+                for y in tile_y..tile_y + h {
+                    for byte_x in (tile_x..tile_x + w).step_by(8) {
+                        let mut byte = 0u8;
+                        for bit in 0..8 {
+                            if self.pixel(byte_x + bit, y) {
+                                byte |= 0x80 >> bit;
+                            }
+                        }
+                        bytes.push(byte);
+                    }
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Decodes a PBM or PGM file. PNG and other formats are reported by name.
+pub fn decode(data: &[u8]) -> Result<Bitmap, String> {
+    if data.starts_with(b"\x89PNG") {
+        return Err("PNG images are not supported; convert to PBM or PGM".to_string());
+    }
+    let magic = match data.get(..2) {
+        Some(b"P1") => 1,
+        Some(b"P2") => 2,
+        Some(b"P4") => 4,
+        Some(b"P5") => 5,
+        _ => return Err("not a PBM or PGM image (expected P1, P2, P4 or P5)".to_string()),
+    };
+
+    let mut header = Header { data, pos: 2 };
+    let width = header.number("width")?;
+    let height = header.number("height")?;
+    let maxval = if magic == 2 || magic == 5 {
+        let max = header.number("maxval")?;
+        if max == 0 || max > 65535 {
+            return Err(format!("invalid PGM maxval {}", max));
+        }
+        max
+    } else {
+        1
+    };
+    if width == 0 || height == 0 {
+        return Err("image has no pixels".to_string());
+    }
+
+    // At one bit per pixel the whole image must still fit in the 4 KiB address
+    // space; checking before allocating keeps a bogus header from exhausting memory
+    let count = width
+        .checked_mul(height)
+        .filter(|&count| count <= MAX_PIXELS)
+        .ok_or_else(|| format!("{}x{} image is too large to fit in memory", width, height))?;
+    let pixels = match magic {
+        1 => {
+            let mut pixels = Vec::with_capacity(count);
+            while pixels.len() < count {
+                header.skip_space();
+                match header.data.get(header.pos) {
+                    Some(b'0') => pixels.push(false),
+                    Some(b'1') => pixels.push(true),
+                    Some(&c) => {
+                        return Err(format!(
+                            "non-binary pixel '{}' at ({}, {})",
+                            c as char,
+                            pixels.len() % width,
+                            pixels.len() / width
+                        ))
+                    }
+                    None => return Err("image data ends early".to_string()),
+                }
+                header.pos += 1;
+            }
+            pixels
+        }
+        4 => {
+            let start = header.pos + 1;
+            let stride = width.div_ceil(8);
+            let end = stride.checked_mul(height).and_then(|len| start.checked_add(len));
+            let raster = end
+                .and_then(|end| data.get(start..end))
+                .ok_or("image data ends early")?;
+            (0..count)
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    raster[y * stride + x / 8] & (0x80 >> (x % 8)) != 0
+                })
+                .collect()
+        }
+        _ => {
+            let values: Vec<usize> = if magic == 2 {
+                (0..count)
+                    .map(|_| header.number("pixel"))
+                    .collect::<Result<_, _>>()?
+            } else {
+                let start = header.pos + 1;
+                let depth = if maxval > 255 { 2 } else { 1 };
+                let end = count.checked_mul(depth).and_then(|len| start.checked_add(len));
+                let raster = end
+                    .and_then(|end| data.get(start..end))
+                    .ok_or("image data ends early")?;
+                raster
+                    .chunks(depth)
+                    .map(|c| c.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize))
+                    .collect()
+            };
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, &v)| match v {
+                    0 => Ok(true),
+                    v if v == maxval => Ok(false),
+                    v => Err(format!(
+                        "non-binary pixel value {} at ({}, {}); only 0 and {} are allowed",
+                        v,
+                        i % width,
+                        i / width,
+                        maxval
+                    )),
+                })
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    Ok(Bitmap {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// ── Header parsing ──────────────────────────────────────────────────────
+
+struct Header<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Header<'_> {
+    /// Skips whitespace and `#` comments.
+    fn skip_space(&mut self) {
+        while let Some(&c) = self.data.get(self.pos) {
+            if c == b'#' {
+                while self.data.get(self.pos).is_some_and(|&c| c != b'\n') {
+                    self.pos += 1;
+                }
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn number(&mut self, what: &str) -> Result<usize, String> {
+        self.skip_space();
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| format!("invalid or missing {} in image header", what))
+    }
+}
@@ -5,6 +5,11 @@ use crate::sourcemap::SourceMap;
 
 pub trait FileProvider {
     fn read_file(&self, path: &Path) -> Result<String, String>;
+
+    /// Raw file contents, used by `.incbin`.
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>, String> {
+        self.read_file(path).map(String::into_bytes)
+    }
 }
 
 pub struct FsFileProvider;
@@ -14,6 +19,10 @@ impl FileProvider for FsFileProvider {
         std::fs::read_to_string(path)
             .map_err(|e| format!("cannot read {}: {}", path.display(), e))
     }
+
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>, String> {
+        std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))
+    }
 }
 
 pub struct OverlayFileProvider<'a> {
@@ -28,6 +37,13 @@ impl<'a> FileProvider for OverlayFileProvider<'a> {
         }
         self.base.read_file(path)
     }
+
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>, String> {
+        if let Some(content) = self.overlay.get(path) {
+            return Ok(content.clone().into_bytes());
+        }
+        self.base.read_bytes(path)
+    }
 }
 
 #[derive(Debug)]
@@ -53,6 +69,12 @@ pub struct IncludeResolver {
     source_map: SourceMap,
 }
 
+impl Default for IncludeResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IncludeResolver {
     pub fn new() -> Self {
         IncludeResolver {
@@ -91,7 +113,7 @@ impl IncludeResolver {
                     .canonicalize()
                     .unwrap_or_else(|_| resolved_path.clone());
 
-                if stack.iter().any(|p| *p == canonical) {
+                if stack.contains(&canonical) {
                     let cycle: Vec<String> = stack
                         .iter()
                         .chain(std::iter::once(&canonical))
@@ -141,3 +163,136 @@ impl IncludeResolver {
         None
     }
 }
+
+// ── Binary inclusion ────────────────────────────────────────────────────
+
+/// Replaces every `.incbin "file" [, offset [, length]]` and
+/// `.image "file" [, w, h]` line with `.byte` lines holding the file contents
+/// (or the sprite rows sliced from the image). Paths are relative to the file
+/// the line came from, as recorded in `source_map`. Returns the new source and,
+/// for each output line, the input line it came from; errors carry the input line.
+pub fn expand_binary_includes(
+    source: &str,
+    source_map: &SourceMap,
+    base_dir: &Path,
+    provider: &dyn FileProvider,
+) -> Result<(String, Vec<usize>), (usize, String)> {
+    let mut output = String::new();
+    let mut origins = Vec::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let Some((label, directive, args)) = parse_binary_directive(line) else {
+            output.push_str(line);
+            output.push('\n');
+            origins.push(line_idx);
+            continue;
+        };
+
+        let (path, numbers) = parse_binary_args(directive, args).map_err(|e| (line_idx, e))?;
+        let dir = match source_map.resolve(line_idx).0 {
+            "<root>" | "<unknown>" => base_dir.to_path_buf(),
+            file => Path::new(file).parent().unwrap_or(base_dir).to_path_buf(),
+        };
+        let full_path = dir.join(&path);
+        let data = provider
+            .read_bytes(&full_path)
+            .map_err(|e| (line_idx, format!("{}: {}", full_path.display(), e)))?;
+
+        let bytes = match directive {
+            ".incbin" => slice_incbin(&data, &path, &numbers),
+            _ => crate::image::decode(&data)
+                .and_then(|img| {
+                    let w = numbers.first().copied().unwrap_or(img.width);
+                    let h = numbers.get(1).copied().unwrap_or(img.height);
+                    img.sprite_rows(w, h)
+                })
+                .map_err(|e| format!("{}: {}", path, e)),
+        }
+        .map_err(|e| (line_idx, e))?;
+
+        if let Some(label) = label {
+            output.push_str(label);
+            output.push('\n');
+            origins.push(line_idx);
+        }
+        for chunk in bytes.chunks(16) {
+            let bytes: Vec<String> = chunk.iter().map(|b| format!("0x{:02X}", b)).collect();
+            output.push_str(&format!("    .byte {}\n", bytes.join(", ")));
+            origins.push(line_idx);
+        }
+    }
+
+    Ok((output, origins))
+}
+
+fn slice_incbin(data: &[u8], path: &str, numbers: &[usize]) -> Result<Vec<u8>, String> {
+    let offset = numbers.first().copied().unwrap_or(0);
+    if offset > data.len() {
+        return Err(format!(
+            "offset {} is past the end of {} ({} bytes)",
+            offset,
+            path,
+            data.len()
+        ));
+    }
+    let length = numbers.get(1).copied().unwrap_or(data.len() - offset);
+    if offset + length > data.len() {
+        return Err(format!(
+            "{} bytes at offset {} exceed the size of {} ({} bytes)",
+            length,
+            offset,
+            path,
+            data.len()
+        ));
+    }
+    Ok(data[offset..offset + length].to_vec())
+}
+
+/// Splits `[label:] .incbin args` / `[label:] .image args` into the label,
+/// the lowercase directive and the argument text.
+fn parse_binary_directive(line: &str) -> Option<(Option<&str>, &'static str, &str)> {
+    let code = line.split(';').next().unwrap_or("").trim();
+    let (label, rest) = match code.split_once(':') {
+        Some((l, r)) if !l.contains('"') && !l.trim().contains(char::is_whitespace) => {
+            (Some(&code[..l.len() + 1]), r.trim())
+        }
+        _ => (None, code),
+    };
+    let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let directive = match rest[..word_end].to_lowercase().as_str() {
+        ".incbin" => ".incbin",
+        ".image" => ".image",
+        _ => return None,
+    };
+    Some((label, directive, rest[word_end..].trim()))
+}
+
+fn parse_binary_args(directive: &str, args: &str) -> Result<(String, Vec<usize>), String> {
+    let usage = || match directive {
+        ".incbin" => "expected '.incbin \"file\" [, offset [, length]]'".to_string(),
+        _ => "expected '.image \"file\" [, width, height]'".to_string(),
+    };
+    let rest = args.strip_prefix('"').ok_or_else(usage)?;
+    let end = rest.find('"').ok_or_else(usage)?;
+    let path = rest[..end].to_string();
+    if path.is_empty() {
+        return Err(usage());
+    }
+
+    let mut numbers = Vec::new();
+    let tail = rest[end + 1..].trim();
+    if !tail.is_empty() {
+        let tail = tail.strip_prefix(',').ok_or_else(usage)?;
+        for part in tail.split(',') {
+            match crate::macroexpand::eval_const(part.trim()) {
+                Some(v) if v >= 0 => numbers.push(v as usize),
+                _ => return Err(format!("invalid {} argument '{}'", directive, part.trim())),
+            }
+        }
+    }
+    if numbers.len() > 2 || (directive == ".image" && numbers.len() == 1) {
+        return Err(usage());
+    }
+
+    Ok((path, numbers))
+}
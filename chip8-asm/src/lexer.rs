@@ -11,6 +11,8 @@ pub enum Token {
     LBrace,
     RBrace,
     Equals,
+    EqEq,
+    NotEq,
     Newline,
     Eof,
     Error(String),
@@ -37,6 +39,38 @@ pub fn tokenize(source: &str) -> Vec<(Token, usize, usize)> {
     tokens
 }
 
+/// Updates the output of [`tokenize`] after an edit that replaced `old_count`
+/// lines starting at line `start` with `new_count` lines of `source` (the
+/// text after the edit). Only the new lines are lexed; tokens of the other
+/// lines are reused, with those after the edit moved to their new line.
+pub fn relex(
+    tokens: &[(Token, usize, usize)],
+    source: &str,
+    start: usize,
+    old_count: usize,
+    new_count: usize,
+) -> Vec<(Token, usize, usize)> {
+    let lines: Vec<&str> = source.lines().collect();
+    // An empty buffer still gets a Newline for line 0; just start over
+    if lines.is_empty() || tokens.len() <= 2 {
+        return tokenize(source);
+    }
+
+    let body = &tokens[..tokens.len() - 1];
+    let mut out: Vec<_> = body.iter().take_while(|t| t.1 < start).cloned().collect();
+    for (line_num, line) in lines.iter().enumerate().skip(start).take(new_count) {
+        out.extend(lex_line(line, line_num));
+        out.push((Token::Newline, line_num, line.len().max(1) - 1));
+    }
+    out.extend(
+        body.iter()
+            .filter(|t| t.1 >= start + old_count)
+            .map(|(tok, line, col)| (tok.clone(), line + new_count - old_count, *col)),
+    );
+    out.push((Token::Eof, lines.len() - 1, 0));
+    out
+}
+
 fn lex_line(line: &str, line_num: usize) -> Vec<(Token, usize, usize)> {
     let mut tokens = Vec::new();
     let chars: Vec<char> = line.chars().collect();
@@ -95,6 +129,27 @@ fn lex_line(line: &str, line_num: usize) -> Vec<(Token, usize, usize)> {
             continue;
         }
 
+        // Binary: 0b1010
+        if chars[i] == '0'
+            && i + 2 < chars.len()
+            && chars[i + 1] == 'b'
+            && matches!(chars[i + 2], '0' | '1')
+        {
+            let start = i + 2;
+            let mut end = start;
+            while end < chars.len() && matches!(chars[end], '0' | '1') {
+                end += 1;
+            }
+            match u16::from_str_radix(&line[start..end], 2) {
+                Ok(val) => tokens.push((Token::Number(val), line_num, start)),
+                Err(e) => {
+                    tokens.push((Token::Error(format!("invalid binary number: {}", e)), line_num, start));
+                }
+            }
+            i = end;
+            continue;
+        }
+
         // Decimal number
         if chars[i].is_ascii_digit() {
             let start = i;
@@ -144,6 +199,14 @@ fn lex_line(line: &str, line_num: usize) -> Vec<(Token, usize, usize)> {
             continue;
         }
 
+        // Comparison operators used by structured control flow
+        if i + 1 < chars.len() && chars[i + 1] == '=' && (chars[i] == '=' || chars[i] == '!') {
+            let tok = if chars[i] == '=' { Token::EqEq } else { Token::NotEq };
+            tokens.push((tok, line_num, i));
+            i += 2;
+            continue;
+        }
+
         // Single-char punctuation
         let col = i;
         match chars[i] {
@@ -169,6 +232,46 @@ fn lex_line(line: &str, line_num: usize) -> Vec<(Token, usize, usize)> {
     tokens
 }
 
+/// Byte range of a token in its line, including the prefixes (`#`, `$`,
+/// `0x`, `0b`) and quotes that the lexer strips from numbers and strings.
+pub fn span(line: &str, tok: &Token, col: usize) -> (usize, usize) {
+    let bytes = line.as_bytes();
+    match tok {
+        Token::Word(w) => (col, col + w.len()),
+        Token::String(s) => (col.saturating_sub(1), col + s.len() + 1),
+        Token::EqEq | Token::NotEq => (col, col + 2),
+        Token::Number(_) => {
+            let mut start = col;
+            if start >= 2 && matches!(line.get(start - 2..start), Some("0x" | "0b")) {
+                start -= 2;
+            } else if start >= 1 && bytes.get(start - 1) == Some(&b'$') {
+                start -= 1;
+            }
+            if start >= 1 && bytes.get(start - 1) == Some(&b'#') {
+                start -= 1;
+            }
+            let len = bytes
+                .get(col..)
+                .unwrap_or(&[])
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric())
+                .count();
+            (start, col + len)
+        }
+        _ => (col, col + 1),
+    }
+}
+
+/// End column of the word starting at `col` in `line`, dotted names
+/// included, or `col + 1` if there is none.
+pub fn word_end(line: &str, col: usize) -> usize {
+    let rest = line.get(col..).unwrap_or("");
+    let len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(rest.len());
+    col + len.max(1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -275,4 +378,36 @@ mod tests {
             .collect();
         assert_eq!(words, vec!["CLS", "RET"]);
     }
+
+    #[test]
+    fn test_comparison_operators() {
+        let t = tokenize("if V0 != V1 {\nwhile V2 == 3 {");
+        assert!(t.iter().any(|(tok, _, c)| *tok == Token::NotEq && *c == 6));
+        assert!(t.iter().any(|(tok, _, _)| *tok == Token::EqEq));
+        assert!(!t.iter().any(|(tok, _, _)| matches!(tok, Token::Equals | Token::Error(_))));
+    }
+
+    #[test]
+    fn test_binary_and_span() {
+        let line = "LD V0, 0b1010 ; x";
+        let t = tokenize(line);
+        assert_eq!(collect_numbers(&t), vec![10]);
+        let (tok, _, col) = &t[3];
+        assert_eq!(span(line, tok, *col), (7, 13));
+        let line = "LD V0, #$0F";
+        let (tok, _, col) = &tokenize(line)[3];
+        assert_eq!(&line[span(line, tok, *col).0..], "#$0F");
+    }
+
+    #[test]
+    fn test_relex_matches_tokenize() {
+        let before = "start:\n    LD V0, 1\n    JP start\n";
+        let tokens = tokenize(before);
+        // Replace line 1 with two lines
+        let after = "start:\n    LD V0, 2\n    ADD V0, 1\n    JP start\n";
+        assert_eq!(relex(&tokens, after, 1, 1, 2), tokenize(after));
+        // Join lines 0 and 1
+        let after = "start: LD V0, 1\n    JP start\n";
+        assert_eq!(relex(&tokens, after, 0, 2, 1), tokenize(after));
+    }
 }
@@ -9,12 +9,17 @@ use crate::symbol::SymbolTable;
 
 pub mod sourcemap;
 pub mod include;
+pub mod image;
 pub mod macroexpand;
 pub mod preprocess;
 pub mod lexer;
 pub mod parser;
 pub mod encoder;
 pub mod symbol;
+pub mod semantic;
+pub mod lint;
+pub mod format;
+pub mod debuginfo;
 
 pub use crate::include::{FileProvider, FsFileProvider, OverlayFileProvider};
 pub use crate::preprocess::{PreprocessError, PreprocessResult};
@@ -67,6 +72,7 @@ pub struct AssemblyOptions {
 pub struct AssembleResult {
     pub bytes: Vec<u8>,
     pub listing: Vec<String>,
+    pub warnings: Vec<AssemblyError>,
 }
 
 #[derive(Debug, Clone)]
@@ -76,6 +82,8 @@ pub struct AnalysisResult {
     pub source_map: sourcemap::SourceMap,
     pub tokens: Vec<(lexer::Token, usize, usize)>,
     pub statements: Vec<Statement>,
+    /// Line of `expanded_source` each statement was parsed from.
+    pub statement_lines: Vec<usize>,
     pub addresses: Vec<u16>,
     pub symbol_table: symbol::SymbolTable,
     pub macro_defs: Vec<macroexpand::MacroDef>,
@@ -85,13 +93,24 @@ pub fn analyze(source: &str) -> Result<AnalysisResult, Vec<AssemblyError>> {
     analyze_with(source, &AssemblyOptions::default())
 }
 
+/// True if `directive` appears as a whole word, so `.for` doesn't match `.format`.
+fn has_directive(source: &str, directive: &str) -> bool {
+    source.match_indices(directive).any(|(pos, _)| {
+        !source[pos + directive.len()..]
+            .starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
 pub fn analyze_with(
     source: &str,
     opts: &AssemblyOptions,
 ) -> Result<AnalysisResult, Vec<AssemblyError>> {
     let has_includes = source.contains("include \"") || source.contains("INCLUDE \"");
     let has_macros = source.contains("\nmacro ") || source.starts_with("macro ");
-    let pp = if has_includes || has_macros {
+    let lower = source.to_lowercase();
+    let has_loops = has_directive(&lower, ".rept") || has_directive(&lower, ".for");
+    let has_incbin = lower.contains(".incbin") || lower.contains(".image");
+    let pp = if has_includes || has_macros || has_loops || has_incbin {
         if opts.files.is_empty() {
             let fs = FsFileProvider;
             preprocess::preprocess(source, &opts.base_dir, &fs)
@@ -124,7 +143,7 @@ pub fn analyze_with(
             .collect());
     }
 
-    let statements = match parser::parse(&tokens) {
+    let (statements, statement_lines) = match parser::parse_with_lines(&tokens) {
         Ok(s) => s,
         Err(errs) => {
             return Err(
@@ -153,6 +172,7 @@ pub fn analyze_with(
         source_map: pp.source_map,
         tokens,
         statements,
+        statement_lines,
         addresses,
         symbol_table: sym,
         macro_defs,
@@ -168,12 +188,30 @@ pub fn assemble_with(
     opts: &AssemblyOptions,
 ) -> Result<AssembleResult, Vec<AssemblyError>> {
     let analysis = analyze_with(source, opts)?;
+    let (errors, warnings): (Vec<_>, Vec<_>) = semantic::check(&analysis)
+        .into_iter()
+        .partition(|d| d.severity == semantic::Severity::Error);
+    if !errors.is_empty() {
+        return Err(errors.into_iter().map(AssemblyError::from).collect());
+    }
+    let warnings = warnings.into_iter().map(AssemblyError::from).collect();
     let (output, listing) = generate_code(&analysis.statements, &analysis.addresses, &analysis.symbol_table)
         .map_err(|e| vec![AssemblyError::from_string(e)])?;
-    Ok(AssembleResult { bytes: output, listing })
+    Ok(AssembleResult { bytes: output, listing, warnings })
 }
 
 pub fn assemble_file(path: &Path) -> Result<AssembleResult, Vec<AssemblyError>> {
+    let (source, opts) = read_source(path)?;
+    assemble_with(&source, &opts)
+}
+
+/// Analyzes a file on disk, resolving includes relative to its directory.
+pub fn analyze_file(path: &Path) -> Result<AnalysisResult, Vec<AssemblyError>> {
+    let (source, opts) = read_source(path)?;
+    analyze_with(&source, &opts)
+}
+
+fn read_source(path: &Path) -> Result<(String, AssemblyOptions), Vec<AssemblyError>> {
     let source = std::fs::read_to_string(path).map_err(|e| {
         vec![AssemblyError {
             message: format!("{}: {}", path.display(), e),
@@ -183,17 +221,25 @@ pub fn assemble_file(path: &Path) -> Result<AssembleResult, Vec<AssemblyError>>
         }]
     })?;
     let base_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
-    assemble_with(
-        &source,
-        &AssemblyOptions {
+    Ok((
+        source,
+        AssemblyOptions {
             base_dir,
             ..Default::default()
         },
-    )
+    ))
 }
 
 fn pp_error_to_assembly(e: PreprocessError) -> AssemblyError {
-    AssemblyError::from_string(e.to_string())
+    match e {
+        PreprocessError::At { file, line, message } => AssemblyError {
+            message,
+            file: Some(file),
+            line,
+            col: 0,
+        },
+        e => AssemblyError::from_string(e.to_string()),
+    }
 }
 
 fn translate_error(msg: String, line: usize, col: usize, source_map: &SourceMap) -> AssemblyError {
@@ -241,6 +287,7 @@ fn extract_err_pos(e: &parser::ParseError) -> (usize, usize) {
         DuplicateLabel(_, l) => (*l, 0),
         MissingLabelName(l, c) => (*l, *c),
         BadConstSyntax(l, c) => (*l, *c),
+        BadBlock(_, l, c) => (*l, *c),
     }
 }
 
@@ -284,31 +331,8 @@ pub fn compute_layout(
                 sym.define_const(&format!("{}.SIZE", name), offset, 0)
                     .map_err(|_| format!("duplicate constant '{}'.SIZE", name))?;
             }
-            Statement::Inst(_) => {
-                addr = addr.wrapping_add(2);
-            }
-            Statement::Byte(v) => {
-                addr = addr.wrapping_add(v.len() as u16);
-            }
-            Statement::Word(v) => {
-                addr = addr.wrapping_add((v.len() * 2) as u16);
-            }
-            Statement::Ascii(s) => {
-                addr = addr.wrapping_add(s.len() as u16);
-            }
-            Statement::Asciz(s) => {
-                addr = addr.wrapping_add(s.len() as u16 + 1);
-            }
-            Statement::Align(n) => {
-                if *n > 1 {
-                    let mask = (*n as u16) - 1;
-                    if addr & mask != 0 {
-                        addr = (addr + mask) & !mask;
-                    }
-                }
-            }
-            Statement::Space(n) => {
-                addr = addr.wrapping_add(*n);
+            _ => {
+                addr = addr.wrapping_add(statement_size(stmt, addr));
             }
         }
     }
@@ -320,6 +344,28 @@ pub fn compute_layout(
     Ok((sym, addresses))
 }
 
+/// Number of bytes `stmt` emits when placed at `addr`.
+pub fn statement_size(stmt: &Statement, addr: u16) -> u16 {
+    match stmt {
+        Statement::Org(_) | Statement::Label(_) | Statement::Const(..) | Statement::Struct { .. } => 0,
+        Statement::Inst(_) => 2,
+        Statement::Pseudo { insts, .. } => insts.len() as u16 * 2,
+        Statement::Byte(v) => v.len() as u16,
+        Statement::Word(v) => (v.len() * 2) as u16,
+        Statement::Ascii(s) => s.len() as u16,
+        Statement::Asciz(s) => s.len() as u16 + 1,
+        Statement::Align(n) => {
+            let mask = (*n as u16).max(1) - 1;
+            if *n > 1 && addr & mask != 0 {
+                ((addr + mask) & !mask) - addr
+            } else {
+                0
+            }
+        }
+        Statement::Space(n) => *n,
+    }
+}
+
 // ── generate_code (was pass2) ───────────────────────────────────────────
 
 pub fn generate_code(
@@ -345,11 +391,27 @@ pub fn generate_code(
                     addr,
                     bytes[0],
                     bytes[1],
-                    instr.mnemonic()
+                    instr.disassemble()
                 );
                 listing.push(line);
                 output.extend_from_slice(&bytes);
             }
+            Statement::Pseudo { text, insts } => {
+                listing.push(format!("  {:04X}            {}", addr, text));
+                for (k, inst) in insts.iter().enumerate() {
+                    let instr = resolve_inst(inst, sym)
+                        .map_err(|name| format!("undefined symbol '{}'", name))?;
+                    let bytes = instr.encode();
+                    listing.push(format!(
+                        "  {:04X}  {:02X}{:02X}      {}",
+                        addr + k as u16 * 2,
+                        bytes[0],
+                        bytes[1],
+                        instr.disassemble()
+                    ));
+                    output.extend_from_slice(&bytes);
+                }
+            }
             Statement::Byte(v) => {
                 let mut resolved = Vec::new();
                 for imm in v {
@@ -406,15 +468,13 @@ pub fn generate_code(
                 let mask = (*n as u16) - 1;
                 if *n > 1 && addr & mask != 0 {
                     let pad_count = (mask + 1) - (addr & mask);
-                    let mut addr2 = addr;
-                    for _ in 0..pad_count {
+                    for addr2 in addr..addr + pad_count {
                         let line = format!(
                             "  {:04X}  00                     .align {}",
                             addr2, n
                         );
                         listing.push(line);
                         output.push(0);
-                        addr2 += 1;
                     }
                 }
             }
@@ -429,10 +489,24 @@ pub fn generate_code(
     Ok((output, listing))
 }
 
+/// Encodes one instruction, resolving its operands against `sym`.
+pub fn encode_inst(inst: &Inst, sym: &SymbolTable) -> Result<[u8; 2], String> {
+    resolve_inst(inst, sym)
+        .map(|instr| instr.encode())
+        .map_err(|name| format!("undefined symbol '{}'", name))
+}
+
 fn resolve_imm(imm: &Imm, sym: &SymbolTable, max_val: u16) -> Result<u16, String> {
     let val = match imm {
         Imm::Val(n) => *n,
         Imm::Label(name) => sym.resolve(name).ok_or_else(|| name.clone())?,
+        Imm::Neg(name) => {
+            let v = sym.resolve(name).ok_or_else(|| name.clone())?;
+            if v > 0xFF {
+                return Err(format!("value {} exceeds max {}", v, 0xFF));
+            }
+            (0x100 - v) & 0xFF
+        }
     };
     if val > max_val {
         return Err(format!("value {} exceeds max {}", val, max_val));
@@ -0,0 +1,482 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{Addr, Imm, Inst, Statement};
+use crate::semantic::{diagnostic, Diagnostic, Severity, Target};
+use crate::AnalysisResult;
+
+const STACK_SIZE: usize = 16;
+
+/// Looks for likely bugs in an analyzed program. Findings are warnings with
+/// a lint ID in [`Diagnostic::code`]. A finding is dropped when its line, or
+/// the comment line right above it, carries `; lint:allow` (every lint) or
+/// `; lint:allow(id, ...)`.
+pub fn lint(analysis: &AnalysisResult) -> Vec<Diagnostic> {
+    let program = Program::new(analysis);
+    let mut findings = Vec::new();
+
+    program.unreachable(&mut findings);
+    program.ret_outside_routine(&mut findings);
+    program.skip_multiword(&mut findings);
+    program.unused_symbols(&mut findings);
+    program.vf_clobber(&mut findings);
+    program.i_unset(&mut findings);
+    program.stack_depth(&mut findings);
+
+    findings
+        .into_iter()
+        .filter(|f| !allowed(analysis, f.line, f.id))
+        .map(|f| {
+            let mut d = diagnostic(analysis, Severity::Warning, f.message, f.line, f.target);
+            d.code = Some(f.id);
+            d
+        })
+        .collect()
+}
+
+struct Finding<'a> {
+    id: &'static str,
+    line: usize,
+    message: String,
+    target: Option<Target<'a>>,
+}
+
+// ── Instruction graph ───────────────────────────────────────────────────
+
+/// One machine instruction; pseudo-instructions contribute one node each.
+struct Node<'a> {
+    stmt: usize,
+    inst: &'a Inst,
+}
+
+struct Program<'a> {
+    analysis: &'a AnalysisResult,
+    nodes: Vec<Node<'a>>,
+    /// Labels placed right before node `i` (one extra slot for the end).
+    labels_at: Vec<Vec<&'a str>>,
+    label_node: HashMap<&'a str, usize>,
+    /// Data, `.org` or padding between node `i - 1` and node `i`.
+    barrier: Vec<bool>,
+    call_refs: HashSet<&'a str>,
+    jump_refs: HashSet<&'a str>,
+    other_refs: HashSet<&'a str>,
+}
+
+impl<'a> Program<'a> {
+    fn new(analysis: &'a AnalysisResult) -> Self {
+        let mut p = Program {
+            analysis,
+            nodes: Vec::new(),
+            labels_at: vec![Vec::new()],
+            label_node: HashMap::new(),
+            barrier: vec![false],
+            call_refs: HashSet::new(),
+            jump_refs: HashSet::new(),
+            other_refs: HashSet::new(),
+        };
+
+        for (idx, stmt) in analysis.statements.iter().enumerate() {
+            match stmt {
+                Statement::Inst(inst) => p.push(idx, inst),
+                Statement::Pseudo { insts, .. } => {
+                    for inst in insts {
+                        p.push(idx, inst);
+                    }
+                }
+                Statement::Label(name) => {
+                    p.label_node.insert(name, p.nodes.len());
+                    p.labels_at.last_mut().unwrap().push(name);
+                }
+                Statement::Byte(v) | Statement::Word(v) => {
+                    *p.barrier.last_mut().unwrap() = true;
+                    for imm in v {
+                        if let Imm::Label(n) | Imm::Neg(n) = imm {
+                            p.other_refs.insert(n);
+                        }
+                    }
+                }
+                Statement::Const(..) | Statement::Struct { .. } => {}
+                Statement::Align(_)
+                | Statement::Org(_)
+                | Statement::Ascii(_)
+                | Statement::Asciz(_)
+                | Statement::Space(_) => *p.barrier.last_mut().unwrap() = true,
+            }
+        }
+        p
+    }
+
+    fn push(&mut self, stmt: usize, inst: &'a Inst) {
+        match inst {
+            Inst::Call(Addr::Label(n)) => {
+                self.call_refs.insert(n);
+            }
+            Inst::Jp(Addr::Label(n)) => {
+                self.jump_refs.insert(n);
+            }
+            Inst::LdI(Addr::Label(n)) | Inst::JpV0(Addr::Label(n)) => {
+                self.other_refs.insert(n);
+            }
+            Inst::SeVb(_, imm)
+            | Inst::SneVb(_, imm)
+            | Inst::LdVb(_, imm)
+            | Inst::AddVb(_, imm)
+            | Inst::Rnd(_, imm)
+            | Inst::Drw(_, _, imm) => {
+                if let Imm::Label(n) | Imm::Neg(n) = imm {
+                    self.other_refs.insert(n);
+                }
+            }
+            _ => {}
+        }
+        self.nodes.push(Node { stmt, inst });
+        self.labels_at.push(Vec::new());
+        self.barrier.push(false);
+    }
+
+    fn line(&self, node: usize) -> usize {
+        self.analysis.statement_lines.get(self.nodes[node].stmt).copied().unwrap_or(0)
+    }
+
+    /// A JP/RET that isn't guarded by a skip right before it.
+    fn ends_flow(&self, k: usize) -> bool {
+        matches!(self.nodes[k].inst, Inst::Jp(_) | Inst::JpV0(_) | Inst::Ret)
+            && !(k > 0 && is_skip(self.nodes[k - 1].inst) && !self.barrier[k])
+    }
+
+    fn falls_through(&self, k: usize) -> bool {
+        k + 1 < self.nodes.len() && !self.barrier[k + 1] && !self.ends_flow(k)
+    }
+
+    /// Nodes control can move to from `k`, not following CALL into the callee.
+    fn successors(&self, k: usize) -> Vec<usize> {
+        let mut next = Vec::new();
+        match self.nodes[k].inst {
+            Inst::Jp(Addr::Label(l)) => next.extend(self.label_node.get(l.as_str())),
+            inst if is_skip(inst) && self.falls_through(k) => {
+                next.push(k + 1);
+                if k + 2 < self.nodes.len() && !self.barrier[k + 2] {
+                    next.push(k + 2);
+                }
+            }
+            _ if self.falls_through(k) => next.push(k + 1),
+            _ => {}
+        }
+        next.retain(|&n| n < self.nodes.len());
+        next
+    }
+
+    /// Nodes control can reach `k` from, the inverse of [`Self::successors`].
+    fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut preds = vec![Vec::new(); self.nodes.len()];
+        for k in 0..self.nodes.len() {
+            for next in self.successors(k) {
+                preds[next].push(k);
+            }
+        }
+        preds
+    }
+
+    /// Walks the control flow backwards from node `k`. A path ends when
+    /// `found` matches an earlier node or it reaches a label `stop_at`
+    /// accepts. Returns false if some path reaches the start of the program
+    /// without either.
+    fn walk_back(
+        &self,
+        preds: &[Vec<usize>],
+        k: usize,
+        found: impl Fn(&Inst) -> bool,
+        stop_at: impl Fn(&str) -> bool,
+    ) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = vec![k];
+        while let Some(idx) = stack.pop() {
+            if !seen.insert(idx) {
+                continue;
+            }
+            if idx != k && found(self.nodes[idx].inst) {
+                continue;
+            }
+            if self.labels_at[idx].iter().any(|l| stop_at(l)) {
+                continue;
+            }
+            if idx == 0 {
+                return false;
+            }
+            stack.extend(&preds[idx]);
+        }
+        true
+    }
+
+    // ── Lints ───────────────────────────────────────────────────────────
+
+    fn unreachable(&self, out: &mut Vec<Finding>) {
+        for k in 0..self.nodes.len().saturating_sub(1) {
+            let next = k + 1;
+            let dead = self.ends_flow(k)
+                && !self.barrier[next]
+                && self.labels_at[next].is_empty()
+                && self.nodes[next].stmt != self.nodes[k].stmt
+                && !is_generated_jump(self.nodes[next].inst);
+            if dead {
+                let after = if matches!(self.nodes[k].inst, Inst::Ret) { "RET" } else { "JP" };
+                out.push(Finding {
+                    id: "unreachable",
+                    line: self.line(next),
+                    message: format!("unreachable code after {}", after),
+                    target: None,
+                });
+            }
+        }
+    }
+
+    fn ret_outside_routine(&self, out: &mut Vec<Finding>) {
+        let preds = self.predecessors();
+        for k in 0..self.nodes.len() {
+            if !matches!(self.nodes[k].inst, Inst::Ret) {
+                continue;
+            }
+            // Labels only reached by JP are part of whatever jumped there, so
+            // keep walking; CALL targets and labels used as data stop it.
+            let entered = self.walk_back(&preds, k, |_| false, |l| {
+                self.call_refs.contains(l) || self.other_refs.contains(l)
+            });
+            if !entered {
+                out.push(Finding {
+                    id: "ret-outside-routine",
+                    line: self.line(k),
+                    message: "RET outside of any routine entered through CALL".into(),
+                    target: None,
+                });
+            }
+        }
+    }
+
+    fn skip_multiword(&self, out: &mut Vec<Finding>) {
+        for k in 0..self.nodes.len().saturating_sub(1) {
+            let next = &self.nodes[k + 1];
+            if !is_skip(self.nodes[k].inst) || next.stmt == self.nodes[k].stmt || self.barrier[k + 1] {
+                continue;
+            }
+            if let Statement::Pseudo { text, insts } = &self.analysis.statements[next.stmt] {
+                if insts.len() > 1 {
+                    out.push(Finding {
+                        id: "skip-multiword",
+                        line: self.line(k + 1),
+                        message: format!(
+                            "the skip before '{}' only skips the first of its {} instructions",
+                            text,
+                            insts.len()
+                        ),
+                        target: None,
+                    });
+                }
+            }
+        }
+    }
+
+    fn unused_symbols(&self, out: &mut Vec<Finding<'a>>) {
+        let used = |n: &str| {
+            self.call_refs.contains(n) || self.jump_refs.contains(n) || self.other_refs.contains(n)
+        };
+        for (idx, stmt) in self.analysis.statements.iter().enumerate() {
+            let (id, kind, name) = match stmt {
+                Statement::Label(n) => ("unused-label", "label", n),
+                Statement::Const(n, _) => ("unused-const", "constant", n),
+                _ => continue,
+            };
+            // Generated (`__if0_end`) and macro/loop-local (`__m1_x`) labels,
+            // and the entry point label, are fine without references
+            let entry = !self.barrier[0] && self.labels_at[0].contains(&name.as_str());
+            if name.starts_with("__") || entry || used(name) {
+                continue;
+            }
+            out.push(Finding {
+                id,
+                line: self.analysis.statement_lines.get(idx).copied().unwrap_or(0),
+                message: format!("unused {} '{}'", kind, name),
+                target: Some(Target::Symbol(name)),
+            });
+        }
+    }
+
+    fn vf_clobber(&self, out: &mut Vec<Finding>) {
+        for k in 0..self.nodes.len().saturating_sub(1) {
+            if !writes_vf(self.nodes[k].inst) || !self.falls_through(k) || !self.labels_at[k + 1].is_empty() {
+                continue;
+            }
+            if let Some(mnemonic) = sets_flag_without_reading_vf(self.nodes[k + 1].inst) {
+                out.push(Finding {
+                    id: "vf-clobber",
+                    line: self.line(k),
+                    message: format!("VF written here is overwritten by the flag result of the next {}", mnemonic),
+                    target: None,
+                });
+            }
+        }
+    }
+
+    fn i_unset(&self, out: &mut Vec<Finding>) {
+        let preds = self.predecessors();
+        for k in 0..self.nodes.len() {
+            let what = match self.nodes[k].inst {
+                Inst::LdIV(_) => "LD [I], Vx",
+                Inst::LdVI(_) => "LD Vx, [I]",
+                Inst::LdB(_) => "LD B, Vx",
+                _ => continue,
+            };
+            let sets_i = |i: &Inst| matches!(i, Inst::LdI(_) | Inst::AddI(_) | Inst::LdF(_) | Inst::LdIV(_) | Inst::LdVI(_));
+            // A routine or data-referenced label may be entered with I set;
+            // JP targets are followed back like in `ret_outside_routine`
+            let entry = |l: &str| self.call_refs.contains(l) || self.other_refs.contains(l);
+            if !self.walk_back(&preds, k, sets_i, entry) {
+                out.push(Finding {
+                    id: "i-unset",
+                    line: self.line(k),
+                    message: format!("{} without a preceding LD I", what),
+                    target: None,
+                });
+            }
+        }
+    }
+
+    fn stack_depth(&self, out: &mut Vec<Finding>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut memo: HashMap<usize, (usize, Option<usize>)> = HashMap::new();
+        let mut active = Vec::new();
+        let (depth, first_call) = self.depth(0, &mut memo, &mut active, out);
+        if depth > STACK_SIZE {
+            let k = first_call.unwrap_or(0);
+            out.push(Finding {
+                id: "stack-depth",
+                line: self.line(k),
+                message: format!(
+                    "call chain starting here nests {} routines deep; the stack holds {}",
+                    depth, STACK_SIZE
+                ),
+                target: None,
+            });
+        }
+    }
+
+    /// Deepest nesting of CALLs reachable from `entry`, and the CALL node
+    /// starting that chain. Recursive calls are reported and not followed.
+    fn depth(
+        &self,
+        entry: usize,
+        memo: &mut HashMap<usize, (usize, Option<usize>)>,
+        active: &mut Vec<usize>,
+        out: &mut Vec<Finding>,
+    ) -> (usize, Option<usize>) {
+        if let Some(&r) = memo.get(&entry) {
+            return r;
+        }
+        active.push(entry);
+
+        let mut best = (0, None);
+        let mut seen = HashSet::new();
+        let mut stack = vec![entry];
+        while let Some(k) = stack.pop() {
+            if !seen.insert(k) {
+                continue;
+            }
+            if let Inst::Call(Addr::Label(l)) = self.nodes[k].inst {
+                if let Some(&callee) = self.label_node.get(l.as_str()) {
+                    if callee < self.nodes.len() {
+                        if active.contains(&callee) {
+                            out.push(Finding {
+                                id: "stack-depth",
+                                line: self.line(k),
+                                message: format!("recursive CALL {} can overflow the {}-entry stack", l, STACK_SIZE),
+                                target: None,
+                            });
+                        } else {
+                            let d = self.depth(callee, memo, active, out).0 + 1;
+                            if d > best.0 {
+                                best = (d, Some(k));
+                            }
+                        }
+                    }
+                }
+            }
+            stack.extend(self.successors(k));
+        }
+
+        active.pop();
+        memo.insert(entry, best);
+        best
+    }
+}
+
+fn is_skip(inst: &Inst) -> bool {
+    matches!(
+        inst,
+        Inst::SeVb(..) | Inst::SneVb(..) | Inst::SeVV(..) | Inst::SneVV(..) | Inst::Skp(_) | Inst::Sknp(_)
+    )
+}
+
+/// Jumps emitted by `if`/`while`/`loop` lowering.
+fn is_generated_jump(inst: &Inst) -> bool {
+    matches!(inst, Inst::Jp(Addr::Label(l)) if l.starts_with("__"))
+}
+
+fn writes_vf(inst: &Inst) -> bool {
+    const VF: u8 = 0xF;
+    match inst {
+        Inst::LdVb(x, _)
+        | Inst::AddVb(x, _)
+        | Inst::LdVV(x, _)
+        | Inst::Or(x, _)
+        | Inst::And(x, _)
+        | Inst::Xor(x, _)
+        | Inst::Rnd(x, _)
+        | Inst::LdVdt(x)
+        | Inst::LdK(x)
+        | Inst::LdVI(x) => *x == VF,
+        _ => false,
+    }
+}
+
+/// Mnemonic of an instruction that sets VF as a flag without using its value.
+fn sets_flag_without_reading_vf(inst: &Inst) -> Option<&'static str> {
+    const VF: u8 = 0xF;
+    match inst {
+        Inst::AddVV(x, y) if *x != VF && *y != VF => Some("ADD"),
+        Inst::Sub(x, y) if *x != VF && *y != VF => Some("SUB"),
+        Inst::Subn(x, y) if *x != VF && *y != VF => Some("SUBN"),
+        Inst::Shr(x) if *x != VF => Some("SHR"),
+        Inst::Shl(x) if *x != VF => Some("SHL"),
+        Inst::Drw(x, y, _) if *x != VF && *y != VF => Some("DRW"),
+        _ => None,
+    }
+}
+
+/// Whether `line` of the expanded source, or a comment-only line right
+/// above it, silences lint `id`.
+fn allowed(analysis: &AnalysisResult, line: usize, id: &str) -> bool {
+    let lines: Vec<&str> = analysis.expanded_source.lines().collect();
+    let own = lines.get(line).copied().unwrap_or("");
+    let above = line
+        .checked_sub(1)
+        .and_then(|l| lines.get(l))
+        .filter(|t| t.trim_start().starts_with(';'))
+        .copied()
+        .unwrap_or("");
+    [own, above].iter().any(|text| allows(text, id))
+}
+
+fn allows(text: &str, id: &str) -> bool {
+    let Some((_, comment)) = text.split_once(';') else { return false };
+    let Some(pos) = comment.find("lint:allow") else { return false };
+    let rest = comment[pos + "lint:allow".len()..].trim_start();
+    match rest.strip_prefix('(') {
+        Some(list) => list
+            .split(')')
+            .next()
+            .unwrap_or("")
+            .split(',')
+            .any(|item| item.trim() == id),
+        None => true,
+    }
+}
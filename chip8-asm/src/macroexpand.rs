@@ -18,6 +18,31 @@ pub enum MacroError {
     },
     UnclosedMacro(String),
     EmptyMacroName,
+    UnclosedLoop {
+        directive: String,
+        line: usize,
+    },
+    UnmatchedLoopEnd {
+        directive: String,
+        line: usize,
+    },
+    BadLoopHeader {
+        message: String,
+        line: usize,
+    },
+}
+
+impl MacroError {
+    /// Input line the error refers to, when it has one.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            MacroError::WrongArgCount { line, .. }
+            | MacroError::UnclosedLoop { line, .. }
+            | MacroError::UnmatchedLoopEnd { line, .. }
+            | MacroError::BadLoopHeader { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for MacroError {
@@ -41,6 +66,13 @@ impl std::fmt::Display for MacroError {
             MacroError::EmptyMacroName => {
                 write!(f, "empty macro name")
             }
+            MacroError::UnclosedLoop { directive, .. } => {
+                write!(f, "unclosed '{}' block", directive)
+            }
+            MacroError::UnmatchedLoopEnd { directive, .. } => {
+                write!(f, "'{}' without matching loop", directive)
+            }
+            MacroError::BadLoopHeader { message, .. } => write!(f, "{}", message),
         }
     }
 }
@@ -60,6 +92,7 @@ pub fn collect_definitions(source: &str) -> Result<(String, Vec<MacroDef>), Macr
             let (name, params, body_head) = parse_header(rest)?;
 
             let mut body = Vec::new();
+            let start = i;
 
             if let Some(head) = body_head {
                 // { was on this line, head is whatever came after {
@@ -74,7 +107,6 @@ pub fn collect_definitions(source: &str) -> Result<(String, Vec<MacroDef>), Macr
                 // No { on header line; look for it on next non-empty line
                 i += 1;
                 while i < lines.len() && lines[i].trim().is_empty() {
-                    remaining.push(String::new());
                     i += 1;
                 }
                 if i >= lines.len() {
@@ -93,6 +125,8 @@ pub fn collect_definitions(source: &str) -> Result<(String, Vec<MacroDef>), Macr
                 }
             }
 
+            // Blank out the definition so line numbers keep matching the source map
+            remaining.extend((start..i).map(|_| String::new()));
             definitions.push(MacroDef { name, params, body });
         } else {
             remaining.push(lines[i].to_string());
@@ -137,8 +171,8 @@ fn parse_header(
 
 fn collect_single_line_body(text: &str, body: &mut Vec<String>) -> bool {
     let trimmed = text.trim();
-    if trimmed.ends_with('}') {
-        let inner = trimmed[..trimmed.len() - 1].trim();
+    if let Some(inner) = trimmed.strip_suffix('}') {
+        let inner = inner.trim();
         if !inner.is_empty() {
             body.push(inner.to_string());
         }
@@ -161,17 +195,28 @@ fn read_body_lines(
     if !head.is_empty() {
         body.push(head.to_string());
     }
+    // Braces of `if`/`while`/`loop` blocks inside the body nest.
+    let mut depth = 1usize;
     loop {
         if *i >= lines.len() {
             return Err(MacroError::UnclosedMacro("missing '}'".into()));
         }
         let bl = lines[*i].trim();
+        let code = split_comment(bl).0;
+        let opens = code.matches('{').count();
+        let closes = code.matches('}').count();
+        if depth + opens > closes {
+            depth = depth + opens - closes;
+            body.push(lines[*i].to_string());
+            *i += 1;
+            continue;
+        }
         if bl == "}" {
             *i += 1;
             break;
         }
-        if bl.ends_with('}') {
-            let inner = bl[..bl.len() - 1].trim();
+        if let Some(inner) = bl.strip_suffix('}') {
+            let inner = inner.trim();
             if !inner.is_empty() {
                 body.push(inner.to_string());
             }
@@ -187,30 +232,42 @@ fn read_body_lines(
 // ── Phase 2: Expand macro invocations ───────────────────────────────────
 
 pub fn expand(source: &str, macros: &[MacroDef]) -> Result<String, MacroError> {
+    expand_mapped(source, macros).map(|(output, _)| output)
+}
+
+/// Like [`expand`], but also returns, for every output line, the index of the
+/// input line it was expanded from (see [`SourceMap::remap`](crate::sourcemap::SourceMap::remap)).
+pub fn expand_mapped(
+    source: &str,
+    macros: &[MacroDef],
+) -> Result<(String, Vec<usize>), MacroError> {
     let macro_map: HashMap<String, &MacroDef> =
         macros.iter().map(|m| (m.name.clone(), m)).collect();
 
     if macro_map.is_empty() {
-        return Ok(source.to_string());
+        let origins = (0..source.lines().count()).collect();
+        return Ok((source.to_string(), origins));
     }
 
     let lines: Vec<&str> = source.lines().collect();
     let mut output = String::new();
+    let mut origins = Vec::new();
     let mut counter: u64 = 0;
 
-    for line_idx in 0..lines.len() {
+    for (line_idx, line) in lines.iter().enumerate() {
         let expanded = expand_one_line(
-            lines[line_idx],
+            line,
             &macro_map,
             &mut counter,
             line_idx,
             &mut Vec::new(),
         )?;
+        origins.extend(expanded.split('\n').map(|_| line_idx));
         output.push_str(&expanded);
         output.push('\n');
     }
 
-    Ok(output)
+    Ok((output, origins))
 }
 
 fn expand_one_line(
@@ -267,7 +324,7 @@ fn expand_one_line(
 
     let mut expanded_lines = Vec::new();
     for body_line in &mac.body {
-        let subbed = substitute_line(body_line, &subst, invocation_id);
+        let subbed = substitute_line(body_line, &subst, &format!("__m{}_", invocation_id));
         let inner =
             expand_one_line(&subbed, macro_map, counter, line_idx, expansion_stack)?;
         for inner_line in inner.lines() {
@@ -328,7 +385,7 @@ fn parse_invocation_args(rest: &str) -> Vec<String> {
     args
 }
 
-fn substitute_line(line: &str, subst: &HashMap<String, String>, invocation_id: u64) -> String {
+fn substitute_line(line: &str, subst: &HashMap<String, String>, local_prefix: &str) -> String {
     let mut result = String::new();
     let chars: Vec<char> = line.chars().collect();
     let mut i = 0;
@@ -343,7 +400,8 @@ fn substitute_line(line: &str, subst: &HashMap<String, String>, invocation_id: u
                 end += 1;
             }
             let label: String = chars[start..end].iter().collect();
-            result.push_str(&format!("__m{}_{}", invocation_id, label));
+            result.push_str(local_prefix);
+            result.push_str(&label);
             i = end;
         } else if chars[i].is_ascii_alphabetic() || chars[i] == '_' {
             let start = i;
@@ -366,3 +424,379 @@ fn substitute_line(line: &str, subst: &HashMap<String, String>, invocation_id: u
 
     result
 }
+
+// ── Phase 3: Repetition and loop directives ─────────────────────────────
+
+/// Upper bound on the number of lines a single source may unroll into.
+const MAX_UNROLLED_LINES: usize = 1 << 16;
+
+enum LoopHeader {
+    Rept(u16),
+    For { var: String, from: u16, to: u16 },
+}
+
+/// Unrolls `.rept N … .endr` and `.for i = a to b … .endfor` blocks.
+///
+/// Inside a `.for` body every occurrence of the loop variable is replaced by
+/// its value, after which constant expressions such as `i * 8 + 1` are folded.
+/// `%%name` labels are made unique per iteration of the innermost loop.
+/// Returns the unrolled source and, for every output line, the input line it
+/// came from.
+pub fn expand_loops(source: &str) -> Result<(String, Vec<usize>), MacroError> {
+    let lines: Vec<(String, usize)> = source
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (l.to_string(), i))
+        .collect();
+
+    if !lines.iter().any(|(l, _)| loop_keyword(l).is_some()) {
+        let origins = (0..lines.len()).collect();
+        return Ok((source.to_string(), origins));
+    }
+
+    let mut out = Vec::new();
+    let mut counter: u64 = 0;
+    unroll(&lines, &mut out, &mut counter)?;
+
+    let origins = out.iter().map(|(_, o)| *o).collect();
+    let mut output = String::new();
+    for (line, _) in out {
+        output.push_str(&line);
+        output.push('\n');
+    }
+    Ok((output, origins))
+}
+
+fn unroll(
+    lines: &[(String, usize)],
+    out: &mut Vec<(String, usize)>,
+    counter: &mut u64,
+) -> Result<(), MacroError> {
+    let mut i = 0;
+    while i < lines.len() {
+        let (text, origin) = &lines[i];
+        let keyword = match loop_keyword(text) {
+            None => {
+                out.push((text.clone(), *origin));
+                i += 1;
+                continue;
+            }
+            Some(kw @ ("endr" | "endfor")) => {
+                return Err(MacroError::UnmatchedLoopEnd {
+                    directive: format!(".{}", kw),
+                    line: *origin,
+                });
+            }
+            Some(kw) => kw,
+        };
+
+        let header = parse_loop_header(text, keyword, *origin)?;
+        let end = find_loop_end(lines, i, keyword)?;
+        let body = &lines[i + 1..end];
+
+        let (var, values): (Option<&str>, Vec<u16>) = match &header {
+            LoopHeader::Rept(n) => (None, (0..*n).collect()),
+            LoopHeader::For { var, from, to } => (Some(var), (*from..=*to).collect()),
+        };
+
+        for value in values {
+            *counter += 1;
+            let prefix = format!("__r{}_", counter);
+            let subst: HashMap<String, String> = var
+                .map(|v| (v.to_string(), value.to_string()))
+                .into_iter()
+                .collect();
+
+            // Only the innermost loop owns `%%` labels; nested bodies keep them
+            // untouched until their own expansion.
+            let mut depth = 0usize;
+            let mut iteration = Vec::with_capacity(body.len());
+            for (line, o) in body {
+                let kw = loop_keyword(line);
+                if matches!(kw, Some("endr" | "endfor")) {
+                    depth = depth.saturating_sub(1);
+                }
+                let local = if depth == 0 { prefix.as_str() } else { "%%" };
+                iteration.push((fold_const_exprs(&substitute_line(line, &subst, local)), *o));
+                if matches!(kw, Some("rept" | "for")) {
+                    depth += 1;
+                }
+            }
+
+            unroll(&iteration, out, counter)?;
+            if out.len() > MAX_UNROLLED_LINES {
+                return Err(MacroError::BadLoopHeader {
+                    message: format!(
+                        "loop expands to more than {} lines",
+                        MAX_UNROLLED_LINES
+                    ),
+                    line: *origin,
+                });
+            }
+        }
+
+        i = end + 1;
+    }
+    Ok(())
+}
+
+/// Returns the loop directive (`rept`, `for`, `endr`, `endfor`) a line starts with.
+fn loop_keyword(line: &str) -> Option<&'static str> {
+    let (code, _) = split_comment(line);
+    let rest = code.trim().strip_prefix('.')?;
+    let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    match rest[..word_end].to_lowercase().as_str() {
+        "rept" => Some("rept"),
+        "for" => Some("for"),
+        "endr" => Some("endr"),
+        "endfor" => Some("endfor"),
+        _ => None,
+    }
+}
+
+fn find_loop_end(
+    lines: &[(String, usize)],
+    start: usize,
+    keyword: &str,
+) -> Result<usize, MacroError> {
+    let closing = if keyword == "rept" { "endr" } else { "endfor" };
+    let mut depth = 0usize;
+    for (j, (line, origin)) in lines.iter().enumerate().skip(start + 1) {
+        match loop_keyword(line) {
+            Some("rept" | "for") => depth += 1,
+            Some(kw @ ("endr" | "endfor")) => {
+                if depth > 0 {
+                    depth -= 1;
+                } else if kw == closing {
+                    return Ok(j);
+                } else {
+                    return Err(MacroError::UnmatchedLoopEnd {
+                        directive: format!(".{}", kw),
+                        line: *origin,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(MacroError::UnclosedLoop {
+        directive: format!(".{}", keyword),
+        line: lines[start].1,
+    })
+}
+
+fn parse_loop_header(line: &str, keyword: &str, origin: usize) -> Result<LoopHeader, MacroError> {
+    let bad = |message: String| MacroError::BadLoopHeader { message, line: origin };
+    let (code, _) = split_comment(line);
+    let rest = code.trim()[1 + keyword.len()..].trim();
+
+    let value = |expr: &str, what: &str| -> Result<u16, MacroError> {
+        match eval_const(expr) {
+            Some(v) if (0..=0xFFFF).contains(&v) => Ok(v as u16),
+            Some(v) => Err(bad(format!("{} {} out of range", what, v))),
+            None => Err(bad(format!("expected constant {}, got '{}'", what, expr))),
+        }
+    };
+
+    if keyword == "rept" {
+        return Ok(LoopHeader::Rept(value(rest, "repeat count")?));
+    }
+
+    // .for VAR = FROM to TO
+    let (var, range) = rest
+        .split_once('=')
+        .ok_or_else(|| bad("expected '.for NAME = FROM to TO'".into()))?;
+    let var = var.trim();
+    if var.is_empty()
+        || !var.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        || !var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(bad(format!("invalid loop variable '{}'", var)));
+    }
+    let lower = range.to_lowercase();
+    let to_pos = lower
+        .find(" to ")
+        .ok_or_else(|| bad("expected '.for NAME = FROM to TO'".into()))?;
+    let from = value(&range[..to_pos], "loop start")?;
+    let to = value(&range[to_pos + 4..], "loop end")?;
+
+    Ok(LoopHeader::For {
+        var: var.to_string(),
+        from,
+        to,
+    })
+}
+
+/// Splits a line into code and its trailing `;` comment (comment keeps the `;`).
+fn split_comment(line: &str) -> (&str, &str) {
+    let mut in_string = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return line.split_at(idx),
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn is_operator_char(c: char) -> bool {
+    matches!(c, '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^' | '~' | '<' | '>' | '(')
+}
+
+/// Replaces operands that are constant expressions (`3 * 8 + 1`) by their value.
+fn fold_const_exprs(line: &str) -> String {
+    let (code, comment) = split_comment(line);
+    if code.contains('"') || !code.contains(is_operator_char) {
+        return line.to_string();
+    }
+
+    let mut out = String::with_capacity(line.len());
+    for (n, segment) in code.split(',').enumerate() {
+        if n > 0 {
+            out.push(',');
+        }
+        let head = if n == 0 { operand_start(segment) } else { 0 };
+        out.push_str(&segment[..head]);
+        out.push_str(&fold_operand(&segment[head..]));
+    }
+    out.push_str(comment);
+    out
+}
+
+/// Length of the label / mnemonic / `.const NAME =` prefix before the first operand.
+fn operand_start(segment: &str) -> usize {
+    if let Some(eq) = segment.find('=') {
+        return eq + 1;
+    }
+    let pos = skip_word(segment, 0);
+    if segment[..pos].trim_end().ends_with(':') {
+        skip_word(segment, pos)
+    } else {
+        pos
+    }
+}
+
+fn skip_word(s: &str, from: usize) -> usize {
+    let rest = &s[from..];
+    let start = from + (rest.len() - rest.trim_start().len());
+    start + s[start..].find(char::is_whitespace).unwrap_or(s.len() - start)
+}
+
+fn fold_operand(operand: &str) -> String {
+    let trimmed = operand.trim();
+    let (hash, expr) = match trimmed.strip_prefix('#') {
+        Some(e) => ("#", e),
+        None => ("", trimmed),
+    };
+    if !expr.contains(is_operator_char) {
+        return operand.to_string();
+    }
+    match eval_const(expr) {
+        Some(v) if (0..=0xFFFF).contains(&v) => {
+            let lead = &operand[..operand.len() - operand.trim_start().len()];
+            let trail = &operand[operand.trim_end().len()..];
+            format!("{}{}{}{}", lead, hash, v, trail)
+        }
+        _ => operand.to_string(),
+    }
+}
+
+/// Evaluates an integer expression made only of literals (`0x10`, `$10`,
+/// `0b101`, `16`), parentheses and C operators. Returns `None` if it refers to
+/// any symbol or is malformed.
+pub(crate) fn eval_const(expr: &str) -> Option<i64> {
+    let chars: Vec<char> = expr.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut pos = 0;
+    let v = eval_binary(&chars, &mut pos, 0)?;
+    if pos == chars.len() {
+        Some(v)
+    } else {
+        None
+    }
+}
+
+const BINARY_OPS: &[(&str, u8)] = &[
+    ("|", 0),
+    ("^", 1),
+    ("&", 2),
+    ("<<", 3),
+    (">>", 3),
+    ("+", 4),
+    ("-", 4),
+    ("*", 5),
+    ("/", 5),
+    ("%", 5),
+];
+
+fn eval_binary(chars: &[char], pos: &mut usize, min_prec: u8) -> Option<i64> {
+    let mut lhs = eval_unary(chars, pos)?;
+    while let Some(&(op, prec)) = BINARY_OPS.iter().find(|(op, _)| {
+        op.chars()
+            .enumerate()
+            .all(|(k, c)| chars.get(*pos + k) == Some(&c))
+    }) {
+        if prec < min_prec {
+            break;
+        }
+        *pos += op.len();
+        let rhs = eval_binary(chars, pos, prec + 1)?;
+        lhs = match op {
+            "|" => lhs | rhs,
+            "^" => lhs ^ rhs,
+            "&" => lhs & rhs,
+            "<<" => lhs.checked_shl(u32::try_from(rhs).ok()?)?,
+            ">>" => lhs.checked_shr(u32::try_from(rhs).ok()?)?,
+            "+" => lhs.checked_add(rhs)?,
+            "-" => lhs.checked_sub(rhs)?,
+            "*" => lhs.checked_mul(rhs)?,
+            "/" => lhs.checked_div(rhs)?,
+            _ => lhs.checked_rem(rhs)?,
+        };
+    }
+    Some(lhs)
+}
+
+fn eval_unary(chars: &[char], pos: &mut usize) -> Option<i64> {
+    match chars.get(*pos)? {
+        '-' => {
+            *pos += 1;
+            eval_unary(chars, pos)?.checked_neg()
+        }
+        '~' => {
+            *pos += 1;
+            Some(!eval_unary(chars, pos)? & 0xFFFF)
+        }
+        '(' => {
+            *pos += 1;
+            let v = eval_binary(chars, pos, 0)?;
+            if chars.get(*pos) != Some(&')') {
+                return None;
+            }
+            *pos += 1;
+            Some(v)
+        }
+        _ => eval_number(chars, pos),
+    }
+}
+
+fn eval_number(chars: &[char], pos: &mut usize) -> Option<i64> {
+    let (radix, skip) = match (chars.get(*pos), chars.get(*pos + 1)) {
+        (Some('$'), _) => (16, 1),
+        (Some('0'), Some('x' | 'X')) => (16, 2),
+        (Some('0'), Some('b' | 'B')) => (2, 2),
+        (Some(c), _) if c.is_ascii_digit() => (10, 0),
+        _ => return None,
+    };
+    let start = *pos + skip;
+    let mut end = start;
+    while end < chars.len() && chars[end].is_digit(radix) {
+        end += 1;
+    }
+    if end == start || chars.get(end).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_') {
+        return None;
+    }
+    *pos = end;
+    let digits: String = chars[start..end].iter().collect();
+    i64::from_str_radix(&digits, radix).ok()
+}
@@ -1,20 +1,47 @@
-use std::path::PathBuf;
-use clap::Parser;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
-#[command(name = "chip8-asm", about = "CHIP-8 assembler")]
+#[command(name = "chip8-asm", about = "CHIP-8 assembler", args_conflicts_with_subcommands = true)]
 struct Cli {
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    input: Option<PathBuf>,
     #[arg(short = 'o', long, default_value = "a.out.ch8")]
     output: PathBuf,
     #[arg(short = 'l', long)]
     listing: Option<PathBuf>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Report likely bugs without assembling; exits 1 if any are found
+    Lint { input: PathBuf },
+    /// Reformat files in place
+    Fmt {
+        /// Only report files that would change; exits 1 if any would
+        #[arg(long)]
+        check: bool,
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+}
+
 fn main() {
     let args = Cli::parse();
 
-    let result = match chip8_asm::assemble_file(&args.input) {
+    match &args.command {
+        Some(Command::Lint { input }) => std::process::exit(lint(input)),
+        Some(Command::Fmt { check, inputs }) => std::process::exit(fmt(inputs, *check)),
+        None => {}
+    }
+    let Some(input) = &args.input else {
+        eprintln!("error: no input file (see --help)");
+        std::process::exit(2);
+    };
+
+    let result = match chip8_asm::assemble_file(input) {
         Ok(r) => r,
         Err(errs) => {
             for e in &errs {
@@ -24,6 +51,10 @@ fn main() {
         }
     };
 
+    for w in &result.warnings {
+        eprintln!("warning: {}", w);
+    }
+
     if let Err(e) = std::fs::write(&args.output, &result.bytes) {
         eprintln!("error: writing {}: {}", args.output.display(), e);
         std::process::exit(1);
@@ -40,3 +71,83 @@ fn main() {
         }
     }
 }
+
+fn lint(input: &Path) -> i32 {
+    let analysis = match chip8_asm::analyze_file(input) {
+        Ok(a) => a,
+        Err(errs) => {
+            for e in &errs {
+                eprintln!("error: {}", e);
+            }
+            return 1;
+        }
+    };
+
+    let mut found = chip8_asm::semantic::check(&analysis);
+    found.extend(chip8_asm::lint::lint(&analysis));
+    let mut out = io::stdout().lock();
+    for d in &found {
+        let file = if d.file == "<root>" {
+            input.display().to_string()
+        } else {
+            d.file.clone()
+        };
+        let kind = match (d.severity, d.code) {
+            (chip8_asm::semantic::Severity::Error, _) => "error".to_string(),
+            (_, Some(id)) => format!("warning[{}]", id),
+            (_, None) => "warning".to_string(),
+        };
+        if let Err(e) = writeln!(out, "{}:{}:{}: {}: {}", file, d.line + 1, d.col + 1, kind, d.message) {
+            return output_failed(e);
+        }
+    }
+    if found.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+fn fmt(inputs: &[PathBuf], check: bool) -> i32 {
+    let mut out = io::stdout().lock();
+    let mut status = 0;
+    for input in inputs {
+        let source = match std::fs::read_to_string(input) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: {}: {}", input.display(), e);
+                status = 1;
+                continue;
+            }
+        };
+        let formatted = chip8_asm::format::format(&source);
+        if formatted == source {
+            continue;
+        }
+        if check {
+            let line = source
+                .lines()
+                .zip(formatted.lines())
+                .position(|(a, b)| a != b)
+                .unwrap_or(0);
+            if let Err(e) = writeln!(out, "{}:{}: not formatted", input.display(), line + 1) {
+                return output_failed(e);
+            }
+            status = 1;
+        } else if let Err(e) = std::fs::write(input, &formatted) {
+            eprintln!("error: writing {}: {}", input.display(), e);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// Exit status once stdout can't be written to. Only ever reached while
+/// reporting a problem, so it is always 1; a reader that went away, as in
+/// `chip8-asm lint x.asm | head`, isn't worth an error message.
+fn output_failed(e: io::Error) -> i32 {
+    if e.kind() != io::ErrorKind::BrokenPipe {
+        eprintln!("error: writing output: {}", e);
+    }
+    1
+}
@@ -10,6 +10,8 @@ pub enum Addr {
 pub enum Imm {
     Val(u16),
     Label(String),
+    /// Two's complement of a symbol's byte value (`SUB Vx, SYM`).
+    Neg(String),
 }
 
 #[derive(Debug, Clone)]
@@ -56,6 +58,13 @@ pub enum Statement {
     Const(String, u16),
     Label(String),
     Inst(Inst),
+    /// A pseudo-instruction (`INC V0`, `JEQ V1, 3, done`, ...) and the raw
+    /// instructions it expands to. `text` is the normalized source form shown
+    /// in the listing.
+    Pseudo {
+        text: String,
+        insts: Vec<Inst>,
+    },
     Byte(Vec<Imm>),
     Word(Vec<Imm>),
     Ascii(String),
@@ -104,6 +113,7 @@ pub enum ParseError {
     DuplicateLabel(String, usize),
     MissingLabelName(usize, usize),
     BadConstSyntax(usize, usize),
+    BadBlock(String, usize, usize),
 }
 
 impl std::fmt::Display for ParseError {
@@ -123,13 +133,23 @@ impl std::fmt::Display for ParseError {
             ParseError::DuplicateLabel(l, ..) => write!(f, "duplicate label '{}'", l),
             ParseError::MissingLabelName(..) => write!(f, "missing label name"),
             ParseError::BadConstSyntax(..) => write!(f, "bad .const syntax"),
+            ParseError::BadBlock(m, ..) => write!(f, "{}", m),
         }
     }
 }
 
 pub fn parse(tokens: &[(Token, usize, usize)]) -> Result<Vec<Statement>, Vec<ParseError>> {
+    parse_with_lines(tokens).map(|(stmts, _)| stmts)
+}
+
+/// Like [`parse`], but also returns the source line each statement came from.
+pub fn parse_with_lines(
+    tokens: &[(Token, usize, usize)],
+) -> Result<(Vec<Statement>, Vec<usize>), Vec<ParseError>> {
     let mut stmts = Vec::new();
+    let mut lines = Vec::new();
     let mut errors = Vec::new();
+    let mut flow = Flow::default();
     let mut i = 0;
 
     while i < tokens.len() {
@@ -137,8 +157,12 @@ pub fn parse(tokens: &[(Token, usize, usize)]) -> Result<Vec<Statement>, Vec<Par
         skip_newlines(tokens, &mut i);
         if i >= tokens.len() { break; }
 
-        match parse_line(tokens, &mut i) {
-            Ok(mut line_stmts) => stmts.append(&mut line_stmts),
+        let line = tokens[i].1;
+        match parse_line(tokens, &mut i, &mut flow) {
+            Ok(mut line_stmts) => {
+                lines.extend(std::iter::repeat_n(line, line_stmts.len()));
+                stmts.append(&mut line_stmts);
+            }
             Err(e) => {
                 errors.push(e);
                 eat_rest(tokens, &mut i);
@@ -146,7 +170,15 @@ pub fn parse(tokens: &[(Token, usize, usize)]) -> Result<Vec<Statement>, Vec<Par
         }
     }
 
-    if errors.is_empty() { Ok(stmts) } else { Err(errors) }
+    for block in flow.blocks {
+        errors.push(ParseError::BadBlock(
+            format!("'{}' block is never closed", block.keyword()),
+            block.line,
+            block.col,
+        ));
+    }
+
+    if errors.is_empty() { Ok((stmts, lines)) } else { Err(errors) }
 }
 
 // -- helpers --
@@ -169,7 +201,9 @@ fn is_eol(tokens: &[(Token, usize, usize)], i: usize) -> bool {
 
 // -- line parsing --
 
-fn parse_line(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<Vec<Statement>, ParseError> {
+fn parse_line(
+    tokens: &[(Token, usize, usize)], i: &mut usize, flow: &mut Flow,
+) -> Result<Vec<Statement>, ParseError> {
     let mut stmts = Vec::new();
 
     // Check for label: Word followed by Colon
@@ -200,6 +234,12 @@ fn parse_line(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<Vec<Sta
         }
     }
 
+    // Structured control flow: if / while / loop / break / continue / }
+    if let Some(mut flow_stmts) = parse_flow(tokens, i, flow)? {
+        stmts.append(&mut flow_stmts);
+        return Ok(stmts);
+    }
+
     // Instruction
     if !is_eol(tokens, *i) {
         if let Some(s) = parse_instruction_line(tokens, i)? {
@@ -232,10 +272,193 @@ fn parse_instruction_line(
         _ => return Ok(None),
     };
 
+    if let Some(stmt) = parse_pseudo(&word, tokens, i, ln, cl)? {
+        return Ok(Some(stmt));
+    }
     let inst = parse_inst(&word, tokens, i, ln, cl)?;
     Ok(Some(Statement::Inst(inst)))
 }
 
+// -- pseudo-instructions --
+
+/// Scratch register used by pseudo-instructions that need a constant operand.
+const SCRATCH: u8 = 0xF;
+
+/// Recognizes pseudo-instructions. `SUB`, `LD` and `CALL` are only treated as
+/// pseudo when their operands don't match a raw form; otherwise `i` is left
+/// untouched and `None` is returned so `parse_inst` handles the line.
+fn parse_pseudo(
+    word: &str, tokens: &[(Token, usize, usize)], i: &mut usize, line: usize, col: usize,
+) -> Result<Option<Statement>, ParseError> {
+    let upper = word.to_uppercase();
+    if !matches!(upper.as_str(), "NOT" | "NEG" | "INC" | "DEC" | "JEQ" | "JNE" | "SUB" | "LD" | "CALL") {
+        return Ok(None);
+    }
+
+    let mut j = *i;
+    let ops = match parse_operand_list(tokens, &mut j) {
+        Ok(ops) => ops,
+        Err(_) if matches!(upper.as_str(), "SUB" | "LD" | "CALL") => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let arg_count = |expected: usize| ParseError::WrongArgCount {
+        mnemonic: upper.clone(), expected, got: ops.len(), line, col,
+    };
+
+    let (text, insts) = match (upper.as_str(), ops.as_slice()) {
+        ("NOT" | "NEG", [Operand::Reg(SCRATCH)]) => {
+            return Err(ParseError::UnexpectedToken(
+                format!("{} cannot be used on VF (VF is its scratch register)", upper), line, col,
+            ));
+        }
+        // VF = 0xFF; Vx ^= VF
+        ("NOT", [Operand::Reg(x)]) => (
+            format!("NOT V{:X}", x),
+            vec![Inst::LdVb(SCRATCH, Imm::Val(0xFF)), Inst::Xor(*x, SCRATCH)],
+        ),
+        // VF = 0; Vx = VF - Vx
+        ("NEG", [Operand::Reg(x)]) => (
+            format!("NEG V{:X}", x),
+            vec![Inst::LdVb(SCRATCH, Imm::Val(0)), Inst::Subn(*x, SCRATCH)],
+        ),
+        ("INC", [Operand::Reg(x)]) => (format!("INC V{:X}", x), vec![Inst::AddVb(*x, Imm::Val(1))]),
+        ("DEC", [Operand::Reg(x)]) => (format!("DEC V{:X}", x), vec![Inst::AddVb(*x, Imm::Val(0xFF))]),
+        ("NOT" | "NEG" | "INC" | "DEC", [_]) => return Err(ParseError::ExpectedRegister(line, col)),
+        ("NOT" | "NEG" | "INC" | "DEC", _) => return Err(arg_count(1)),
+
+        // SUB Vx, #imm  =>  ADD Vx, -imm
+        ("SUB", [Operand::Reg(x), Operand::Imm(n)]) => {
+            if *n > 0xFF {
+                return Err(ParseError::UnexpectedToken(
+                    format!("value {} exceeds max {}", n, 0xFF), line, col,
+                ));
+            }
+            (format!("SUB V{:X}, {}", x, n), vec![Inst::AddVb(*x, Imm::Val((0x100 - n) & 0xFF))])
+        }
+        ("SUB", [Operand::Reg(x), Operand::Ident(s)]) => {
+            (format!("SUB V{:X}, {}", x, s), vec![Inst::AddVb(*x, Imm::Neg(s.clone()))])
+        }
+
+        // JEQ jumps when equal: skip the jump unless equal, and vice versa.
+        ("JEQ" | "JNE", [Operand::Reg(x), cmp, target]) => {
+            let eq = upper == "JEQ";
+            let skip = match cmp {
+                Operand::Reg(y) if eq => Inst::SneVV(*x, *y),
+                Operand::Reg(y) => Inst::SeVV(*x, *y),
+                _ => {
+                    let kk = operand_byte(cmp, line, col)?;
+                    if eq { Inst::SneVb(*x, kk) } else { Inst::SeVb(*x, kk) }
+                }
+            };
+            let target = parse_addr(target.clone(), line, col)?;
+            (
+                format!("{} V{:X}, {}, {}", upper, x, operand_text(cmp), addr_text(&target)),
+                vec![skip, Inst::Jp(target)],
+            )
+        }
+        ("JEQ" | "JNE", [_, _, _]) => return Err(ParseError::ExpectedRegister(line, col)),
+        ("JEQ" | "JNE", _) => return Err(arg_count(3)),
+
+        // LD V0, [addr] / LD [addr], V0  =>  point I at addr, then F065 / F055.
+        // Fx65/Fx55 touch V0..Vx, so any other register needs the range spelled out
+        ("LD", [Operand::Reg(0), Operand::Mem(a)]) => (
+            format!("LD V0, [{}]", addr_text(a)),
+            vec![Inst::LdI(a.clone()), Inst::LdVI(0)],
+        ),
+        ("LD", [Operand::Mem(a), Operand::Reg(0)]) => (
+            format!("LD [{}], V0", addr_text(a)),
+            vec![Inst::LdI(a.clone()), Inst::LdIV(0)],
+        ),
+        ("LD", [Operand::Reg(x), Operand::Mem(_)] | [Operand::Mem(_), Operand::Reg(x)]) => {
+            return Err(ParseError::UnexpectedToken(
+                format!("LD with [addr] moves V0..V{:X}; write the range as V0..V{:X}", x, x),
+                line, col,
+            ));
+        }
+        ("LD", [Operand::Regs(x), Operand::Mem(a)]) => (
+            format!("LD V0..V{:X}, [{}]", x, addr_text(a)),
+            vec![Inst::LdI(a.clone()), Inst::LdVI(*x)],
+        ),
+        ("LD", [Operand::Mem(a), Operand::Regs(x)]) => (
+            format!("LD [{}], V0..V{:X}", addr_text(a), x),
+            vec![Inst::LdI(a.clone()), Inst::LdIV(*x)],
+        ),
+
+        // CALL target, Vx, [save]  =>  save V0..Vx, call, restore V0..Vx
+        ("CALL", [target, Operand::Reg(x), Operand::Mem(save)]) => {
+            let target = parse_addr(target.clone(), line, col)?;
+            (
+                format!("CALL {}, V{:X}, [{}]", addr_text(&target), x, addr_text(save)),
+                vec![
+                    Inst::LdI(save.clone()),
+                    Inst::LdIV(*x),
+                    Inst::Call(target),
+                    Inst::LdI(save.clone()),
+                    Inst::LdVI(*x),
+                ],
+            )
+        }
+
+        _ => return Ok(None),
+    };
+
+    *i = j;
+    Ok(Some(Statement::Pseudo { text, insts }))
+}
+
+/// Parses comma-separated operands up to the end of the line.
+fn parse_operand_list(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<Vec<Operand>, ParseError> {
+    let mut ops = Vec::new();
+    while !is_eol(tokens, *i) {
+        ops.push(parse_operand(tokens, i)?);
+        if !is_eol(tokens, *i) {
+            match peek(tokens, *i) {
+                Some(Token::Comma) => *i += 1,
+                Some(t) => {
+                    let (l, c) = tok_pos(tokens, *i);
+                    return Err(unexpected(t, l, c));
+                }
+                None => {}
+            }
+        }
+    }
+    Ok(ops)
+}
+
+/// Converts a number or symbol operand into a byte immediate.
+fn operand_byte(op: &Operand, line: usize, col: usize) -> Result<Imm, ParseError> {
+    match op {
+        Operand::Imm(n) if *n <= 0xFF => Ok(Imm::Val(*n)),
+        Operand::Imm(n) => Err(ParseError::UnexpectedToken(
+            format!("value {} exceeds max {}", n, 0xFF), line, col,
+        )),
+        Operand::Ident(s) => Ok(Imm::Label(s.clone())),
+        _ => Err(ParseError::ExpectedImmediate(line, col)),
+    }
+}
+
+fn addr_text(a: &Addr) -> String {
+    match a {
+        Addr::Num(n) => format!("0x{:03X}", n),
+        Addr::Label(s) => s.clone(),
+    }
+}
+
+fn operand_text(op: &Operand) -> String {
+    match op {
+        Operand::Reg(r) => format!("V{:X}", r),
+        Operand::Imm(n) => n.to_string(),
+        Operand::Ident(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Every mnemonic the parser accepts, pseudo-instructions included.
+pub const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+    "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "NOT", "NEG", "INC", "DEC", "JEQ", "JNE",
+];
+
 fn parse_inst(
     word: &str, tokens: &[(Token, usize, usize)], i: &mut usize, line: usize, col: usize,
 ) -> Result<Inst, ParseError> {
@@ -349,8 +572,8 @@ fn parse_ld(
         (Operand::F, Operand::Reg(x)) => Ok(Inst::LdF(x)),
         (Operand::B, Operand::Reg(x)) => Ok(Inst::LdB(x)),
         (Operand::I, op) => Ok(Inst::LdI(parse_addr(op, line, col)?)),
-        (Operand::MemI, Operand::Reg(x)) => Ok(Inst::LdIV(x)),
-        (Operand::Reg(x), Operand::MemI) => Ok(Inst::LdVI(x)),
+        (Operand::MemI, Operand::Reg(x) | Operand::Regs(x)) => Ok(Inst::LdIV(x)),
+        (Operand::Reg(x) | Operand::Regs(x), Operand::MemI) => Ok(Inst::LdVI(x)),
         _ => Err(ParseError::WrongArgCount { mnemonic: "LD".into(), expected: 2, got: 2, line, col }),
     }
 }
@@ -406,9 +629,197 @@ fn parse_bin_reg(
     Ok(f(x, y))
 }
 
+// -- structured control flow --
+
+/// An open `{` block. Blocks are lowered as they are parsed into generated
+/// labels (`__if{id}_else`, `__loop{id}_top`, ...) plus `SE`/`SNE`/`JP`, so
+/// `compute_layout` and `generate_code` only ever see plain statements.
+struct Block {
+    kind: BlockKind,
+    id: usize,
+    line: usize,
+    col: usize,
+}
+
+enum BlockKind {
+    If { has_else: bool },
+    While,
+    Loop,
+}
+
+impl Block {
+    fn keyword(&self) -> &'static str {
+        match self.kind {
+            BlockKind::If { .. } => "if",
+            BlockKind::While => "while",
+            BlockKind::Loop => "loop",
+        }
+    }
+
+    fn label(&self, part: &str) -> String {
+        match self.kind {
+            BlockKind::If { .. } => format!("__if{}_{}", self.id, part),
+            BlockKind::While | BlockKind::Loop => format!("__loop{}_{}", self.id, part),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Flow {
+    next_id: usize,
+    blocks: Vec<Block>,
+}
+
+impl Flow {
+    fn open(&mut self, kind: BlockKind, line: usize, col: usize) -> &Block {
+        self.blocks.push(Block { kind, id: self.next_id, line, col });
+        self.next_id += 1;
+        self.blocks.last().unwrap()
+    }
+}
+
+/// Parses a control-flow line, or returns `None` if the line isn't one.
+///
+/// ```text
+/// if V0 == 3 {          SE V0, 3 / JP __if0_else
+/// } else {              JP __if0_end / __if0_else:
+/// }                     __if0_end:
+/// while V1 != V2 {      __loop1_top: / SNE V1, V2 / JP __loop1_end
+/// }                     JP __loop1_top / __loop1_end:
+/// loop { break }        __loop2_top: / JP __loop2_end / JP __loop2_top / __loop2_end:
+/// ```
+fn parse_flow(
+    tokens: &[(Token, usize, usize)], i: &mut usize, flow: &mut Flow,
+) -> Result<Option<Vec<Statement>>, ParseError> {
+    let (line, col) = tok_pos(tokens, *i);
+    let word = match peek(tokens, *i) {
+        Some(Token::Word(w)) => w.as_str(),
+        Some(Token::RBrace) => {
+            *i += 1;
+            return close_block(tokens, i, flow, line, col).map(Some);
+        }
+        _ => return Ok(None),
+    };
+
+    // Flow keywords are case-insensitive, like mnemonics
+    let keyword = word.to_lowercase();
+    let stmts = match keyword.as_str() {
+        "if" => {
+            *i += 1;
+            let skip = parse_condition(tokens, i, line, col)?;
+            expect_block_open(tokens, i)?;
+            let block = flow.open(BlockKind::If { has_else: false }, line, col);
+            vec![Statement::Inst(skip), jump_to(block.label("else"))]
+        }
+        "while" => {
+            *i += 1;
+            let skip = parse_condition(tokens, i, line, col)?;
+            expect_block_open(tokens, i)?;
+            let block = flow.open(BlockKind::While, line, col);
+            vec![
+                Statement::Label(block.label("top")),
+                Statement::Inst(skip),
+                jump_to(block.label("end")),
+            ]
+        }
+        "loop" if matches!(peek(tokens, *i + 1), Some(Token::LBrace)) => {
+            *i += 1;
+            expect_block_open(tokens, i)?;
+            let block = flow.open(BlockKind::Loop, line, col);
+            vec![Statement::Label(block.label("top"))]
+        }
+        "break" | "continue" => {
+            *i += 1;
+            expect_eol(tokens, i)?;
+            let block = flow
+                .blocks
+                .iter()
+                .rev()
+                .find(|b| !matches!(b.kind, BlockKind::If { .. }))
+                .ok_or_else(|| ParseError::BadBlock(format!("'{}' outside of a loop", keyword), line, col))?;
+            let part = if keyword == "break" { "end" } else { "top" };
+            vec![jump_to(block.label(part))]
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(stmts))
+}
+
+/// Handles `}` and `} else {`.
+fn close_block(
+    tokens: &[(Token, usize, usize)], i: &mut usize, flow: &mut Flow, line: usize, col: usize,
+) -> Result<Vec<Statement>, ParseError> {
+    let mut block = flow
+        .blocks
+        .pop()
+        .ok_or_else(|| ParseError::BadBlock("unmatched '}'".into(), line, col))?;
+
+    if matches!(peek(tokens, *i), Some(Token::Word(w)) if w.eq_ignore_ascii_case("else")) {
+        let (el, ec) = tok_pos(tokens, *i);
+        *i += 1;
+        if !matches!(block.kind, BlockKind::If { has_else: false }) {
+            return Err(ParseError::BadBlock(
+                format!("'else' without a matching 'if' (closing '{}')", block.keyword()),
+                el, ec,
+            ));
+        }
+        expect_block_open(tokens, i)?;
+        let stmts = vec![jump_to(block.label("end")), Statement::Label(block.label("else"))];
+        block.kind = BlockKind::If { has_else: true };
+        flow.blocks.push(block);
+        return Ok(stmts);
+    }
+
+    expect_eol(tokens, i)?;
+    Ok(match block.kind {
+        BlockKind::If { has_else: false } => vec![Statement::Label(block.label("else"))],
+        BlockKind::If { has_else: true } => vec![Statement::Label(block.label("end"))],
+        BlockKind::While | BlockKind::Loop => vec![
+            jump_to(block.label("top")),
+            Statement::Label(block.label("end")),
+        ],
+    })
+}
+
+/// Parses `Vx == operand` / `Vx != operand` and returns the instruction that
+/// skips the following jump when the condition holds.
+fn parse_condition(
+    tokens: &[(Token, usize, usize)], i: &mut usize, line: usize, col: usize,
+) -> Result<Inst, ParseError> {
+    let x = parse_reg(tokens, i)?;
+    let (ol, oc) = tok_pos(tokens, *i);
+    let eq = match peek(tokens, *i) {
+        Some(Token::EqEq) => true,
+        Some(Token::NotEq) => false,
+        _ => return Err(ParseError::BadBlock("expected '==' or '!=' in condition".into(), ol, oc)),
+    };
+    *i += 1;
+    match parse_operand(tokens, i)? {
+        Operand::Reg(y) if eq => Ok(Inst::SeVV(x, y)),
+        Operand::Reg(y) => Ok(Inst::SneVV(x, y)),
+        op => {
+            let kk = operand_byte(&op, line, col)?;
+            Ok(if eq { Inst::SeVb(x, kk) } else { Inst::SneVb(x, kk) })
+        }
+    }
+}
+
+fn expect_block_open(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<(), ParseError> {
+    let (l, c) = tok_pos(tokens, *i);
+    if !matches!(peek(tokens, *i), Some(Token::LBrace)) {
+        return Err(ParseError::BadBlock("expected '{' to open a block".into(), l, c));
+    }
+    *i += 1;
+    expect_eol(tokens, i)
+}
+
+fn jump_to(label: String) -> Statement {
+    Statement::Inst(Inst::Jp(Addr::Label(label)))
+}
+
 // -- operand parsing --
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Operand {
     Reg(u8),
     Imm(u16),
@@ -420,6 +831,10 @@ enum Operand {
     F,
     B,
     MemI,
+    /// `[label]` or `[0x300]`, only valid in pseudo-instructions.
+    Mem(Addr),
+    /// `V0..Vx`, only valid in pseudo-instructions.
+    Regs(u8),
 }
 
 fn parse_operand(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<Operand, ParseError> {
@@ -441,9 +856,25 @@ fn parse_operand(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<Oper
                 "F" => Ok(Operand::F),
                 "B" => Ok(Operand::B),
                 r if r.starts_with('V') && r.len() <= 3 => {
-                    match u8::from_str_radix(&r[1..], 16) {
-                        Ok(n) if n <= 15 => Ok(Operand::Reg(n)),
-                        _ => Err(ParseError::InvalidRegister(name, line, col)),
+                    let n = match u8::from_str_radix(&r[1..], 16) {
+                        Ok(n) if n <= 15 => n,
+                        _ => return Err(ParseError::InvalidRegister(name, line, col)),
+                    };
+                    if !matches!(
+                        (peek(tokens, *i), peek(tokens, *i + 1)),
+                        (Some(Token::Dot), Some(Token::Dot))
+                    ) {
+                        return Ok(Operand::Reg(n));
+                    }
+                    *i += 2;
+                    if n != 0 {
+                        return Err(ParseError::UnexpectedToken(
+                            "register range must start at V0".into(), line, col,
+                        ));
+                    }
+                    match parse_operand(tokens, i)? {
+                        Operand::Reg(x) => Ok(Operand::Regs(x)),
+                        _ => Err(ParseError::ExpectedRegister(line, col)),
                     }
                 }
                 _ => Ok(Operand::Ident(name)),
@@ -451,15 +882,18 @@ fn parse_operand(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<Oper
         }
         Token::LBracket => {
             *i += 1;
-            match peek(tokens, *i) {
-                Some(Token::Word(w)) if w.to_uppercase() == "I" => { *i += 1; }
+            let op = match peek(tokens, *i) {
+                Some(Token::Word(w)) if w.to_uppercase() == "I" => Operand::MemI,
+                Some(Token::Word(w)) => Operand::Mem(Addr::Label(w.clone())),
+                Some(Token::Number(n)) => Operand::Mem(Addr::Num(*n)),
                 _ => return Err(ParseError::ExpectedRegister(line, col)),
-            }
+            };
+            *i += 1;
             match peek(tokens, *i) {
                 Some(Token::RBracket) => { *i += 1; }
                 _ => return Err(ParseError::UnexpectedToken("expected ']'".into(), line, col)),
             }
-            Ok(Operand::MemI)
+            Ok(op)
         }
         t => Err(ParseError::UnexpectedToken(format!("{:?}", t), line, col)),
     }
@@ -542,6 +976,7 @@ fn parse_directive(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<St
         "align" => { let n = parse_single_imm(tokens, i, 0xFF)? as u8; Ok(Statement::Align(n)) }
         "space" => { let n = parse_single_imm(tokens, i, 0xFFFF)?; Ok(Statement::Space(n)) }
         "const" => parse_const(tokens, i, line, col),
+        "sprite" => { let v = parse_sprite_rows(tokens, i)?; Ok(Statement::Byte(v)) }
         d => Err(ParseError::InvalidDirective(d.to_string(), line, col)),
     }
 }
@@ -604,6 +1039,45 @@ fn parse_imm_or_label_list(tokens: &[(Token, usize, usize)], i: &mut usize, max_
     }
 }
 
+/// `.sprite "..##..##", ".#....#."` — one string per row, 8 or 16 pixels wide.
+/// `#`, `X`, `1`, `@` and `*` are lit pixels; `.`, `0`, `_`, `-` and space are dark.
+fn parse_sprite_rows(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<Vec<Imm>, ParseError> {
+    let mut bytes = Vec::new();
+    let mut width = None;
+    loop {
+        let (l, c) = tok_pos(tokens, *i);
+        let row = parse_string(tokens, i)?;
+        let pixels: Vec<bool> = row
+            .chars()
+            .map(|ch| match ch {
+                '#' | 'X' | 'x' | '1' | '@' | '*' => Ok(true),
+                '.' | '0' | '_' | '-' | ' ' => Ok(false),
+                other => Err(ParseError::UnexpectedToken(
+                    format!("invalid sprite pixel '{}'", other), l, c,
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        if pixels.len() != 8 && pixels.len() != 16 {
+            return Err(ParseError::UnexpectedToken(
+                format!("sprite row must be 8 or 16 pixels wide, got {}", pixels.len()), l, c,
+            ));
+        }
+        if *width.get_or_insert(pixels.len()) != pixels.len() {
+            return Err(ParseError::UnexpectedToken(
+                "sprite rows must all have the same width".into(), l, c,
+            ));
+        }
+        for chunk in pixels.chunks(8) {
+            let byte = chunk.iter().fold(0u16, |acc, &on| (acc << 1) | on as u16);
+            bytes.push(Imm::Val(byte));
+        }
+        if !matches!(peek(tokens, *i), Some(Token::Comma)) { break; }
+        *i += 1;
+        skip_newlines(tokens, i);
+    }
+    Ok(bytes)
+}
+
 fn parse_string(tokens: &[(Token, usize, usize)], i: &mut usize) -> Result<String, ParseError> {
     let (line, col) = tok_pos(tokens, *i);
     match peek(tokens, *i) {
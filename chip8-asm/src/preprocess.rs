@@ -2,6 +2,7 @@ use std::path::Path;
 
 use crate::sourcemap::SourceMap;
 use crate::include::{FileProvider, IncludeError, IncludeResolver};
+use crate::macroexpand::MacroError;
 
 pub struct PreprocessResult {
     pub source: String,
@@ -12,6 +13,11 @@ pub struct PreprocessResult {
 pub enum PreprocessError {
     Include(IncludeError),
     Macro(String),
+    At {
+        file: String,
+        line: usize,
+        message: String,
+    },
 }
 
 impl std::fmt::Display for PreprocessError {
@@ -19,6 +25,9 @@ impl std::fmt::Display for PreprocessError {
         match self {
             PreprocessError::Include(e) => write!(f, "{}", e),
             PreprocessError::Macro(msg) => write!(f, "macro error: {}", msg),
+            PreprocessError::At { file, line, message } => {
+                write!(f, "{}:{}: {}", file, line + 1, message)
+            }
         }
     }
 }
@@ -50,22 +59,73 @@ pub fn preprocess(
     let (stripped, macro_defs) = match crate::macroexpand::collect_definitions(&expanded) {
         Ok(r) => r,
         Err(e) => {
-            errors.push(PreprocessError::Macro(format!("{}", e)));
+            errors.push(macro_error(e, &source_map));
             return Err(errors);
         }
     };
 
-    // Step 3: Expand macro invocations
-    let final_source = match crate::macroexpand::expand(&stripped, &macro_defs) {
+    // Step 3: Unroll loops written directly in the source
+    let (unrolled, origins) = match crate::macroexpand::expand_loops(&stripped) {
         Ok(r) => r,
         Err(e) => {
-            errors.push(PreprocessError::Macro(format!("{}", e)));
+            errors.push(macro_error(e, &source_map));
             return Err(errors);
         }
     };
+    let source_map = source_map.remap(&origins);
+
+    // Step 4: Expand macro invocations
+    let (macro_expanded, origins) = match crate::macroexpand::expand_mapped(&unrolled, &macro_defs) {
+        Ok(r) => r,
+        Err(e) => {
+            errors.push(macro_error(e, &source_map));
+            return Err(errors);
+        }
+    };
+    let source_map = source_map.remap(&origins);
+
+    // Step 5: Unroll loops that came from macro bodies
+    let (final_source, origins) = match crate::macroexpand::expand_loops(&macro_expanded) {
+        Ok(r) => r,
+        Err(e) => {
+            errors.push(macro_error(e, &source_map));
+            return Err(errors);
+        }
+    };
+    let source_map = source_map.remap(&origins);
+
+    // Step 6: Pull in binary files and images
+    let (final_source, origins) =
+        match crate::include::expand_binary_includes(&final_source, &source_map, base_dir, provider) {
+            Ok(r) => r,
+            Err((line, message)) => {
+                let (file, file_line) = source_map.resolve(line);
+                errors.push(PreprocessError::At {
+                    file: file.to_string(),
+                    line: file_line,
+                    message,
+                });
+                return Err(errors);
+            }
+        };
+    let source_map = source_map.remap(&origins);
 
     Ok(PreprocessResult {
         source: final_source,
         source_map,
     })
 }
+
+fn macro_error(e: MacroError, source_map: &SourceMap) -> PreprocessError {
+    match e.line() {
+        Some(line) => {
+            let (file, file_line) = source_map.resolve(line);
+            PreprocessError::At {
+                file: file.to_string(),
+                line: file_line,
+                message: e.to_string(),
+            }
+        }
+        None => PreprocessError::Macro(format!("{}", e)),
+    }
+}
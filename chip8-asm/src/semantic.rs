@@ -0,0 +1,268 @@
+use crate::lexer::{word_end, Token};
+use crate::parser::{Addr, Imm, Inst, Statement};
+use crate::{statement_size, AnalysisResult, AssemblyError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found after layout, positioned in the original file.
+/// `col..end_col` covers the offending operand where it can be located,
+/// otherwise the statement's text.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub end_col: usize,
+    /// Lint ID (`unused-label`, ...) for findings from [`crate::lint`].
+    pub code: Option<&'static str>,
+}
+
+impl From<Diagnostic> for AssemblyError {
+    fn from(d: Diagnostic) -> Self {
+        AssemblyError {
+            message: d.message,
+            file: Some(d.file),
+            line: d.line,
+            col: d.col,
+        }
+    }
+}
+
+/// Checks an analyzed program for errors `generate_code` would only report
+/// without a position: undefined symbols, operands that don't fit their
+/// field, `DRW` heights above 15 and code regions placed on top of each other
+/// by `.org`. Also warns about `.org` into the interpreter area and `DRW`
+/// with height 0, which only SCHIP draws (as a 16x16 sprite).
+pub fn check(analysis: &AnalysisResult) -> Vec<Diagnostic> {
+    let mut cx = Checker {
+        analysis,
+        out: Vec::new(),
+    };
+
+    for (idx, stmt) in analysis.statements.iter().enumerate() {
+        let line = cx.line_of(idx);
+        match stmt {
+            Statement::Inst(inst) => cx.inst(inst, line),
+            Statement::Pseudo { insts, .. } => {
+                for inst in insts {
+                    cx.inst(inst, line);
+                }
+            }
+            Statement::Byte(v) => {
+                for imm in v {
+                    cx.imm(imm, 0xFF, line);
+                }
+            }
+            Statement::Word(v) => {
+                for imm in v {
+                    cx.imm(imm, 0xFFFF, line);
+                }
+            }
+            Statement::Org(a) if *a < 0x200 => cx.push(
+                Severity::Warning,
+                format!(".org 0x{:03X} is below 0x200, inside the interpreter area", a),
+                line,
+                None,
+            ),
+            _ => {}
+        }
+    }
+
+    cx.overlaps();
+    cx.out
+}
+
+struct Checker<'a> {
+    analysis: &'a AnalysisResult,
+    out: Vec<Diagnostic>,
+}
+
+/// What to underline in a statement's line.
+pub(crate) enum Target<'a> {
+    Symbol(&'a str),
+    Number(u16),
+}
+
+impl Checker<'_> {
+    fn line_of(&self, stmt_idx: usize) -> usize {
+        self.analysis.statement_lines.get(stmt_idx).copied().unwrap_or(0)
+    }
+
+    fn inst(&mut self, inst: &Inst, line: usize) {
+        match inst {
+            Inst::Jp(a) | Inst::Call(a) | Inst::LdI(a) | Inst::JpV0(a) => self.addr(a, line),
+            Inst::SeVb(_, imm)
+            | Inst::SneVb(_, imm)
+            | Inst::LdVb(_, imm)
+            | Inst::AddVb(_, imm)
+            | Inst::Rnd(_, imm) => self.imm(imm, 0xFF, line),
+            Inst::Drw(_, _, imm) => match self.value(imm, line) {
+                Some(n) if n > 0xF => self.push(
+                    Severity::Error,
+                    format!("DRW height {} exceeds 15", n),
+                    line,
+                    Some(target_of(imm, n)),
+                ),
+                Some(0) => self.push(
+                    Severity::Warning,
+                    "DRW height 0 draws a 16x16 sprite on SCHIP only".to_string(),
+                    line,
+                    Some(target_of(imm, 0)),
+                ),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn imm(&mut self, imm: &Imm, max: u16, line: usize) {
+        if let Some(n) = self.value(imm, line) {
+            if n > max {
+                self.push(
+                    Severity::Error,
+                    format!("value {} exceeds max {}", n, max),
+                    line,
+                    Some(target_of(imm, n)),
+                );
+            }
+        }
+    }
+
+    fn addr(&mut self, addr: &Addr, line: usize) {
+        let (n, target) = match addr {
+            Addr::Num(n) => (*n, Target::Number(*n)),
+            Addr::Label(name) => match self.resolve(name, line) {
+                Some(n) => (n, Target::Symbol(name)),
+                None => return,
+            },
+        };
+        if n > 0xFFF {
+            self.push(
+                Severity::Error,
+                format!("address 0x{:X} exceeds 0xFFF", n),
+                line,
+                Some(target),
+            );
+        }
+    }
+
+    /// Resolves an immediate, reporting undefined symbols. `Imm::Neg` yields
+    /// the symbol's own value so range checks apply to what the user wrote.
+    fn value(&mut self, imm: &Imm, line: usize) -> Option<u16> {
+        match imm {
+            Imm::Val(n) => Some(*n),
+            Imm::Label(name) | Imm::Neg(name) => self.resolve(name, line),
+        }
+    }
+
+    fn resolve(&mut self, name: &str, line: usize) -> Option<u16> {
+        let v = self.analysis.symbol_table.resolve(name);
+        if v.is_none() {
+            self.push(
+                Severity::Error,
+                format!("undefined symbol '{}'", name),
+                line,
+                Some(Target::Symbol(name)),
+            );
+        }
+        v
+    }
+
+    /// Reports the first statement of each `.org` region that lands on bytes
+    /// already emitted by an earlier region.
+    fn overlaps(&mut self) {
+        let mut covered: Vec<(u16, u16, usize)> = Vec::new();
+        let mut reported = false;
+
+        for (idx, stmt) in self.analysis.statements.iter().enumerate() {
+            if matches!(stmt, Statement::Org(_)) {
+                reported = false;
+                continue;
+            }
+            let start = self.analysis.addresses[idx];
+            let end = start.saturating_add(statement_size(stmt, start));
+            if end == start {
+                continue;
+            }
+            let clash = covered.iter().find(|(s, e, _)| start < *e && *s < end).copied();
+            if let (Some((s, e, other)), false) = (clash, reported) {
+                let other_line = self.analysis.source_map.resolve(self.line_of(other)).1;
+                self.push(
+                    Severity::Error,
+                    format!(
+                        "code at 0x{:03X} overlaps bytes 0x{:03X}-0x{:03X} emitted at line {}",
+                        start,
+                        s.max(start),
+                        e.min(end) - 1,
+                        other_line + 1
+                    ),
+                    self.line_of(idx),
+                    None,
+                );
+                reported = true;
+            }
+            covered.push((start, end, idx));
+        }
+    }
+
+    fn push(&mut self, severity: Severity, message: String, line: usize, target: Option<Target>) {
+        let d = diagnostic(self.analysis, severity, message, line, target);
+        self.out.push(d);
+    }
+}
+
+/// Builds a diagnostic for `line` of the expanded source, mapped back to its
+/// file through the source map.
+pub(crate) fn diagnostic(
+    analysis: &AnalysisResult,
+    severity: Severity,
+    message: String,
+    line: usize,
+    target: Option<Target>,
+) -> Diagnostic {
+    let (col, end_col) = span(analysis, line, target);
+    let (file, file_line) = analysis.source_map.resolve(line);
+    Diagnostic {
+        severity,
+        message,
+        file: file.to_string(),
+        line: file_line,
+        col,
+        end_col,
+        code: None,
+    }
+}
+
+/// Columns of `target` on `line` of the expanded source, falling back to
+/// the code part of the whole line.
+fn span(analysis: &AnalysisResult, line: usize, target: Option<Target>) -> (usize, usize) {
+    let text = analysis.expanded_source.lines().nth(line).unwrap_or("");
+    let mut on_line = analysis.tokens.iter().rev().filter(|(_, l, _)| *l == line);
+    let found = match target {
+        Some(Target::Symbol(name)) => on_line
+            .find(|(t, _, _)| matches!(t, Token::Word(w) if w == name))
+            .map(|(_, _, c)| (*c, c + name.len())),
+        Some(Target::Number(n)) => on_line
+            .find(|(t, _, _)| *t == Token::Number(n))
+            .map(|(_, _, c)| (*c, word_end(text, *c))),
+        None => None,
+    };
+    found.unwrap_or_else(|| {
+        let code = text.split(';').next().unwrap_or("");
+        let start = code.len() - code.trim_start().len();
+        (start, code.trim_end().len().max(start + 1))
+    })
+}
+
+fn target_of(imm: &Imm, value: u16) -> Target<'_> {
+    match imm {
+        Imm::Val(_) => Target::Number(value),
+        Imm::Label(name) | Imm::Neg(name) => Target::Symbol(name),
+    }
+}
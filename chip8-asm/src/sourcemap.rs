@@ -12,6 +12,22 @@ impl SourceMap {
         self.lines.push((file.to_string(), file_line));
     }
 
+    /// Builds the map for a rewritten source, where `origins[i]` is the line
+    /// of the current expanded source that produced new line `i`.
+    pub fn remap(&self, origins: &[usize]) -> SourceMap {
+        SourceMap {
+            lines: origins
+                .iter()
+                .map(|&o| {
+                    self.lines
+                        .get(o)
+                        .cloned()
+                        .unwrap_or_else(|| ("<unknown>".to_string(), o))
+                })
+                .collect(),
+        }
+    }
+
     pub fn resolve(&self, expanded_line: usize) -> (&str, usize) {
         self.lines
             .get(expanded_line)
@@ -19,6 +35,17 @@ impl SourceMap {
             .unwrap_or(("<unknown>", expanded_line))
     }
 
+    /// Every file that contributed lines, in order of first appearance.
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = Vec::new();
+        for (f, _) in &self.lines {
+            if !files.contains(&f.as_str()) {
+                files.push(f);
+            }
+        }
+        files
+    }
+
     pub fn resolve_pos(&self, expanded_line: usize, col: usize) -> (&str, usize, usize) {
         self.lines
             .get(expanded_line)
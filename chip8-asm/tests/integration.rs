@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
-use chip8_asm::{assemble, assemble_with, AssemblyOptions};
+use chip8_asm::{analyze, analyze_with, assemble, assemble_with, debuginfo, encoder, format, lint, semantic, AssemblyOptions};
 
 fn asm(src: &str) -> Vec<u8> {
     assemble(src).unwrap().bytes
@@ -227,3 +227,603 @@ A
         "expected recursion error, got: {msg}"
     );
 }
+
+// ── Test 9: Repetition and loops ────────────────────────────────────────
+
+#[test]
+fn test_rept_basic() {
+    let src = r#"
+.rept 3
+CLS
+.endr
+"#;
+    let bytes = asm(src);
+    assert_eq!(bytes, vec![0x00, 0xE0, 0x00, 0xE0, 0x00, 0xE0]);
+}
+
+#[test]
+fn test_for_variable_in_expressions() {
+    let src = r#"
+.for i = 1 to 3
+.byte i * 8 + 1
+.endfor
+"#;
+    let bytes = asm(src);
+    assert_eq!(bytes, vec![9, 17, 25]);
+}
+
+#[test]
+fn test_nested_loops() {
+    let src = r#"
+.for row = 0 to 1
+.for col = 0 to 2
+.byte row * 16 + col
+.endfor
+.endfor
+"#;
+    let bytes = asm(src);
+    assert_eq!(bytes, vec![0x00, 0x01, 0x02, 0x10, 0x11, 0x12]);
+}
+
+#[test]
+fn test_loop_local_labels() {
+    let src = r#"
+.rept 2
+%%wait:
+SE V0, 0
+JP %%wait
+.endr
+"#;
+    let bytes = asm(src);
+    assert_eq!(bytes.len(), 8);
+    let jp1_addr = ((bytes[2] as u16) << 8 | bytes[3] as u16) & 0x0FFF;
+    let jp2_addr = ((bytes[6] as u16) << 8 | bytes[7] as u16) & 0x0FFF;
+    assert_eq!(jp1_addr, 0x200);
+    assert_eq!(jp2_addr, 0x204);
+}
+
+#[test]
+fn test_loop_inside_macro() {
+    let src = r#"
+macro ramp n {
+.for i = 1 to n
+.byte i * 2
+.endfor
+}
+
+ramp 3
+"#;
+    let bytes = asm(src);
+    assert_eq!(bytes, vec![2, 4, 6]);
+}
+
+#[test]
+fn test_unclosed_loop_detected() {
+    let errs = must_err("CLS\n.rept 2\nCLS\n");
+    let e = errs.first().unwrap();
+    assert!(e.message.contains(".rept"), "got: {}", e);
+    assert_eq!(e.line, 1);
+}
+
+#[test]
+fn test_loop_error_maps_to_source_line() {
+    let src = r#"macro nop {
+CLS
+}
+.rept 2
+nop
+BOGUS V0
+.endr
+"#;
+    let errs = must_err(src);
+    assert!(errs.iter().all(|e| e.line == 5), "got: {:?}", errs);
+}
+
+#[test]
+fn test_loop_listing_shows_expansion() {
+    let src = r#"
+.for i = 0 to 2
+LD V0, i
+.endfor
+"#;
+    let listing = assemble(src).unwrap().listing;
+    assert_eq!(listing.len(), 3);
+    assert!(listing[0].contains("LD V0, 0x00"), "got: {}", listing[0]);
+    assert!(listing[2].contains("LD V0, 0x02"), "got: {}", listing[2]);
+}
+
+// ── Test 10: Binary and sprite inclusion ────────────────────────────────
+
+#[test]
+fn test_incbin_whole_file() {
+    let bytes = asm_with(
+        "CLS\ndata:\n.incbin \"font.bin\"\nLD I, data\n",
+        HashMap::from([("font.bin", "ABC")]),
+    );
+    assert_eq!(bytes, vec![0x00, 0xE0, 0x41, 0x42, 0x43, 0xA2, 0x02]);
+}
+
+#[test]
+fn test_incbin_offset_and_length() {
+    let bytes = asm_with(
+        "gfx: .incbin \"tiles.bin\", 2, 3\nJP gfx\n",
+        HashMap::from([("tiles.bin", "abcdefg")]),
+    );
+    assert_eq!(bytes, vec![b'c', b'd', b'e', 0x12, 0x00]);
+}
+
+#[test]
+fn test_incbin_out_of_range() {
+    let errs = must_err_with(
+        "CLS\n.incbin \"tiles.bin\", 4, 8\n",
+        HashMap::from([("tiles.bin", "abcdefg")]),
+    );
+    let e = errs.first().unwrap();
+    assert!(e.message.contains("exceed"), "got: {}", e);
+    assert_eq!(e.line, 1);
+}
+
+#[test]
+fn test_sprite_rows() {
+    let src = r##"
+.sprite "..####..",
+        ".#....#.",
+        "#.#..#.#"
+.sprite "XXXXXXXX"
+"##;
+    let bytes = asm(src);
+    assert_eq!(bytes, vec![0x3C, 0x42, 0xA5, 0xFF]);
+}
+
+#[test]
+fn test_sprite_wide_rows() {
+    let bytes = asm(".sprite \"##............##\"\n");
+    assert_eq!(bytes, vec![0xC0, 0x03]);
+}
+
+#[test]
+fn test_sprite_bad_width() {
+    let errs = must_err(".sprite \"..##\"\n");
+    assert!(errs[0].message.contains("8 or 16"), "got: {}", errs[0]);
+}
+
+// ── Test 11: Image import ───────────────────────────────────────────────
+
+#[test]
+fn test_image_pbm_single_sprite() {
+    let pbm = "P1\n# ball\n8 3\n0 0 1 1 1 1 0 0\n0 1 0 0 0 0 1 0\n1 1 1 1 1 1 1 1\n";
+    let bytes = asm_with(
+        "ball: .image \"ball.pbm\"\nLD I, ball\n",
+        HashMap::from([("ball.pbm", pbm)]),
+    );
+    assert_eq!(bytes, vec![0x3C, 0x42, 0xFF, 0xA2, 0x00]);
+}
+
+#[test]
+fn test_image_sliced_into_tiles() {
+    // Two 8x2 tiles side by side; tiles are emitted left to right.
+    let pbm = "P1 16 2\n1111111100000000\n1000000100000001\n";
+    let bytes = asm_with(
+        ".image \"tiles.pbm\", 8, 2\n",
+        HashMap::from([("tiles.pbm", pbm)]),
+    );
+    assert_eq!(bytes, vec![0xFF, 0x81, 0x00, 0x01]);
+}
+
+#[test]
+fn test_image_pgm_black_and_white() {
+    let pgm = "P2\n8 1\n255\n0 255 0 255 0 255 0 255\n";
+    let bytes = asm_with(".image \"a.pgm\"\n", HashMap::from([("a.pgm", pgm)]));
+    assert_eq!(bytes, vec![0xAA]);
+}
+
+#[test]
+fn test_image_rejects_grey_pixels() {
+    let pgm = "P2\n8 1\n255\n0 255 0 128 0 255 0 255\n";
+    let errs = must_err_with(
+        "CLS\n.image \"a.pgm\"\n",
+        HashMap::from([("a.pgm", pgm)]),
+    );
+    let e = errs.first().unwrap();
+    assert!(e.message.contains("non-binary"), "got: {}", e);
+    assert_eq!(e.line, 1);
+}
+
+#[test]
+fn test_image_width_not_multiple_of_8() {
+    let pbm = "P1\n12 1\n1 1 1 1 1 1 1 1 1 1 1 1\n";
+    let errs = must_err_with(
+        ".image \"a.pbm\", 12, 1\n",
+        HashMap::from([("a.pbm", pbm)]),
+    );
+    assert!(errs[0].message.contains("multiple of 8"), "got: {}", errs[0]);
+}
+
+#[test]
+fn test_image_rejects_oversized_header() {
+    // Header dimensions whose product overflows, or that can't fit in 4 KiB
+    for pbm in ["P4\n18446744073709551615 2\n", "P1\n65536 65536\n0\n"] {
+        let errs = must_err_with(".image \"a.pbm\"\n", HashMap::from([("a.pbm", pbm)]));
+        assert!(errs[0].message.contains("too large"), "got: {}", errs[0]);
+    }
+}
+
+// ── Test 12: Pseudo-instructions ────────────────────────────────────────
+
+#[test]
+fn test_pseudo_inc_dec_sub() {
+    let bytes = asm(".const STEP 3\nINC V1\nDEC V2\nSUB V3, #5\nSUB V4, STEP\n");
+    assert_eq!(
+        bytes,
+        vec![0x71, 0x01, 0x72, 0xFF, 0x73, 0xFB, 0x74, 0xFD]
+    );
+}
+
+#[test]
+fn test_pseudo_not_neg() {
+    let bytes = asm("NOT V2\nNEG V3\n");
+    assert_eq!(
+        bytes,
+        vec![0x6F, 0xFF, 0x82, 0xF3, 0x6F, 0x00, 0x83, 0xF7]
+    );
+    let errs = must_err("NOT VF\n");
+    assert!(errs[0].message.contains("scratch"), "got: {}", errs[0]);
+}
+
+#[test]
+fn test_pseudo_conditional_jumps() {
+    let bytes = asm("top:\nJEQ V0, 7, top\nJNE V1, V2, top\n");
+    assert_eq!(
+        bytes,
+        vec![0x40, 0x07, 0x12, 0x00, 0x51, 0x20, 0x12, 0x00]
+    );
+}
+
+#[test]
+fn test_pseudo_memory_access() {
+    let bytes = asm("LD V0..V2, [table]\nLD [table], V0..V1\nLD V0, [table]\ntable:\n.byte 1, 2, 3\n");
+    assert_eq!(
+        bytes,
+        vec![0xA2, 0x0C, 0xF2, 0x65, 0xA2, 0x0C, 0xF1, 0x55, 0xA2, 0x0C, 0xF0, 0x65, 1, 2, 3]
+    );
+    // Fx65/Fx55 move V0..Vx, so a lone register other than V0 would hide that
+    assert!(assemble("LD V2, [table]\ntable:\n.byte 1\n").is_err());
+    assert!(assemble("LD V1..V2, [table]\ntable:\n.byte 1\n").is_err());
+}
+
+#[test]
+fn test_pseudo_call_with_save() {
+    let bytes = asm("CALL sub, V3, [save]\nsub:\nRET\nsave:\n.space 4\n");
+    assert_eq!(
+        bytes,
+        vec![
+            0xA2, 0x0C, 0xF3, 0x55, 0x22, 0x0A, 0xA2, 0x0C, 0xF3, 0x65, 0x00, 0xEE, 0, 0, 0, 0
+        ]
+    );
+}
+
+#[test]
+fn test_raw_forms_still_win() {
+    // Register operands keep the original single-instruction encodings.
+    let bytes = asm("SUB V0, V1\nLD V3, [I]\nCALL 0x300\n");
+    assert_eq!(bytes, vec![0x80, 0x15, 0xF3, 0x65, 0x23, 0x00]);
+}
+
+#[test]
+fn test_pseudo_listing_shows_expansion() {
+    let listing = assemble("NOT V2\nCLS\n").unwrap().listing;
+    assert!(listing[0].contains("NOT V2"), "got: {}", listing[0]);
+    assert!(listing[1].contains("6FFF") && listing[1].contains("LD VF, 0xFF"), "got: {}", listing[1]);
+    assert!(listing[2].contains("0202") && listing[2].contains("XOR V2, VF"), "got: {}", listing[2]);
+    assert!(listing[3].contains("0204"), "got: {}", listing[3]);
+}
+
+// ── Test 13: Structured control flow ────────────────────────────────────
+
+#[test]
+fn test_if_without_else() {
+    let bytes = asm("if V0 == 3 {\n    CLS\n}\nRET\n");
+    // SE V0, 3 / JP else / CLS / else: RET
+    assert_eq!(bytes, vec![0x30, 0x03, 0x12, 0x06, 0x00, 0xE0, 0x00, 0xEE]);
+}
+
+#[test]
+fn test_if_else() {
+    let src = "if V1 != V2 {\n    LD V0, 1\n} else {\n    LD V0, 2\n}\nRET\n";
+    let bytes = asm(src);
+    assert_eq!(
+        bytes,
+        vec![
+            0x91, 0x20, 0x12, 0x08, // SNE V1, V2 / JP else
+            0x60, 0x01, 0x12, 0x0A, // LD V0, 1 / JP end
+            0x60, 0x02, // else: LD V0, 2
+            0x00, 0xEE, // end: RET
+        ]
+    );
+}
+
+#[test]
+fn test_while_loop() {
+    let bytes = asm("while V0 != 10 {\n    INC V0\n}\n");
+    assert_eq!(
+        bytes,
+        vec![0x40, 0x0A, 0x12, 0x08, 0x70, 0x01, 0x12, 0x00]
+    );
+}
+
+#[test]
+fn test_loop_break_continue_nested() {
+    let src = "\
+loop {
+    ADD V0, 1
+    if V0 == 5 {
+        break
+    }
+    continue
+}
+CLS
+";
+    let bytes = asm(src);
+    assert_eq!(
+        bytes,
+        vec![
+            0x70, 0x01, // top: ADD V0, 1
+            0x30, 0x05, 0x12, 0x08, // SE V0, 5 / JP else
+            0x12, 0x0C, // break -> JP end
+            0x12, 0x00, // else: continue -> JP top
+            0x12, 0x00, // JP top
+            0x00, 0xE0, // end: CLS
+        ]
+    );
+}
+
+#[test]
+fn test_control_flow_keywords_any_case() {
+    let lower = "loop {\n    if V0 == 5 {\n        break\n    } else {\n        continue\n    }\n}\nwhile V1 != 2 {\n    ADD V1, 1\n}\n";
+    let upper = lower
+        .replace("loop", "LOOP")
+        .replace("if", "If")
+        .replace("break", "BREAK")
+        .replace("else", "ELSE")
+        .replace("continue", "Continue")
+        .replace("while", "WHILE");
+    assert_eq!(asm(&upper), asm(lower));
+    assert!(must_err("CLS\nBREAK\n")[0].message.contains("'break' outside of a loop"));
+}
+
+#[test]
+fn test_control_flow_errors_map_to_lines() {
+    let errs = must_err("CLS\nbreak\n");
+    assert!(errs[0].message.contains("outside of a loop"), "got: {}", errs[0]);
+    assert_eq!(errs[0].line, 1);
+
+    let errs = must_err("CLS\nRET\n}\n");
+    assert!(errs[0].message.contains("unmatched"), "got: {}", errs[0]);
+    assert_eq!(errs[0].line, 2);
+
+    let errs = must_err("CLS\n\nwhile V0 == 1 {\n    CLS\n");
+    assert!(errs[0].message.contains("never closed"), "got: {}", errs[0]);
+    assert_eq!(errs[0].line, 2);
+}
+
+#[test]
+fn test_control_flow_inside_macro() {
+    let src = "\
+macro clamp reg, max {
+    if reg == max {
+        LD reg, 0
+    }
+}
+clamp V1, 9
+clamp V2, 9
+";
+    let bytes = asm(src);
+    assert_eq!(
+        bytes,
+        vec![
+            0x31, 0x09, 0x12, 0x06, 0x61, 0x00, // first expansion
+            0x32, 0x09, 0x12, 0x0C, 0x62, 0x00, // second gets its own labels
+        ]
+    );
+}
+
+// ── Test 14: Semantic checks ────────────────────────────────────────────
+
+fn check(src: &str) -> Vec<semantic::Diagnostic> {
+    semantic::check(&analyze(src).unwrap())
+}
+
+#[test]
+fn test_semantic_undefined_symbol_position() {
+    let diags = check("start:\n    LD I, sprite\n    JP start\n");
+    assert_eq!(diags.len(), 1);
+    let d = &diags[0];
+    assert_eq!(d.severity, semantic::Severity::Error);
+    assert!(d.message.contains("undefined symbol 'sprite'"), "got: {}", d.message);
+    assert_eq!((d.line, d.col, d.end_col), (1, 10, 16));
+
+    // assemble reports the same position instead of a bare message
+    let errs = must_err("start:\n    LD I, sprite\n");
+    assert_eq!((errs[0].line, errs[0].col), (1, 10));
+}
+
+#[test]
+fn test_semantic_value_overflow() {
+    let diags = check(".const BIG 300\nLD V0, BIG\nLD V1, 256\n");
+    assert_eq!(diags.len(), 2, "got: {:?}", diags);
+    assert!(diags[0].message.contains("exceeds max 255"));
+    assert_eq!((diags[0].line, diags[0].col), (1, 7));
+    assert_eq!((diags[1].line, diags[1].col, diags[1].end_col), (2, 7, 10));
+}
+
+#[test]
+fn test_semantic_drw_height() {
+    let diags = check(".const H 16\nDRW V0, V1, H\nDRW V0, V1, 0\n");
+    assert_eq!(diags.len(), 2, "got: {:?}", diags);
+    assert!(diags[0].message.contains("DRW height 16"));
+    assert_eq!(diags[0].severity, semantic::Severity::Error);
+    assert_eq!(diags[1].severity, semantic::Severity::Warning);
+    assert_eq!(diags[1].line, 2);
+    // warnings alone don't fail the build
+    assert_eq!(asm("DRW V0, V1, 0\n"), vec![0xD0, 0x10]);
+}
+
+#[test]
+fn test_semantic_org_overlap() {
+    let src = "CLS\nCLS\nCLS\n.org 0x202\n.byte 1\n.org 0x300\nRET\n";
+    let diags = check(src);
+    assert_eq!(diags.len(), 1, "got: {:?}", diags);
+    assert!(diags[0].message.contains("overlaps"), "got: {}", diags[0].message);
+    assert!(diags[0].message.contains("line 2"), "got: {}", diags[0].message);
+    assert_eq!(diags[0].line, 4);
+}
+
+// ── Test 15: Lint ───────────────────────────────────────────────────────
+
+fn lints(src: &str) -> Vec<(&'static str, usize)> {
+    lint::lint(&analyze(src).unwrap())
+        .into_iter()
+        .map(|d| (d.code.unwrap(), d.line))
+        .collect()
+}
+
+#[test]
+fn test_lint_clean_program() {
+    let src = "start:\n    CALL draw\n    JP start\ndraw:\n    LD I, sprite\n    DRW V0, V1, 1\n    RET\nsprite:\n    .byte 0xFF\n";
+    assert_eq!(lints(src), vec![]);
+}
+
+#[test]
+fn test_lint_unreachable() {
+    let src = "start:\n    JP start\n    CLS\n";
+    assert_eq!(lints(src), vec![("unreachable", 2)]);
+    // a skip makes the JP conditional
+    assert_eq!(lints("start:\n    SE V0, 1\n    JP start\n    CLS\n    JP start\n"), vec![]);
+}
+
+#[test]
+fn test_lint_ret_outside_routine() {
+    assert_eq!(lints("CLS\nRET\n"), vec![("ret-outside-routine", 1)]);
+    // labels only reached by JP belong to the routine that jumped there
+    let src = "start:\n    CALL sub\n    JP start\nsub:\n    SE V0, 0\n    JP done\n    RET\ndone:\n    RET\n";
+    assert_eq!(lints(src), vec![]);
+}
+
+#[test]
+fn test_lint_skip_multiword() {
+    let src = "start:\n    SE V0, 1\n    NOT V1\n    JP start\n";
+    assert_eq!(lints(src), vec![("skip-multiword", 2)]);
+}
+
+#[test]
+fn test_lint_unused_symbols() {
+    let src = ".const SPEED 2\nstart:\n    JP start\nspare:\n    .byte 1\n";
+    assert_eq!(lints(src), vec![("unused-const", 0), ("unused-label", 3)]);
+}
+
+#[test]
+fn test_lint_vf_clobber() {
+    let src = "start:\n    LD VF, 1\n    ADD V0, V1\n    JP start\n";
+    assert_eq!(lints(src), vec![("vf-clobber", 1)]);
+    // reading VF first is fine
+    assert_eq!(lints("start:\n    LD VF, 1\n    ADD V0, VF\n    JP start\n"), vec![]);
+}
+
+#[test]
+fn test_lint_i_unset() {
+    assert_eq!(lints("start:\n    LD V3, [I]\n    JP start\n"), vec![("i-unset", 1)]);
+    assert_eq!(lints("start:\n    LD F, V0\n    LD B, V0\n    JP start\n"), vec![]);
+}
+
+#[test]
+fn test_lint_stack_depth() {
+    let mut src = String::from("start:\n    CALL level0\n    JP start\n");
+    for i in 0..17 {
+        src.push_str(&format!("level{}:\n    CALL level{}\n    RET\n", i, i + 1));
+    }
+    src.push_str("level17:\n    RET\n");
+    assert_eq!(lints(&src), vec![("stack-depth", 1)]);
+
+    let recursive = "start:\n    CALL sub\n    JP start\nsub:\n    CALL sub\n    RET\n";
+    assert_eq!(lints(recursive), vec![("stack-depth", 4)]);
+}
+
+#[test]
+fn test_lint_allow() {
+    let src = "start:\n    JP start\n    CLS ; lint:allow(unreachable)\n";
+    assert_eq!(lints(src), vec![]);
+    let src = "start:\n    JP start\n; lint:allow\n    CLS\n";
+    assert_eq!(lints(src), vec![]);
+    // other IDs don't silence it
+    let src = "start:\n    JP start\n    CLS ; lint:allow(vf-clobber)\n";
+    assert_eq!(lints(src), vec![("unreachable", 2)]);
+}
+
+// ── Test 16: Formatter ──────────────────────────────────────────────────
+
+#[test]
+fn test_format_instructions() {
+    let src = "start:\n  ld v0,#$0F ; load\n  drw v0 , v1, 5   ; draw it\n    jp start\n";
+    let expected = "start:\n    LD  V0, #$0F   ; load\n    DRW V0, V1, 5  ; draw it\n    JP  start\n";
+    assert_eq!(format::format(src), expected);
+    // a label with code keeps both on its line
+    assert_eq!(format::format("a: cls\nlonger:   ret"), "a:  CLS\nlonger: RET");
+    // register ranges stay glued together
+    assert_eq!(format::format("ld v0..v2, [table]"), "    LD V0..V2, [table]");
+}
+
+#[test]
+fn test_format_blocks_and_declarations() {
+    let src = "   .const SPEED = 2\nmacro bump r {\nadd r, SPEED\n}\nstruct P {\nx byte\nscore word\n}\nif v0 == 1 {\ncls\n}\n";
+    let expected = ".const SPEED = 2\nmacro bump r {\n    ADD r, SPEED\n}\nstruct P {\n    x     byte\n    score word\n}\n    if V0 == 1 {\n        CLS\n    }\n";
+    assert_eq!(format::format(src), expected);
+    // Flow keywords keep their case but are still recognised
+    assert_eq!(format::format("IF v0 == 1 {\ncls\n} ELSE {\nret\n}\n"), "    IF V0 == 1 {\n        CLS\n    } ELSE {\n        RET\n    }\n");
+}
+
+#[test]
+fn test_format_preserves_comments_and_meaning() {
+    let src = "; header\n\n    ; section\nmacro twice r {\n    ADD r, 1\n    ADD r, 1\n}\nloop:\n    twice V3\n    .rept 2\n    %%x: SHL V0\n    .endr\n    JP loop\n";
+    let formatted = format::format(src);
+    assert!(formatted.starts_with("; header\n\n    ; section\n"), "got:\n{}", formatted);
+    assert!(formatted.contains("        %%x: SHL V0\n"), "got:\n{}", formatted);
+    assert_eq!(formatted.lines().count(), src.lines().count());
+    assert_eq!(asm(&formatted), asm(src));
+    // formatting is idempotent
+    assert_eq!(format::format(&formatted), formatted);
+}
+
+// ── Test 17: Debug info ─────────────────────────────────────────────────
+
+#[test]
+fn test_line_table() {
+    let src = "macro twice r {\n    ADD r, 1\n    ADD r, 1\n}\nstart:\n    CLS\n    twice V0\n    include \"lib.asm\"\n    JP start\n";
+    let files = HashMap::from([(PathBuf::from("lib.asm"), "sub:\n    RET\n".to_string())]);
+    let analysis = analyze_with(src, &AssemblyOptions { files, ..Default::default() }).unwrap();
+    let table: Vec<(String, usize, u16, u16)> = debuginfo::line_table(&analysis)
+        .into_iter()
+        .map(|e| (e.file, e.line, e.addr, e.size))
+        .collect();
+    assert_eq!(
+        table,
+        vec![
+            ("<root>".to_string(), 5, 0x200, 2),
+            ("<root>".to_string(), 6, 0x202, 4),
+            ("lib.asm".to_string(), 1, 0x206, 2),
+            ("<root>".to_string(), 8, 0x208, 2),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_roundtrip() {
+    let bytes = asm("CLS\nRET\nJP 0x234\nSE V1, 5\nLD V2, V3\nSHR V4\nDRW V0, V1, 5\nSKNP VA\nLD [I], V7\nLD B, V2\n");
+    for pair in bytes.chunks(2) {
+        let op = u16::from_be_bytes([pair[0], pair[1]]);
+        let instr = encoder::Instr::decode(op).unwrap();
+        assert_eq!(instr.encode(), [pair[0], pair[1]]);
+    }
+    assert_eq!(encoder::Instr::decode(0xF0FF), None);
+    assert_eq!(encoder::Instr::decode(0x5121), None);
+    assert_eq!(encoder::Instr::decode(0xD125).unwrap().disassemble(), "DRW V1, V2, 5");
+}
@@ -0,0 +1,754 @@
+use std::collections::{HashMap, HashSet};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+
+use chip8_asm::encoder::Instr;
+use serde_json::{json, Value};
+
+use crate::client::Client;
+use crate::program::{spawn_emulator, DebugInfo};
+use crate::protocol::{base64_decode, base64_encode, Output};
+
+const DEFAULT_PORT: u16 = 9876;
+const MEMORY_SIZE: i64 = 4096;
+/// CHIP-8 has one thread of execution and one set of registers.
+const THREAD_ID: u64 = 1;
+const REGISTERS_REF: u64 = 1;
+
+/// Shared with the thread that turns emulator events into `stopped` events.
+#[derive(Default)]
+struct StopState {
+    /// Breakpoints currently set in the emulator on the editor's behalf.
+    breakpoints: HashSet<u16>,
+    /// Temporary breakpoint of a `next` or `stepOut`, dropped at the next stop.
+    step_target: Option<u16>,
+    /// Message of the last `fault`, shown with the stop it causes.
+    fault: Option<String>,
+}
+
+pub struct Adapter {
+    out: Output,
+    client: Option<Arc<Client>>,
+    /// Emulator started by `launch`; stopped on disconnect.
+    emulator: Option<Child>,
+    /// ROM assembled for `launch`; removed on disconnect.
+    temp_rom: Option<PathBuf>,
+    debug: DebugInfo,
+    lines_start_at1: bool,
+    columns_start_at1: bool,
+    stop_on_entry: bool,
+    stop: Arc<Mutex<StopState>>,
+    source_breakpoints: HashMap<PathBuf, Vec<u16>>,
+    instruction_breakpoints: Vec<u16>,
+}
+
+impl Adapter {
+    pub fn new(out: Output) -> Self {
+        Adapter {
+            out,
+            client: None,
+            emulator: None,
+            temp_rom: None,
+            debug: DebugInfo::default(),
+            lines_start_at1: true,
+            columns_start_at1: true,
+            stop_on_entry: false,
+            stop: Default::default(),
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn handle(&mut self, req: &Value) -> Result<Value, String> {
+        let args = &req["arguments"];
+        match req["command"].as_str().unwrap_or("") {
+            "initialize" => Ok(self.initialize(args)),
+            "launch" => self.launch(args),
+            "attach" => self.attach(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({"breakpoints": []})),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "CHIP-8"}]})),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({"scopes": [{
+                "name": "Registers",
+                "presentationHint": "registers",
+                "variablesReference": REGISTERS_REF,
+                "expensive": false,
+            }]})),
+            "variables" => self.variables(args),
+            "continue" => {
+                self.request(json!({"cmd": "continue"}))?;
+                Ok(json!({"allThreadsContinued": true}))
+            }
+            "next" => self.step_over(),
+            "stepIn" => self.request(json!({"cmd": "step"})).map(|_| Value::Null),
+            "stepOut" => self.step_out(),
+            "pause" => self.request(json!({"cmd": "pause"})).map(|_| Value::Null),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disassemble" => self.disassemble(args),
+            "disconnect" | "terminate" => self.disconnect(),
+            other => Err(format!("unsupported request: {other}")),
+        }
+    }
+
+    /// Events that must follow the response to `command`.
+    pub fn after_response(&self, command: &str) {
+        match command {
+            "launch" | "attach" => self.out.event("initialized", json!({})),
+            "configurationDone" if self.emulator.is_some() && self.stop_on_entry => {
+                self.out.event("stopped", json!({"reason": "entry", "threadId": THREAD_ID, "allThreadsStopped": true}));
+            }
+            _ => {}
+        }
+    }
+
+    fn initialize(&mut self, args: &Value) -> Value {
+        self.lines_start_at1 = args["linesStartAt1"].as_bool().unwrap_or(true);
+        self.columns_start_at1 = args["columnsStartAt1"].as_bool().unwrap_or(true);
+        json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsReadMemoryRequest": true,
+            "supportsWriteMemoryRequest": true,
+            "supportsDisassembleRequest": true,
+            "supportsInstructionBreakpoints": true,
+            "supportsTerminateRequest": true,
+        })
+    }
+
+    /// Assembles `program` if it is a source file, starts a paused emulator
+    /// on it and connects.
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = PathBuf::from(args["program"].as_str().ok_or("launch needs a \"program\"")?);
+        let port = port(args)?;
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return Err(format!("port {port} is already in use; set \"port\" or attach to that emulator"));
+        }
+        let rom = if is_rom(&program) {
+            program
+        } else {
+            let (bytes, debug) = DebugInfo::assemble(&program)?;
+            self.debug = debug;
+            let rom = std::env::temp_dir().join(format!("chip8-dap-{}.ch8", std::process::id()));
+            std::fs::write(&rom, bytes).map_err(|e| format!("writing {}: {e}", rom.display()))?;
+            self.temp_rom = Some(rom.clone());
+            rom
+        };
+        self.emulator = Some(spawn_emulator(&rom, port, args)?);
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.connect(port)?;
+        Ok(Value::Null)
+    }
+
+    /// Connects to a running emulator. `program`, if given, is the source it
+    /// was assembled from, for source breakpoints and locations.
+    fn attach(&mut self, args: &Value) -> Result<Value, String> {
+        if let Some(program) = args["program"].as_str().map(Path::new).filter(|p| !is_rom(p)) {
+            self.debug = DebugInfo::assemble(program)?.1;
+        }
+        self.connect(port(args)?)?;
+        Ok(Value::Null)
+    }
+
+    fn connect(&mut self, port: u16) -> Result<(), String> {
+        let (out, stop) = (self.out.clone(), self.stop.clone());
+        let closed = self.out.clone();
+        let client = Client::connect(
+            port,
+            move |client, event| on_event(&out, &stop, client, event),
+            move || closed.event("terminated", json!({})),
+        )
+        .map_err(|e| format!("cannot connect to the emulator on port {port}: {e}"))?;
+        self.client = Some(client);
+        self.sync_breakpoints();
+        Ok(())
+    }
+
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        // A launched emulator starts paused so breakpoints are in place first
+        if self.emulator.is_some() && !self.stop_on_entry {
+            self.request(json!({"cmd": "continue"}))?;
+        }
+        Ok(Value::Null)
+    }
+
+    fn disconnect(&mut self) -> Result<Value, String> {
+        if let Some(mut emulator) = self.emulator.take() {
+            let _ = emulator.kill();
+            let _ = emulator.wait();
+        } else if let Some(client) = self.client.clone() {
+            // Leave an attached emulator running, without our breakpoints
+            self.source_breakpoints.clear();
+            self.instruction_breakpoints.clear();
+            self.sync_breakpoints();
+            client.notify(json!({"cmd": "continue"}));
+        }
+        if let Some(rom) = self.temp_rom.take() {
+            let _ = std::fs::remove_file(rom);
+        }
+        self.client = None;
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = PathBuf::from(args["source"]["path"].as_str().ok_or("source has no path")?);
+        let base = self.lines_start_at1 as usize;
+        let mut addrs = Vec::new();
+        let mut result = Vec::new();
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = bp["line"].as_u64().unwrap_or(0) as usize;
+            match self.debug.breakpoint_line(&path, line.saturating_sub(base)) {
+                Some(l) => {
+                    addrs.push(l.addr);
+                    result.push(json!({
+                        "verified": true,
+                        "line": l.line + base,
+                        "instructionReference": hex(l.addr),
+                    }));
+                }
+                None => result.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at or after this line",
+                })),
+            }
+        }
+        self.source_breakpoints.insert(path, addrs);
+        self.sync_breakpoints();
+        Ok(json!({"breakpoints": result}))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let mut result = Vec::new();
+        self.instruction_breakpoints.clear();
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let addr = parse_ref(&bp["instructionReference"])? + bp["offset"].as_i64().unwrap_or(0);
+            if (0..MEMORY_SIZE).contains(&addr) {
+                self.instruction_breakpoints.push(addr as u16);
+                result.push(json!({"verified": true, "instructionReference": hex(addr as u16)}));
+            } else {
+                result.push(json!({"verified": false, "message": "Address outside memory"}));
+            }
+        }
+        self.sync_breakpoints();
+        Ok(json!({"breakpoints": result}))
+    }
+
+    /// Makes the emulator's breakpoints match the editor's.
+    fn sync_breakpoints(&mut self) {
+        let Some(client) = self.client.clone() else { return };
+        let wanted: HashSet<u16> = self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.instruction_breakpoints)
+            .copied()
+            .collect();
+        let mut stop = self.stop.lock().unwrap();
+        for &addr in stop.breakpoints.difference(&wanted) {
+            if stop.step_target != Some(addr) {
+                client.notify(json!({"cmd": "clear_bp", "a": addr}));
+            }
+        }
+        for &addr in wanted.difference(&stop.breakpoints) {
+            client.notify(json!({"cmd": "set_bp", "a": addr}));
+        }
+        stop.breakpoints = wanted;
+    }
+
+    /// The current instruction, then one frame per return address on the
+    /// stack, showing the `CALL` it returns to.
+    fn stack_trace(&self) -> Result<Value, String> {
+        let regs = self.request(json!({"cmd": "get_registers"}))?;
+        let pc = regs["pc"].as_u64().unwrap_or(0) as u16;
+        let stack: Vec<u16> = serde_json::from_value(regs["stack"].clone()).unwrap_or_default();
+        let frames: Vec<Value> = std::iter::once(pc)
+            .chain(stack.iter().rev().map(|ret| ret.wrapping_sub(2)))
+            .enumerate()
+            .map(|(id, addr)| self.frame(id, addr))
+            .collect();
+        Ok(json!({"totalFrames": frames.len(), "stackFrames": frames}))
+    }
+
+    fn frame(&self, id: usize, addr: u16) -> Value {
+        let name = match self.debug.routine_at(addr) {
+            Some(routine) => format!("{routine} ({})", hex(addr)),
+            None => hex(addr),
+        };
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": hex(addr),
+        });
+        if let Some(line) = self.debug.line_at(addr) {
+            frame["source"] = source(&line.path);
+            frame["line"] = (line.line + self.lines_start_at1 as usize).into();
+            frame["column"] = (self.columns_start_at1 as usize).into();
+        }
+        frame
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        if args["variablesReference"].as_u64() != Some(REGISTERS_REF) {
+            return Ok(json!({"variables": []}));
+        }
+        let regs = self.request(json!({"cmd": "get_registers"}))?;
+        let v: Vec<u8> = serde_json::from_value(regs["v_regs"].clone()).unwrap_or_default();
+        let num = |key: &str| regs[key].as_u64().unwrap_or(0);
+        let mut vars: Vec<Value> = v
+            .iter()
+            .enumerate()
+            .map(|(i, val)| variable(&format!("V{i:X}"), format!("0x{val:02X} ({val})"), None))
+            .collect();
+        let (i, pc) = (num("i") as u16, num("pc") as u16);
+        vars.push(variable("I", hex(i), Some(i)));
+        vars.push(variable("PC", hex(pc), Some(pc)));
+        vars.push(variable("DT", num("delay").to_string(), None));
+        vars.push(variable("ST", num("sound").to_string(), None));
+        Ok(json!({"variables": vars}))
+    }
+
+    /// Steps over a `CALL` by running to the instruction after it.
+    fn step_over(&self) -> Result<Value, String> {
+        let pc = self.request(json!({"cmd": "get_registers"}))?["pc"].as_u64().unwrap_or(0) as u16;
+        let code = self.memory(pc as i64, pc as i64 + 2)?;
+        if code.first().is_some_and(|b| b >> 4 == 0x2) {
+            self.run_to(pc + 2)
+        } else {
+            self.request(json!({"cmd": "step"})).map(|_| Value::Null)
+        }
+    }
+
+    /// Runs until the current routine returns to its caller.
+    fn step_out(&self) -> Result<Value, String> {
+        let regs = self.request(json!({"cmd": "get_registers"}))?;
+        let stack: Vec<u16> = serde_json::from_value(regs["stack"].clone()).unwrap_or_default();
+        match stack.last() {
+            Some(&ret) => self.run_to(ret),
+            None => self.request(json!({"cmd": "step"})).map(|_| Value::Null),
+        }
+    }
+
+    fn run_to(&self, target: u16) -> Result<Value, String> {
+        let client = self.client.as_ref().ok_or("not connected to an emulator")?;
+        {
+            // The event thread takes this lock, so no waiting on replies here
+            let mut stop = self.stop.lock().unwrap();
+            if !stop.breakpoints.contains(&target) {
+                client.notify(json!({"cmd": "set_bp", "a": target}));
+            }
+            stop.step_target = Some(target);
+        }
+        self.request(json!({"cmd": "continue"})).map(|_| Value::Null)
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let start = parse_ref(&args["memoryReference"])? + args["offset"].as_i64().unwrap_or(0);
+        let count = args["count"].as_i64().unwrap_or(0);
+        let (from, to) = (start.clamp(0, MEMORY_SIZE), (start + count).clamp(0, MEMORY_SIZE));
+        let data = if from < to { self.memory(from, to)? } else { Vec::new() };
+        Ok(json!({
+            "address": hex(from as u16),
+            "data": base64_encode(&data),
+            "unreadableBytes": count - data.len() as i64,
+        }))
+    }
+
+    fn write_memory(&self, args: &Value) -> Result<Value, String> {
+        let start = parse_ref(&args["memoryReference"])? + args["offset"].as_i64().unwrap_or(0);
+        let data = base64_decode(args["data"].as_str().unwrap_or("")).ok_or("data is not valid base64")?;
+        if start < 0 || start + data.len() as i64 > MEMORY_SIZE {
+            return Err("write outside memory".to_string());
+        }
+        let written = data.len();
+        self.request(json!({"cmd": "set_memory", "a": start, "data": data}))?;
+        Ok(json!({"bytesWritten": written}))
+    }
+
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let start = parse_ref(&args["memoryReference"])?
+            + args["offset"].as_i64().unwrap_or(0)
+            + args["instructionOffset"].as_i64().unwrap_or(0) * 2;
+        let count = args["instructionCount"].as_i64().unwrap_or(0);
+        let memory = self.memory(0, MEMORY_SIZE)?;
+
+        let instructions: Vec<Value> = (0..count)
+            .map(|k| {
+                let addr = start + 2 * k;
+                if addr < 0 || addr + 1 >= MEMORY_SIZE {
+                    return json!({
+                        "address": format!("{}0x{:03X}", if addr < 0 { "-" } else { "" }, addr.abs()),
+                        "instruction": "??",
+                        "presentationHint": "invalid",
+                    });
+                }
+                let op = u16::from_be_bytes([memory[addr as usize], memory[addr as usize + 1]]);
+                let addr = addr as u16;
+                let text = Instr::decode(op).map_or_else(|| format!("DW 0x{op:04X}"), |i| i.disassemble());
+                let mut instr = json!({
+                    "address": hex(addr),
+                    "instructionBytes": format!("{op:04X}"),
+                    "instruction": text,
+                });
+                if let Some(label) = self.debug.label_exactly_at(addr) {
+                    instr["symbol"] = label.into();
+                }
+                if let Some(line) = self.debug.line_at(addr) {
+                    instr["location"] = source(&line.path);
+                    instr["line"] = (line.line + self.lines_start_at1 as usize).into();
+                }
+                instr
+            })
+            .collect();
+        Ok(json!({"instructions": instructions}))
+    }
+
+    fn memory(&self, from: i64, to: i64) -> Result<Vec<u8>, String> {
+        let resp = self.request(json!({"cmd": "get_memory", "s": from, "e": to}))?;
+        Ok(serde_json::from_value(resp["data"].clone()).unwrap_or_default())
+    }
+
+    fn request(&self, cmd: Value) -> Result<Value, String> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| "not connected to an emulator".to_string())?
+            .request(cmd)
+    }
+}
+
+impl Drop for Adapter {
+    fn drop(&mut self) {
+        let _ = self.disconnect();
+    }
+}
+
+/// Turns the emulator's `paused` events into `stopped` events.
+fn on_event(out: &Output, stop: &Mutex<StopState>, client: &Client, event: Value) {
+    let mut stop = stop.lock().unwrap();
+    match event["event"].as_str() {
+        Some("fault") => stop.fault = event["message"].as_str().map(str::to_string),
+        Some("paused") => {
+            let pc = event["pc"].as_u64().unwrap_or(0) as u16;
+            let mut reason = match event["reason"].as_str() {
+                Some("breakpoint") => "breakpoint",
+                Some("step") => "step",
+                Some("fault") => "exception",
+                _ => "pause",
+            };
+            if let Some(target) = stop.step_target.take() {
+                if !stop.breakpoints.contains(&target) {
+                    client.notify(json!({"cmd": "clear_bp", "a": target}));
+                    if pc == target {
+                        reason = "step";
+                    }
+                }
+            }
+            let mut body = json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true});
+            if reason == "exception" {
+                if let Some(message) = stop.fault.take() {
+                    body["description"] = "Fault".into();
+                    body["text"] = message.into();
+                }
+            }
+            out.event("stopped", body);
+        }
+        _ => {}
+    }
+}
+
+fn port(args: &Value) -> Result<u16, String> {
+    match args["port"].as_u64() {
+        None => Ok(DEFAULT_PORT),
+        Some(p) => u16::try_from(p).map_err(|_| format!("port {p} is above 65535")),
+    }
+}
+
+fn is_rom(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ch8"))
+}
+
+fn hex(addr: u16) -> String {
+    format!("0x{addr:03X}")
+}
+
+/// A memory reference: `0x`-prefixed hex or decimal.
+fn parse_ref(value: &Value) -> Result<i64, String> {
+    let text = value.as_str().ok_or("missing memory reference")?;
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => i64::from_str_radix(digits, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid memory reference: {text}"))
+}
+
+fn source(path: &Path) -> Value {
+    json!({
+        "name": path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default(),
+        "path": path,
+    })
+}
+
+fn variable(name: &str, value: String, memory: Option<u16>) -> Value {
+    let mut var = json!({"name": name, "value": value, "variablesReference": 0});
+    if let Some(addr) = memory {
+        var["memoryReference"] = hex(addr).into();
+    }
+    var
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::program::Line;
+    use crate::protocol::read_message;
+
+    const SOURCE: &str = "/chip8-dap-test/main.asm";
+
+    /// Everything the adapter writes to the editor.
+    #[derive(Clone, Default)]
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Sink {
+        fn messages(&self) -> Vec<Value> {
+            let data = self.0.lock().unwrap().clone();
+            let mut input = &data[..];
+            std::iter::from_fn(|| read_message(&mut input).unwrap()).collect()
+        }
+
+        fn stopped(&self) -> Value {
+            for _ in 0..200 {
+                if let Some(msg) = self.messages().into_iter().find(|m| m["event"] == "stopped") {
+                    return msg["body"].clone();
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("no stopped event");
+        }
+    }
+
+    /// Fake debug server with `memory` and the PC at `pc`. It logs every
+    /// command, and after `continue` stops at the last breakpoint set.
+    fn emulator(memory: Vec<u8>, pc: u16) -> (u16, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let received = log.clone();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut bp = pc;
+            for line in BufReader::new(stream).lines() {
+                let Ok(cmd) = serde_json::from_str::<Value>(&line.unwrap()) else { break };
+                received.lock().unwrap().push(cmd.clone());
+                let mut resp = match cmd["cmd"].as_str().unwrap() {
+                    "get_registers" => json!({"pc": pc, "stack": [], "v_regs": vec![0; 16]}),
+                    "get_memory" => {
+                        let (s, e) = (cmd["s"].as_u64().unwrap() as usize, cmd["e"].as_u64().unwrap() as usize);
+                        json!({"data": memory[s..e]})
+                    }
+                    "set_bp" => {
+                        bp = cmd["a"].as_u64().unwrap() as u16;
+                        json!({"ok": true})
+                    }
+                    _ => json!({"ok": true}),
+                };
+                let Some(id) = cmd.get("id") else { continue };
+                resp["id"] = id.clone();
+                writeln!(writer, "{resp}").unwrap();
+                if cmd["cmd"] == "continue" {
+                    writeln!(writer, "{}", json!({"event": "paused", "pc": bp, "reason": "breakpoint"})).unwrap();
+                }
+            }
+        });
+        (port, log)
+    }
+
+    fn adapter() -> (Adapter, Sink) {
+        let sink = Sink::default();
+        (Adapter::new(Output::new(sink.clone())), sink)
+    }
+
+    fn connected(memory: Vec<u8>, pc: u16) -> (Adapter, Sink, Arc<Mutex<Vec<Value>>>) {
+        let (port, log) = emulator(memory, pc);
+        let (mut adapter, sink) = adapter();
+        adapter.connect(port).unwrap();
+        (adapter, sink, log)
+    }
+
+    fn line(line: usize, addr: u16) -> Line {
+        Line { path: PathBuf::from(SOURCE), line, addr, size: 2 }
+    }
+
+    fn set_breakpoints(adapter: &mut Adapter, lines: &[u64]) -> Vec<Value> {
+        let bps: Vec<Value> = lines.iter().map(|l| json!({"line": l})).collect();
+        let req = json!({"command": "setBreakpoints", "arguments": {"source": {"path": SOURCE}, "breakpoints": bps}});
+        let resp = adapter.handle(&req).unwrap();
+        resp["breakpoints"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn test_set_breakpoints() {
+        let (mut adapter, _) = adapter();
+        adapter.debug.lines = vec![line(2, 0x200), line(5, 0x202), line(5, 0x204)];
+
+        // Lines start at 1 unless the editor says otherwise
+        let bps = set_breakpoints(&mut adapter, &[3, 4, 6, 7]);
+        assert_eq!(bps[0], json!({"verified": true, "line": 3, "instructionReference": "0x200"}));
+        assert_eq!(bps[1], json!({"verified": true, "line": 6, "instructionReference": "0x202"}));
+        assert_eq!(bps[2], bps[1]);
+        assert_eq!(bps[3]["verified"], false);
+        assert_eq!(bps[3]["line"], 7);
+        assert_eq!(adapter.source_breakpoints[Path::new(SOURCE)], [0x200, 0x202, 0x202]);
+
+        adapter.handle(&json!({"command": "initialize", "arguments": {"linesStartAt1": false}})).unwrap();
+        let bps = set_breakpoints(&mut adapter, &[0, 3, 5, 6]);
+        assert_eq!(bps[0]["line"], 2);
+        assert_eq!(bps[0]["instructionReference"], "0x200");
+        assert_eq!(bps[1]["line"], 5);
+        assert_eq!(bps[2]["instructionReference"], "0x202");
+        assert_eq!(bps[3]["verified"], false);
+        assert_eq!(adapter.source_breakpoints[Path::new(SOURCE)], [0x200, 0x202, 0x202]);
+    }
+
+    #[test]
+    fn test_next_stops_as_step_and_clears_target() {
+        let mut memory = vec![0; MEMORY_SIZE as usize];
+        memory[0x200..0x202].copy_from_slice(&[0x23, 0x00]);
+        let (mut adapter, sink, log) = connected(memory, 0x200);
+
+        adapter.handle(&json!({"command": "next"})).unwrap();
+        let body = sink.stopped();
+        assert_eq!(body["reason"], "step");
+        assert_eq!(body["threadId"], THREAD_ID);
+        assert_eq!(adapter.stop.lock().unwrap().step_target, None);
+
+        // clear_bp goes out before the stopped event, so it is logged by the time pause is answered
+        adapter.handle(&json!({"command": "pause"})).unwrap();
+        let cmds: Vec<Value> = log.lock().unwrap().iter().map(|c| c["cmd"].clone()).collect();
+        let set = cmds.iter().position(|c| c == "set_bp").unwrap();
+        let clear = cmds.iter().position(|c| c == "clear_bp").unwrap();
+        assert!(set < clear);
+        assert!(log.lock().unwrap().iter().any(|c| c["cmd"] == "clear_bp" && c["a"] == 0x202));
+    }
+
+    #[test]
+    fn test_on_event_reasons() {
+        let (port, _) = emulator(Vec::new(), 0x200);
+        let client = Client::connect(port, |_, _| {}, || {}).unwrap();
+        let sink = Sink::default();
+        let out = Output::new(sink.clone());
+        let stop = Mutex::new(StopState::default());
+        let paused = |reason| json!({"event": "paused", "pc": 0x200, "reason": reason});
+
+        for (reason, expected) in [("breakpoint", "breakpoint"), ("step", "step"), ("user", "pause")] {
+            sink.0.lock().unwrap().clear();
+            on_event(&out, &stop, &client, paused(reason));
+            assert_eq!(sink.stopped()["reason"], expected);
+        }
+
+        sink.0.lock().unwrap().clear();
+        on_event(&out, &stop, &client, json!({"event": "fault", "message": "stack overflow"}));
+        on_event(&out, &stop, &client, paused("fault"));
+        let body = sink.stopped();
+        assert_eq!(body["reason"], "exception");
+        assert_eq!(body["text"], "stack overflow");
+        assert_eq!(stop.lock().unwrap().fault, None);
+
+        // A target the editor also has a breakpoint on stops as that breakpoint
+        sink.0.lock().unwrap().clear();
+        {
+            let mut stop = stop.lock().unwrap();
+            stop.breakpoints.insert(0x200);
+            stop.step_target = Some(0x200);
+        }
+        on_event(&out, &stop, &client, paused("breakpoint"));
+        assert_eq!(sink.stopped()["reason"], "breakpoint");
+        assert_eq!(stop.lock().unwrap().step_target, None);
+    }
+
+    #[test]
+    fn test_read_memory_clamps() {
+        let memory: Vec<u8> = (0..MEMORY_SIZE).map(|i| i as u8).collect();
+        let (mut adapter, _, _) = connected(memory, 0x200);
+        let read = |adapter: &mut Adapter, reference: &str, offset: i64, count: i64| {
+            let args = json!({"memoryReference": reference, "offset": offset, "count": count});
+            adapter.handle(&json!({"command": "readMemory", "arguments": args})).unwrap()
+        };
+
+        let resp = read(&mut adapter, "0x0", -4, 8);
+        assert_eq!(resp["address"], "0x000");
+        assert_eq!(base64_decode(resp["data"].as_str().unwrap()).unwrap(), [0, 1, 2, 3]);
+        assert_eq!(resp["unreadableBytes"], 4);
+
+        let resp = read(&mut adapter, "4094", 0, 8);
+        assert_eq!(resp["address"], "0xFFE");
+        assert_eq!(base64_decode(resp["data"].as_str().unwrap()).unwrap(), [0xFE, 0xFF]);
+        assert_eq!(resp["unreadableBytes"], 6);
+
+        let resp = read(&mut adapter, "0x1000", 0, 4);
+        assert_eq!(resp["data"], "");
+        assert_eq!(resp["unreadableBytes"], 4);
+    }
+
+    #[test]
+    fn test_disassemble_marks_outside_memory_invalid() {
+        let mut memory = vec![0; MEMORY_SIZE as usize];
+        memory[0..2].copy_from_slice(&[0x00, 0xE0]);
+        memory[0xFFE..].copy_from_slice(&[0x12, 0x00]);
+        let (mut adapter, _, _) = connected(memory, 0x200);
+        let disassemble = |adapter: &mut Adapter, reference: &str, offset: i64, count: i64| {
+            let args = json!({"memoryReference": reference, "instructionOffset": offset, "instructionCount": count});
+            let resp = adapter.handle(&json!({"command": "disassemble", "arguments": args})).unwrap();
+            resp["instructions"].as_array().unwrap().clone()
+        };
+
+        let instrs = disassemble(&mut adapter, "0x0", -1, 2);
+        assert_eq!(instrs[0], json!({"address": "-0x002", "instruction": "??", "presentationHint": "invalid"}));
+        assert_eq!(instrs[1]["address"], "0x000");
+        assert_eq!(instrs[1]["instructionBytes"], "00E0");
+        assert!(instrs[1].get("presentationHint").is_none());
+
+        let instrs = disassemble(&mut adapter, "0xFFE", 0, 2);
+        assert_eq!(instrs[0]["instructionBytes"], "1200");
+        assert_eq!(instrs[1]["address"], "0x1000");
+        assert_eq!(instrs[1]["presentationHint"], "invalid");
+    }
+
+    #[test]
+    fn test_parse_ref() {
+        assert_eq!(parse_ref(&json!("0x200")), Ok(0x200));
+        assert_eq!(parse_ref(&json!("0XfF")), Ok(0xFF));
+        assert_eq!(parse_ref(&json!("512")), Ok(512));
+        assert_eq!(parse_ref(&json!("0xZZ")), Err("invalid memory reference: 0xZZ".to_string()));
+        assert_eq!(parse_ref(&json!(512)), Err("missing memory reference".to_string()));
+        assert_eq!(parse_ref(&Value::Null), Err("missing memory reference".to_string()));
+    }
+
+    #[test]
+    fn test_port() {
+        assert_eq!(port(&json!({})), Ok(DEFAULT_PORT));
+        assert_eq!(port(&json!({"port": 65535})), Ok(65535));
+        assert_eq!(port(&json!({"port": 65536 + 9876})), Err("port 75412 is above 65535".to_string()));
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+type Pending = Arc<Mutex<HashMap<u64, Sender<Value>>>>;
+
+/// Connection to the emulator's debug server. Replies are matched to
+/// requests by `id`; pushed events are handed to the callback given to
+/// `connect`, on the thread reading the connection.
+pub struct Client {
+    writer: Mutex<TcpStream>,
+    pending: Pending,
+    next_id: AtomicU64,
+}
+
+impl Client {
+    /// Connects and subscribes to all events. `on_close` runs once the
+    /// emulator hangs up.
+    pub fn connect(
+        port: u16,
+        on_event: impl Fn(&Client, Value) + Send + 'static,
+        on_close: impl FnOnce() + Send + 'static,
+    ) -> io::Result<Arc<Client>> {
+        let stream = TcpStream::connect(("127.0.0.1", port))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let client = Arc::new(Client {
+            writer: Mutex::new(stream),
+            pending: Pending::default(),
+            next_id: AtomicU64::new(1),
+        });
+
+        let this = client.clone();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                let Ok(msg) = serde_json::from_str::<Value>(&line) else { continue };
+                if msg.get("event").is_some() {
+                    on_event(&this, msg);
+                } else if let Some(id) = msg.get("id").and_then(Value::as_u64) {
+                    if let Some(tx) = this.pending.lock().unwrap().remove(&id) {
+                        let _ = tx.send(msg);
+                    }
+                }
+            }
+            // Fails whatever is still waiting for a reply
+            this.pending.lock().unwrap().clear();
+            on_close();
+        });
+
+        client.request(json!({"cmd": "subscribe"})).map_err(io::Error::other)?;
+        Ok(client)
+    }
+
+    /// Sends `cmd` and waits for its reply; an `error` reply becomes `Err`.
+    /// Must not be called from the event callback, which runs on the thread
+    /// that delivers replies; use `notify` there.
+    pub fn request(&self, mut cmd: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        cmd["id"] = id.into();
+        let (tx, rx) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        if let Err(e) = self.write(&cmd) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(format!("emulator connection lost: {e}"));
+        }
+        let resp = rx.recv().map_err(|_| "emulator disconnected".to_string())?;
+        match resp.get("error").and_then(Value::as_str) {
+            Some(err) => Err(err.to_string()),
+            None => Ok(resp),
+        }
+    }
+
+    /// Sends `cmd` without waiting for the reply.
+    pub fn notify(&self, cmd: Value) {
+        let _ = self.write(&cmd);
+    }
+
+    fn write(&self, cmd: &Value) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{cmd}")?;
+        writer.flush()
+    }
+}
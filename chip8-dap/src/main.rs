@@ -0,0 +1,34 @@
+mod adapter;
+mod client;
+mod program;
+mod protocol;
+
+use adapter::Adapter;
+use protocol::{read_message, Output};
+
+fn main() {
+    let out = Output::new(std::io::stdout());
+    let mut adapter = Adapter::new(out.clone());
+    let mut input = std::io::stdin().lock();
+
+    loop {
+        let req = match read_message(&mut input) {
+            Ok(Some(req)) => req,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("[DAP] {e}");
+                break;
+            }
+        };
+        if req["type"] != "request" {
+            continue;
+        }
+        let command = req["command"].as_str().unwrap_or("").to_string();
+        let result = adapter.handle(&req);
+        out.response(&req, result);
+        adapter.after_response(&command);
+        if command == "disconnect" {
+            break;
+        }
+    }
+}
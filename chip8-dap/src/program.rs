@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+/// Instructions of one source line, with the file resolved to a real path.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub path: PathBuf,
+    /// 0-based.
+    pub line: usize,
+    pub addr: u16,
+    pub size: u16,
+}
+
+/// What the assembler knows about the program being debugged. Empty when
+/// debugging a bare `.ch8`, in which case only disassembly is available.
+#[derive(Debug, Default)]
+pub struct DebugInfo {
+    pub lines: Vec<Line>,
+    /// Labels sorted by address, compiler-generated ones left out.
+    pub labels: Vec<(String, u16)>,
+}
+
+impl DebugInfo {
+    /// Assembles `source`, returning the ROM and its debug info.
+    pub fn assemble(source: &Path) -> Result<(Vec<u8>, DebugInfo), String> {
+        let errors = |errs: Vec<chip8_asm::AssemblyError>| {
+            errs.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+        };
+        let rom = chip8_asm::assemble_file(source).map_err(errors)?.bytes;
+        let analysis = chip8_asm::analyze_file(source).map_err(errors)?;
+
+        let root = canonical(source);
+        let lines = chip8_asm::debuginfo::line_table(&analysis)
+            .into_iter()
+            .map(|entry| Line {
+                path: match entry.file.as_str() {
+                    "<root>" | "<unknown>" => root.clone(),
+                    file => canonical(Path::new(file)),
+                },
+                line: entry.line,
+                addr: entry.addr,
+                size: entry.size,
+            })
+            .collect();
+        let mut labels: Vec<(String, u16)> = analysis
+            .symbol_table
+            .labels()
+            .filter(|(name, _)| !name.starts_with("__"))
+            .map(|(name, addr)| (name.clone(), *addr))
+            .collect();
+        labels.sort_by_key(|(name, addr)| (*addr, name.clone()));
+        Ok((rom, DebugInfo { lines, labels }))
+    }
+
+    /// The line whose instructions cover `addr`.
+    pub fn line_at(&self, addr: u16) -> Option<&Line> {
+        self.lines.iter().find(|l| (l.addr..l.addr + l.size).contains(&addr))
+    }
+
+    /// Where a breakpoint on `line` of `path` lands: that line, or the next
+    /// one with code if it has none.
+    pub fn breakpoint_line(&self, path: &Path, line: usize) -> Option<&Line> {
+        let path = canonical(path);
+        self.lines
+            .iter()
+            .filter(|l| l.path == path && l.line >= line)
+            .min_by_key(|l| (l.line, l.addr))
+    }
+
+    /// The label at or before `addr`, i.e. the routine it is in.
+    pub fn routine_at(&self, addr: u16) -> Option<&str> {
+        self.labels.iter().rev().find(|(_, a)| *a <= addr).map(|(n, _)| n.as_str())
+    }
+
+    pub fn label_exactly_at(&self, addr: u16) -> Option<&str> {
+        self.labels.iter().find(|(_, a)| *a == addr).map(|(n, _)| n.as_str())
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Starts the emulator paused with its debug server on `port`, taking
+/// `headless`, `quirks`, `seed`, `speed` and `emulator` from the launch
+/// arguments, and waits until the server accepts connections.
+pub fn spawn_emulator(rom: &Path, port: u16, args: &Value) -> Result<Child, String> {
+    let binary = args["emulator"]
+        .as_str()
+        .map(PathBuf::from)
+        .unwrap_or_else(emulator_binary);
+    let mut cmd = Command::new(&binary);
+    cmd.arg(rom)
+        .arg("--debug-port")
+        .arg(port.to_string())
+        .arg("--paused");
+    if args["headless"].as_bool().unwrap_or(false) {
+        cmd.arg("--headless");
+    }
+    let quirks: Vec<&str> = match &args["quirks"] {
+        Value::String(list) => list.split(',').collect(),
+        Value::Array(list) => list.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !quirks.is_empty() {
+        cmd.arg("--quirks").arg(quirks.join(","));
+    }
+    if let Some(seed) = args["seed"].as_u64() {
+        cmd.arg("--seed").arg(seed.to_string());
+    }
+    if let Some(speed) = args["speed"].as_u64() {
+        cmd.arg("--speed").arg(speed.to_string());
+    }
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {e}", binary.display()))?;
+
+    for _ in 0..50 {
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("emulator exited during startup ({status})"));
+        }
+        if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return Ok(child);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    Err(format!("emulator did not open port {port} within 5s"))
+}
+
+/// `CHIP8_EMULATOR` if set, else the `rusty_chip8` binary next to this one,
+/// else whatever is on `PATH`.
+fn emulator_binary() -> PathBuf {
+    if let Some(path) = std::env::var_os("CHIP8_EMULATOR") {
+        return PathBuf::from(path);
+    }
+    let name = format!("rusty_chip8{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&name)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(line: usize, addr: u16) -> Line {
+        Line { path: PathBuf::from("/chip8-dap-test/main.asm"), line, addr, size: 2 }
+    }
+
+    #[test]
+    fn test_breakpoint_line() {
+        let debug = DebugInfo {
+            lines: vec![line(2, 0x200), line(5, 0x202), line(5, 0x204), line(7, 0x206)],
+            labels: vec![("start".to_string(), 0x200), ("loop".to_string(), 0x204)],
+        };
+        let path = Path::new("/chip8-dap-test/main.asm");
+        let at = |l| debug.breakpoint_line(path, l).map(|l| (l.line, l.addr));
+        assert_eq!(at(0), Some((2, 0x200)));
+        assert_eq!(at(2), Some((2, 0x200)));
+        // Blank or comment lines move to the next line with code, its first instruction
+        assert_eq!(at(3), Some((5, 0x202)));
+        assert_eq!(at(7), Some((7, 0x206)));
+        assert_eq!(at(8), None);
+        assert!(debug.breakpoint_line(Path::new("/chip8-dap-test/other.asm"), 0).is_none());
+
+        assert_eq!(debug.line_at(0x205).map(|l| l.addr), Some(0x204));
+        assert_eq!(debug.routine_at(0x206), Some("loop"));
+        assert_eq!(debug.label_exactly_at(0x202), None);
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+
+/// Reads one `Content-Length` framed message, `None` at end of input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes framed messages to the editor. Cloned into the thread that turns
+/// emulator events into DAP events, so writes are serialized here.
+#[derive(Clone)]
+pub struct Output {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    seq: Arc<AtomicU64>,
+}
+
+impl Output {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Output {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            seq: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    fn send(&self, mut msg: Value) {
+        msg["seq"] = self.seq.fetch_add(1, Ordering::Relaxed).into();
+        let body = msg.to_string();
+        let mut writer = self.writer.lock().unwrap();
+        let _ = write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = writer.flush();
+    }
+
+    pub fn response(&self, req: &Value, result: Result<Value, String>) {
+        let mut msg = json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "command": req["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => msg["body"] = body,
+            Err(message) => msg["message"] = message.into(),
+        }
+        self.send(msg);
+    }
+
+    pub fn event(&self, event: &str, body: Value) {
+        self.send(json!({"type": "event", "event": event, "body": body}));
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Memory requests carry their bytes as base64.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=' && !c.is_ascii_whitespace()) {
+        let v = BASE64.iter().position(|&b| b == c)? as u32;
+        n = n << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_roundtrip() {
+        assert_eq!(base64_encode(b"CHIP-8"), "Q0hJUC04");
+        assert_eq!(base64_encode(&[0x00, 0xE0, 0x12]), "AOAS");
+        assert_eq!(base64_encode(&[0xF0]), "8A==");
+        for data in [&b""[..], b"a", b"ab", b"abc", &[0xFF, 0x00, 0x80, 0x7F]] {
+            assert_eq!(base64_decode(&base64_encode(data)).unwrap(), data);
+        }
+        assert_eq!(base64_decode("not*base64"), None);
+    }
+
+    #[test]
+    fn test_read_message() {
+        let mut input = &b"Content-Length: 9\r\n\r\n{\"seq\":1}Content-Type: x\r\nContent-Length: 2\r\n\r\n{}"[..];
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({"seq": 1})));
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+}
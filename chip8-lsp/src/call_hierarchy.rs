@@ -0,0 +1,217 @@
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::Token;
+use chip8_asm::parser::{Addr, Inst, Statement};
+use chip8_asm::AnalysisResult;
+
+use crate::workspace::{file_uri, Workspace};
+
+/// One `CALL` in the analyzed program.
+pub(crate) struct CallSite {
+    /// Label of the routine the call sits in, if any precedes it.
+    pub caller: Option<String>,
+    pub callee: String,
+    pub location: Location,
+}
+
+/// Every `CALL` to a label, including those inside pseudo-instructions and
+/// macro expansions, with its position in the file it came from. `root` is
+/// the buffer the analysis was made from.
+pub(crate) fn call_sites(analysis: &AnalysisResult, root: &Url) -> Vec<CallSite> {
+    let labels = &analysis.symbol_table;
+    let expanded: Vec<&str> = analysis.expanded_source.lines().collect();
+    let mut caller: Option<String> = None;
+    let mut sites = Vec::new();
+
+    for (idx, stmt) in analysis.statements.iter().enumerate() {
+        let insts = match stmt {
+            // Generated labels (`__if0_end`, `__m1_x`) don't start a routine
+            Statement::Label(name) if !name.starts_with("__") => {
+                caller = Some(name.clone());
+                continue;
+            }
+            Statement::Inst(inst) => std::slice::from_ref(inst),
+            Statement::Pseudo { insts, .. } => insts.as_slice(),
+            _ => continue,
+        };
+        for inst in insts {
+            let callee = match inst {
+                Inst::Call(Addr::Label(name)) => name.clone(),
+                Inst::Call(Addr::Num(addr)) => {
+                    match labels.labels().find(|(n, a)| *a == addr && !n.starts_with("__")) {
+                        Some((name, _)) => name.clone(),
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+            let Some(&line) = analysis.statement_lines.get(idx) else { continue };
+            let text = expanded.get(line).copied().unwrap_or("");
+            let col = text.rfind(callee.as_str()).unwrap_or(0);
+            let (file, file_line, col) = analysis.source_map.resolve_pos(line, col);
+            let len = if text.contains(callee.as_str()) { callee.len() } else { 0 };
+            sites.push(CallSite {
+                caller: caller.clone(),
+                callee,
+                location: Location {
+                    uri: file_uri(file, root),
+                    range: range(file_line, col, col + len),
+                },
+            });
+        }
+    }
+    sites
+}
+
+/// Where label `name` is defined: its file, the whole line and the name.
+pub(crate) fn label_location(analysis: &AnalysisResult, root: &Url, name: &str) -> Option<(Url, Range, Range)> {
+    let idx = analysis
+        .statements
+        .iter()
+        .position(|s| matches!(s, Statement::Label(l) if l == name))?;
+    let line = *analysis.statement_lines.get(idx)?;
+    let text = analysis.expanded_source.lines().nth(line).unwrap_or("");
+    let col = text.find(name).unwrap_or(0);
+    let (file, file_line, col) = analysis.source_map.resolve_pos(line, col);
+    Some((
+        file_uri(file, root),
+        range(file_line, 0, text.len()),
+        range(file_line, col, col + name.len()),
+    ))
+}
+
+pub fn prepare(ws: &Workspace, uri: &Url, pos: Position) -> Option<Vec<CallHierarchyItem>> {
+    let doc = ws.get_document(uri)?;
+    let analysis = doc.analysis.as_ref()?;
+    let tokens = doc.tokens.as_deref()?;
+    let name = tokens.iter().find_map(|(tok, line, col)| match tok {
+        Token::Word(w)
+            if *line == pos.line as usize
+                && (*col..=col + w.len()).contains(&(pos.character as usize)) =>
+        {
+            Some(w)
+        }
+        _ => None,
+    })?;
+    Some(vec![item(analysis, uri, name)?])
+}
+
+pub fn incoming_calls(ws: &Workspace, item: &CallHierarchyItem) -> Option<Vec<CallHierarchyIncomingCall>> {
+    let root = root_uri(item);
+    let analysis = ws.get_document(&root)?.analysis.as_ref()?;
+    let mut calls: Vec<CallHierarchyIncomingCall> = Vec::new();
+    for site in call_sites(analysis, &root) {
+        let Some(caller) = site.caller.filter(|_| site.callee == item.name) else { continue };
+        match calls.iter_mut().find(|c| c.from.name == caller) {
+            Some(call) => call.from_ranges.push(site.location.range),
+            None => calls.push(CallHierarchyIncomingCall {
+                from: self::item(analysis, &root, &caller)?,
+                from_ranges: vec![site.location.range],
+            }),
+        }
+    }
+    Some(calls)
+}
+
+pub fn outgoing_calls(ws: &Workspace, item: &CallHierarchyItem) -> Option<Vec<CallHierarchyOutgoingCall>> {
+    let root = root_uri(item);
+    let analysis = ws.get_document(&root)?.analysis.as_ref()?;
+    let mut calls: Vec<CallHierarchyOutgoingCall> = Vec::new();
+    for site in call_sites(analysis, &root) {
+        if site.caller.as_deref() != Some(item.name.as_str()) {
+            continue;
+        }
+        match calls.iter_mut().find(|c| c.to.name == site.callee) {
+            Some(call) => call.from_ranges.push(site.location.range),
+            None => calls.push(CallHierarchyOutgoingCall {
+                to: self::item(analysis, &root, &site.callee)?,
+                from_ranges: vec![site.location.range],
+            }),
+        }
+    }
+    Some(calls)
+}
+
+/// An item for label `name`. `data` keeps the analyzed buffer so follow-up
+/// requests use the same program even when the label is in an include.
+fn item(analysis: &AnalysisResult, root: &Url, name: &str) -> Option<CallHierarchyItem> {
+    let addr = analysis.symbol_table.labels().find(|(n, _)| *n == name).map(|(_, a)| *a)?;
+    let (uri, range, selection_range) = label_location(analysis, root, name)?;
+    Some(CallHierarchyItem {
+        name: name.to_string(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: Some(format!("0x{:03X}", addr)),
+        uri,
+        range,
+        selection_range,
+        data: Some(serde_json::Value::String(root.to_string())),
+    })
+}
+
+fn root_uri(item: &CallHierarchyItem) -> Url {
+    item.data
+        .as_ref()
+        .and_then(|d| d.as_str())
+        .and_then(|s| Url::parse(s).ok())
+        .unwrap_or_else(|| item.uri.clone())
+}
+
+fn range(line: usize, start: usize, end: usize) -> Range {
+    Range {
+        start: Position { line: line as u32, character: start as u32 },
+        end: Position { line: line as u32, character: end as u32 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use chip8_asm::analyze;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const SOURCE: &str = "\
+start:
+    CALL init
+    CALL draw
+    JP start
+init:
+    CALL draw
+    RET
+draw:
+    CLS
+    RET
+";
+
+    fn workspace() -> (Workspace, Url) {
+        let uri = Url::parse("file:///test.asm").unwrap();
+        let mut ws = Workspace::new();
+        let mut doc = Document::new(PathBuf::from("/test.asm"), SOURCE.to_string());
+        doc.analysis = Some(Arc::new(analyze(SOURCE).unwrap()));
+        ws.documents.insert(uri.clone(), doc);
+        (ws, uri)
+    }
+
+    #[test]
+    fn test_incoming_and_outgoing_calls() {
+        let (ws, uri) = workspace();
+        let items = prepare(&ws, &uri, Position { line: 7, character: 1 }).unwrap();
+        assert_eq!(items[0].name, "draw");
+        assert_eq!(items[0].detail.as_deref(), Some("0x20A"));
+        assert_eq!(items[0].selection_range.start.line, 7);
+
+        let incoming = incoming_calls(&ws, &items[0]).unwrap();
+        let from: Vec<(&str, u32, u32)> = incoming
+            .iter()
+            .map(|c| (c.from.name.as_str(), c.from_ranges[0].start.line, c.from_ranges[0].start.character))
+            .collect();
+        assert_eq!(from, [("start", 2, 9), ("init", 5, 9)]);
+
+        let start = prepare(&ws, &uri, Position { line: 0, character: 0 }).unwrap();
+        let outgoing = outgoing_calls(&ws, &start[0]).unwrap();
+        let to: Vec<&str> = outgoing.iter().map(|c| c.to.name.as_str()).collect();
+        assert_eq!(to, ["init", "draw"]);
+    }
+}
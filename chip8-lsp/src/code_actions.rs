@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::{span, Token};
+use chip8_asm::parser::MNEMONICS;
+
+use crate::document::Document;
+use crate::workspace::Workspace;
+
+/// Quick fixes for the diagnostics in `context` plus refactorings for the
+/// token or lines under `range`.
+pub fn code_actions(
+    ws: &Workspace,
+    uri: &Url,
+    range: Range,
+    context: &CodeActionContext,
+) -> Option<CodeActionResponse> {
+    let doc = ws.get_document(uri)?;
+    let mut actions = Vec::new();
+
+    for diag in &context.diagnostics {
+        if let Some(name) = quoted(&diag.message, "undefined symbol '") {
+            actions.push(create_label(doc, uri, diag, name));
+            actions.push(create_const(doc, uri, diag, name));
+        } else if let Some(word) = quoted(&diag.message, "unknown mnemonic '") {
+            actions.extend(fix_mnemonic(uri, diag, word));
+        }
+    }
+
+    let lines: Vec<&str> = doc.source.lines().collect();
+    let tokens = doc.tokens.as_deref().unwrap_or(&[]);
+    let pos = range.start;
+    let at_cursor = tokens.iter().find(|(tok, l, c)| {
+        let (start, end) = span(lines.get(*l).copied().unwrap_or(""), tok, *c);
+        *l == pos.line as usize && (start..=end).contains(&(pos.character as usize))
+    });
+    match at_cursor {
+        Some((Token::Number(n), l, c)) => {
+            actions.extend(convert_number(uri, lines[*l], *n, *l, *c));
+        }
+        Some((Token::Word(w), _, _)) => {
+            actions.extend(inline_const(doc, uri, &lines, w));
+        }
+        _ => {}
+    }
+    actions.extend(extract_macro(doc, uri, &lines, range));
+
+    Some(actions)
+}
+
+fn quoted<'a>(message: &'a str, prefix: &str) -> Option<&'a str> {
+    message.strip_prefix(prefix)?.split('\'').next()
+}
+
+fn action(title: String, kind: CodeActionKind, uri: &Url, edits: Vec<TextEdit>) -> CodeAction {
+    CodeAction {
+        title,
+        kind: Some(kind),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn insert(line: usize, text: String) -> TextEdit {
+    let pos = Position { line: line as u32, character: 0 };
+    TextEdit { range: Range { start: pos, end: pos }, new_text: text }
+}
+
+fn line_range(line: usize, text: &str) -> Range {
+    Range {
+        start: Position { line: line as u32, character: 0 },
+        end: Position { line: line as u32, character: text.encode_utf16().count() as u32 },
+    }
+}
+
+// ── Quick fixes ─────────────────────────────────────────────────────────
+
+fn create_label(doc: &Document, uri: &Url, diag: &Diagnostic, name: &str) -> CodeActionOrCommand {
+    let lines = doc.source.lines().count();
+    let sep = if doc.source.is_empty() || doc.source.ends_with('\n') { "" } else { "\n" };
+    let edit = insert(lines, format!("{}\n{}:\n", sep, name));
+    let mut a = action(format!("Create label '{}'", name), CodeActionKind::QUICKFIX, uri, vec![edit]);
+    a.diagnostics = Some(vec![diag.clone()]);
+    CodeActionOrCommand::CodeAction(a)
+}
+
+/// Adds `.const NAME = 0` after the last top-level `.const`, or at the top.
+fn create_const(doc: &Document, uri: &Url, diag: &Diagnostic, name: &str) -> CodeActionOrCommand {
+    let line = doc
+        .source
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| l.trim_start().to_lowercase().starts_with(".const "))
+        .last()
+        .map_or(0, |(i, _)| i + 1);
+    let edit = insert(line, format!(".const {} = 0\n", name));
+    let mut a = action(format!("Create .const {}", name), CodeActionKind::QUICKFIX, uri, vec![edit]);
+    a.diagnostics = Some(vec![diag.clone()]);
+    CodeActionOrCommand::CodeAction(a)
+}
+
+/// Replacements for a misspelled mnemonic, closest first.
+fn fix_mnemonic(uri: &Url, diag: &Diagnostic, word: &str) -> Vec<CodeActionOrCommand> {
+    let upper = word.to_uppercase();
+    let mut candidates: Vec<(usize, &str)> = MNEMONICS
+        .iter()
+        .map(|m| (edit_distance(&upper, m), *m))
+        .filter(|(d, m)| *d <= 2 && *d < m.len())
+        .collect();
+    candidates.sort();
+
+    // Keep the case the user typed in
+    let lower = word.chars().all(|c| !c.is_ascii_uppercase());
+    candidates
+        .into_iter()
+        .take(3)
+        .enumerate()
+        .map(|(i, (_, m))| {
+            let text = if lower { m.to_lowercase() } else { m.to_string() };
+            let edit = TextEdit { range: diag.range, new_text: text.clone() };
+            let mut a = action(format!("Change to '{}'", text), CodeActionKind::QUICKFIX, uri, vec![edit]);
+            a.diagnostics = Some(vec![diag.clone()]);
+            a.is_preferred = Some(i == 0);
+            CodeActionOrCommand::CodeAction(a)
+        })
+        .collect()
+}
+
+/// Levenshtein distance.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+// ── Refactorings ────────────────────────────────────────────────────────
+
+fn convert_number(uri: &Url, text: &str, n: u16, line: usize, col: usize) -> Vec<CodeActionOrCommand> {
+    let (start, end) = span(text, &Token::Number(n), col);
+    let current = &text[start..end];
+    let bits = if n > 0xFF { 16 } else { 8 };
+    let forms = [
+        ("hex", format!("0x{:02X}", n)),
+        ("decimal", n.to_string()),
+        ("binary", format!("0b{:0width$b}", n, width = bits)),
+    ];
+    let range = Range {
+        start: Position { line: line as u32, character: start as u32 },
+        end: Position { line: line as u32, character: end as u32 },
+    };
+    forms
+        .into_iter()
+        .filter(|(kind, form)| form != current && !(*kind == "hex" && is_hex(current)))
+        .map(|(kind, form)| {
+            let edit = TextEdit { range, new_text: form.clone() };
+            let title = format!("Convert to {} ({})", kind, form);
+            CodeActionOrCommand::CodeAction(action(title, CodeActionKind::REFACTOR_REWRITE, uri, vec![edit]))
+        })
+        .collect()
+}
+
+fn is_hex(literal: &str) -> bool {
+    let l = literal.trim_start_matches('#');
+    l.starts_with('$') || l.starts_with("0x")
+}
+
+/// Replaces every use of a constant in this document with its value. The
+/// definition is removed when it is in this document too.
+fn inline_const(doc: &Document, uri: &Url, lines: &[&str], name: &str) -> Option<CodeActionOrCommand> {
+    let sym = doc.symbol_table()?;
+    let value = sym.resolve(name)?;
+    if sym.labels().any(|(l, _)| l == name) {
+        return None;
+    }
+
+    let def_line = lines.iter().position(|l| {
+        let mut words = l.split_whitespace();
+        words.next().is_some_and(|w| w.eq_ignore_ascii_case(".const"))
+            && words.next().is_some_and(|w| w.trim_end_matches('=') == name)
+    });
+    // Reuse the literal as written in the definition when there is one
+    let literal = def_line
+        .and_then(|l| {
+            let text = lines[l];
+            let after = text.find(name)? + name.len();
+            let rest = text[after..].split(';').next()?.trim().trim_start_matches('=').trim();
+            (!rest.is_empty()).then(|| rest.to_string())
+        })
+        .unwrap_or_else(|| value.to_string());
+
+    let mut edits: Vec<TextEdit> = doc
+        .tokens
+        .as_deref()
+        .unwrap_or(&[])
+        .iter()
+        .filter(|(tok, l, _)| matches!(tok, Token::Word(w) if w == name) && Some(*l) != def_line)
+        .map(|(_, l, c)| TextEdit {
+            range: Range {
+                start: Position { line: *l as u32, character: *c as u32 },
+                end: Position { line: *l as u32, character: (c + name.len()) as u32 },
+            },
+            new_text: literal.clone(),
+        })
+        .collect();
+    if let Some(l) = def_line {
+        edits.push(TextEdit {
+            range: Range {
+                start: Position { line: l as u32, character: 0 },
+                end: Position { line: l as u32 + 1, character: 0 },
+            },
+            new_text: String::new(),
+        });
+    }
+    let title = format!("Inline constant {}", name);
+    Some(CodeActionOrCommand::CodeAction(action(title, CodeActionKind::REFACTOR_INLINE, uri, edits)))
+}
+
+/// Moves the selected lines into a new parameterless macro defined above the
+/// enclosing top-level label, and invokes it in their place. Selections with
+/// labels or unbalanced braces are left alone.
+fn extract_macro(doc: &Document, uri: &Url, lines: &[&str], range: Range) -> Option<CodeActionOrCommand> {
+    if range.start == range.end {
+        return None;
+    }
+    let first = range.start.line as usize;
+    let mut last = range.end.line as usize;
+    if range.end.character == 0 && last > first {
+        last -= 1;
+    }
+    let selected = lines.get(first..=last)?;
+
+    let tokens = doc.tokens.as_deref().unwrap_or(&[]);
+    let in_selection = || tokens.iter().filter(|(_, l, _)| (first..=last).contains(l));
+    let has_label = in_selection()
+        .zip(in_selection().skip(1))
+        .any(|(a, b)| matches!(a.0, Token::Word(_)) && b.0 == Token::Colon && a.1 == b.1);
+    let depth = in_selection().fold(0i32, |d, (t, _, _)| match t {
+        Token::LBrace => d + 1,
+        Token::RBrace => d - 1,
+        _ => d,
+    });
+    if has_label || depth != 0 || !in_selection().any(|(t, _, _)| matches!(t, Token::Word(_))) {
+        return None;
+    }
+
+    let taken: Vec<&str> = lines
+        .iter()
+        .filter_map(|l| l.trim().strip_prefix("macro "))
+        .filter_map(|r| r.split_whitespace().next())
+        .collect();
+    let name = std::iter::once("extracted".to_string())
+        .chain((2..).map(|i| format!("extracted{}", i)))
+        .find(|n| !taken.contains(&n.as_str()))?;
+
+    // Above the last column-0 label before the selection, or the file start
+    let at = lines[..first]
+        .iter()
+        .rposition(|l| l.split(';').next().is_some_and(|c| c.trim_end().ends_with(':')) && !l.starts_with(' '))
+        .unwrap_or(0);
+
+    let min_indent = selected
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let body: String = selected
+        .iter()
+        .map(|l| match l.get(min_indent..) {
+            Some(rest) if !rest.is_empty() => format!("    {}\n", rest),
+            _ => "\n".to_string(),
+        })
+        .collect();
+
+    let edits = vec![
+        insert(at, format!("macro {} {{\n{}}}\n\n", name, body)),
+        TextEdit {
+            range: Range { start: line_range(first, lines[first]).start, end: line_range(last, lines[last]).end },
+            new_text: format!("{}{}", " ".repeat(min_indent), name),
+        },
+    ];
+    let title = format!("Extract into macro '{}'", name);
+    Some(CodeActionOrCommand::CodeAction(action(title, CodeActionKind::REFACTOR_EXTRACT, uri, edits)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_asm::analyze;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn workspace(source: &str) -> (Workspace, Url) {
+        let analysis = analyze(source).unwrap();
+        let uri = Url::parse("file:///test.asm").unwrap();
+        let mut ws = Workspace::new();
+        let mut doc = Document::new(PathBuf::from("/test.asm"), source.to_string());
+        doc.analysis = Some(Arc::new(analysis));
+        ws.documents.insert(uri.clone(), doc);
+        (ws, uri)
+    }
+
+    fn point(line: u32, character: u32) -> Range {
+        let p = Position { line, character };
+        Range { start: p, end: p }
+    }
+
+    fn actions(ws: &Workspace, uri: &Url, range: Range, diagnostics: Vec<Diagnostic>) -> Vec<CodeAction> {
+        let context = CodeActionContext { diagnostics, ..Default::default() };
+        code_actions(ws, uri, range, &context)
+            .unwrap()
+            .into_iter()
+            .map(|a| match a {
+                CodeActionOrCommand::CodeAction(a) => a,
+                CodeActionOrCommand::Command(_) => panic!("unexpected command"),
+            })
+            .collect()
+    }
+
+    fn edits<'a>(a: &'a CodeAction, uri: &Url) -> &'a [TextEdit] {
+        &a.edit.as_ref().unwrap().changes.as_ref().unwrap()[uri]
+    }
+
+    #[test]
+    fn test_create_missing_symbol() {
+        let (ws, uri) = workspace(".const A = 1\nstart:\n    LD V0, A\n");
+        let diag = Diagnostic {
+            range: point(2, 11),
+            message: "undefined symbol 'speed'".into(),
+            ..Default::default()
+        };
+        let found = actions(&ws, &uri, point(0, 0), vec![diag]);
+        assert_eq!(found[0].title, "Create label 'speed'");
+        assert_eq!(edits(&found[0], &uri)[0].new_text, "\nspeed:\n");
+        assert_eq!(found[1].title, "Create .const speed");
+        assert_eq!(edits(&found[1], &uri)[0].range.start.line, 1);
+    }
+
+    #[test]
+    fn test_fix_mnemonic_typo() {
+        let (ws, uri) = workspace("start:\n    CLS\n");
+        let diag = Diagnostic {
+            range: Range { start: Position { line: 1, character: 4 }, end: Position { line: 1, character: 7 } },
+            message: "unknown mnemonic 'drq'".into(),
+            ..Default::default()
+        };
+        let found = actions(&ws, &uri, point(1, 4), vec![diag]);
+        assert_eq!(found[0].title, "Change to 'drw'");
+        assert_eq!(found[0].is_preferred, Some(true));
+        assert_eq!(edit_distance("SKNPP", "SKNP"), 1);
+    }
+
+    #[test]
+    fn test_convert_number() {
+        let (ws, uri) = workspace("LD V0, #$1F\n");
+        let found = actions(&ws, &uri, point(0, 9), vec![]);
+        let titles: Vec<&str> = found.iter().map(|a| a.title.as_str()).collect();
+        assert_eq!(titles, ["Convert to decimal (31)", "Convert to binary (0b00011111)"]);
+        let edit = &edits(&found[0], &uri)[0];
+        assert_eq!((edit.range.start.character, edit.range.end.character), (7, 11));
+    }
+
+    #[test]
+    fn test_inline_const() {
+        let (ws, uri) = workspace(".const SPEED = 0x02 ; px\nLD V0, SPEED\nADD V1, SPEED\n");
+        let found = actions(&ws, &uri, point(1, 8), vec![]);
+        let inline = found.iter().find(|a| a.title == "Inline constant SPEED").unwrap();
+        let e = edits(inline, &uri);
+        assert_eq!(e.len(), 3);
+        assert!(e[..2].iter().all(|e| e.new_text == "0x02"));
+        assert_eq!(e[2].range.end.line, 1);
+    }
+
+    #[test]
+    fn test_extract_macro() {
+        let source = "; top\nstart:\n    CLS\n    LD V0, 1\n    JP start\n";
+        let (ws, uri) = workspace(source);
+        let range = Range { start: Position { line: 2, character: 0 }, end: Position { line: 4, character: 0 } };
+        let found = actions(&ws, &uri, range, vec![]);
+        let extract = found.iter().find(|a| a.title == "Extract into macro 'extracted'").unwrap();
+        let e = edits(extract, &uri);
+        assert_eq!(e[0].range.start.line, 1);
+        assert_eq!(e[0].new_text, "macro extracted {\n    CLS\n    LD V0, 1\n}\n\n");
+        assert_eq!(e[1].new_text, "    extracted");
+
+        // labels can't be moved into a macro
+        let range = Range { start: Position { line: 1, character: 0 }, end: Position { line: 3, character: 0 } };
+        assert!(actions(&ws, &uri, range, vec![]).iter().all(|a| !a.title.starts_with("Extract")));
+    }
+}
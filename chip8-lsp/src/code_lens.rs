@@ -0,0 +1,110 @@
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::Token;
+use chip8_asm::parser::Statement;
+
+use crate::call_hierarchy::{call_sites, label_location};
+use crate::workspace::Workspace;
+
+/// A lens above each label in the buffer: how often it is referenced, how
+/// many bytes lie between it and the next label, and how many `CALL`s reach
+/// it.
+pub fn code_lenses(ws: &Workspace, uri: &Url) -> Option<Vec<CodeLens>> {
+    let doc = ws.get_document(uri)?;
+    let analysis = doc.analysis.as_ref()?;
+    let calls = call_sites(analysis, uri);
+
+    // Label starts in address order, and where the program ends
+    let mut starts: Vec<u16> = analysis
+        .symbol_table
+        .labels()
+        .filter(|(name, _)| !name.starts_with("__"))
+        .map(|(_, addr)| *addr)
+        .collect();
+    starts.sort_unstable();
+    let end = analysis
+        .statements
+        .iter()
+        .zip(&analysis.addresses)
+        .map(|(stmt, &addr)| addr + chip8_asm::statement_size(stmt, addr))
+        .max()
+        .unwrap_or(0x200);
+
+    let mut lenses = Vec::new();
+    for stmt in &analysis.statements {
+        let Statement::Label(name) = stmt else { continue };
+        if name.starts_with("__") {
+            continue;
+        }
+        let Some((file, _, selection)) = label_location(analysis, uri, name) else { continue };
+        if &file != uri {
+            continue;
+        }
+        let Some(addr) = analysis.symbol_table.resolve(name) else { continue };
+
+        // Every mention in the expanded program except the definition itself
+        let tokens = &analysis.tokens;
+        let references = tokens
+            .iter()
+            .enumerate()
+            .filter(|(i, (tok, ..))| {
+                matches!(tok, Token::Word(w) if w == name)
+                    && !matches!(tokens.get(i + 1), Some((Token::Colon, ..)))
+            })
+            .count();
+        let next = starts.iter().find(|&&a| a > addr).copied().unwrap_or(end.max(addr));
+        let callers = calls.iter().filter(|c| &c.callee == name).count();
+
+        let mut parts = vec![
+            plural(references, "reference", "references"),
+            plural((next - addr) as usize, "byte", "bytes"),
+        ];
+        if callers > 0 {
+            parts.push(format!("called from {}", plural(callers, "place", "places")));
+        }
+        lenses.push(CodeLens {
+            range: selection,
+            command: Some(Command {
+                title: parts.join(" · "),
+                command: String::new(),
+                arguments: None,
+            }),
+            data: None,
+        });
+    }
+    Some(lenses)
+}
+
+fn plural(n: usize, one: &str, many: &str) -> String {
+    format!("{} {}", n, if n == 1 { one } else { many })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use chip8_asm::analyze;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_label_lenses() {
+        let source = "start:\n    CALL draw\n    CALL draw\n    JP start\ndraw:\n    CLS\n    RET\nsprite:\n.byte 0x80\n";
+        let uri = Url::parse("file:///test.asm").unwrap();
+        let mut ws = Workspace::new();
+        let mut doc = Document::new(PathBuf::from("/test.asm"), source.to_string());
+        doc.analysis = Some(Arc::new(analyze(source).unwrap()));
+        ws.documents.insert(uri.clone(), doc);
+
+        let titles: Vec<(u32, String)> = code_lenses(&ws, &uri)
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.range.start.line, l.command.unwrap().title))
+            .collect();
+        assert_eq!(titles, [
+            (0, "1 reference · 6 bytes".to_string()),
+            (4, "2 references · 4 bytes · called from 2 places".to_string()),
+            (7, "0 references · 1 byte".to_string()),
+        ]);
+    }
+}
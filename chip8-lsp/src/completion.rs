@@ -5,7 +5,7 @@ use crate::workspace::Workspace;
 const INSTRUCTIONS: &[&str] = &[
     "CLS", "RET", "SYS", "JP ", "CALL ", "SE ", "SNE ", "LD ", "ADD ",
     "OR ", "AND ", "XOR ", "SUB ", "SUBN ", "SHR ", "SHL ", "RND ", "DRW ",
-    "SKP ", "SKNP ",
+    "SKP ", "SKNP ", "NOT ", "NEG ", "INC ", "DEC ", "JEQ ", "JNE ",
 ];
 
 const REGISTERS: &[&str] = &[
@@ -17,6 +17,7 @@ const REGISTERS: &[&str] = &[
 const DIRECTIVES: &[&str] = &[
     ".org ", ".byte ", ".word ", ".ascii ", ".asciz ",
     ".align ", ".space ", ".const ", ".struct ",
+    ".rept ", ".endr", ".for ", ".endfor", ".incbin ", ".image ", ".sprite ",
 ];
 
 fn text(kind: CompletionItemKind, label: &str, detail: &str, insert: &str) -> CompletionItem {
@@ -72,6 +73,12 @@ pub fn get_completions(ws: &Workspace, uri: &Url, pos: Position) -> Option<Compl
                 "DRW" => "Draw sprite",
                 "SKP" => "Skip if key pressed",
                 "SKNP" => "Skip if key not pressed",
+                "NOT" => "Bitwise NOT (pseudo)",
+                "NEG" => "Negate (pseudo)",
+                "INC" => "Increment (pseudo)",
+                "DEC" => "Decrement (pseudo)",
+                "JEQ" => "Jump if equal (pseudo)",
+                "JNE" => "Jump if not equal (pseudo)",
                 _ => "Instruction",
             };
             items.push(text(CompletionItemKind::OPERATOR, instr.trim(), detail, instr));
@@ -1,9 +1,10 @@
 use tower_lsp::lsp_types::*;
 
-use crate::workspace::Workspace;
+use crate::workspace::{file_uri, Workspace};
 use chip8_asm::lexer::Token;
+use chip8_asm::AnalysisResult;
 
-fn find_token_at<'a>(tokens: &'a [(Token, usize, usize)], line: u32, col: u32) -> Option<(&'a Token, usize, usize)> {
+fn find_token_at(tokens: &[(Token, usize, usize)], line: u32, col: u32) -> Option<(&Token, usize, usize)> {
     for (tok, l, c) in tokens {
         let end = c + match tok {
             Token::Word(w) => w.len(),
@@ -25,57 +26,61 @@ pub fn goto_definition(ws: &Workspace, uri: &Url, pos: Position) -> Option<GotoD
 
     let (tok, _line, _col) = find_token_at(tokens, pos.line, pos.character)?;
 
-    match tok {
-        Token::Word(w) => {
-            let sym = doc.symbol_table.as_ref()?;
+    if let Token::Word(w) = tok {
+        let sym = doc.symbol_table()?;
 
-            // Skip if it's an instruction, register, or directive keyword
-            let upper = w.to_uppercase();
-            let is_instr = matches!(
-                upper.as_str(),
-                "CLS" | "RET" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD"
-                | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW"
-                | "SKP" | "SKNP"
-            );
-            let is_reg = matches!(
-                upper.as_str(),
-                "I" | "DT" | "ST" | "K" | "F" | "B"
-            ) || (upper.starts_with('V') && upper[1..].parse::<u8>().ok().map_or(false, |n| n <= 0xF));
-            let is_directive = matches!(
-                upper.as_str(),
-                "ORG" | "BYTE" | "WORD" | "ASCII" | "ASCIZ" | "ALIGN" | "SPACE" | "CONST" | "STRUCT"
-            );
+        // Skip if it's an instruction, register, or directive keyword
+        let upper = w.to_uppercase();
+        let is_instr = matches!(
+            upper.as_str(),
+            "CLS" | "RET" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD"
+            | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW"
+            | "SKP" | "SKNP"
+        );
+        let is_reg = matches!(
+            upper.as_str(),
+            "I" | "DT" | "ST" | "K" | "F" | "B"
+        ) || (upper.starts_with('V') && upper[1..].parse::<u8>().ok().is_some_and(|n| n <= 0xF));
+        let is_directive = matches!(
+            upper.as_str(),
+            "ORG" | "BYTE" | "WORD" | "ASCII" | "ASCIZ" | "ALIGN" | "SPACE" | "CONST" | "STRUCT"
+        );
 
-            if is_instr || is_reg || is_directive {
-                return None;
-            }
+        if is_instr || is_reg || is_directive {
+            return None;
+        }
 
-            // Try to resolve as symbol
-            if let Some(_addr) = sym.resolve(w) {
-                // Find where this symbol is defined in the source
-                let source = &analysis.source;
-                for (line_idx, line) in source.lines().enumerate() {
-                    let trimmed = line.trim();
-                    let is_label_def = trimmed == format!("{}:", w)
-                        || trimmed.starts_with(&format!("{}:", w));
-                    let is_const_def = trimmed.contains(".const")
-                        && trimmed.contains(w)
-                        && trimmed.contains('=');
-                    if is_label_def || is_const_def {
-                        let loc = Location {
-                            uri: uri.clone(),
-                            range: Range {
-                                start: Position { line: line_idx as u32, character: 0 },
-                                end: Position { line: line_idx as u32, character: line.len() as u32 },
-                            },
-                        };
-                        return Some(GotoDefinitionResponse::Scalar(loc));
-                    }
-                }
-            }
+        // Try to resolve as symbol
+        if sym.resolve(w).is_some() {
+            let (file, line, len) = find_definition(analysis, w)?;
+            let loc = Location {
+                uri: file_uri(file, uri),
+                range: Range {
+                    start: Position { line: line as u32, character: 0 },
+                    end: Position { line: line as u32, character: len as u32 },
+                },
+            };
+            return Some(GotoDefinitionResponse::Scalar(loc));
         }
-        _ => {}
     }
 
     None
 }
+
+/// Finds the line defining `name` (label or `.const`) in the preprocessed
+/// source and maps it back through the source map. Returns the file (`<root>`
+/// for the analyzed buffer), the line in that file and the line's length.
+pub fn find_definition<'a>(analysis: &'a AnalysisResult, name: &str) -> Option<(&'a str, usize, usize)> {
+    for (line_idx, line) in analysis.expanded_source.lines().enumerate() {
+        let trimmed = line.trim();
+        let is_label_def = trimmed.starts_with(&format!("{}:", name));
+        let is_const_def = trimmed.contains(".const")
+            && trimmed.contains(name)
+            && trimmed.contains('=');
+        if is_label_def || is_const_def {
+            let (file, file_line) = analysis.source_map.resolve(line_idx);
+            return Some((file, file_line, line.len()));
+        }
+    }
+    None
+}
@@ -1,37 +1,60 @@
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range, Url};
+use std::collections::HashMap;
 
-pub fn extract_parse_pos(e: &chip8_asm::parser::ParseError) -> (usize, usize) {
-    use chip8_asm::parser::ParseError::*;
-    match e {
-        UnknownMnemonic(_, l, c) => (*l, *c),
-        WrongArgCount { line, col, .. } => (*line, *col),
-        ExpectedRegister(l, c) => (*l, *c),
-        ExpectedImmediate(l, c) => (*l, *c),
-        ExpectedIdent(l, c) => (*l, *c),
-        ExpectedValue(l, c) => (*l, *c),
-        InvalidRegister(_, l, c) => (*l, *c),
-        InvalidDirective(_, l, c) => (*l, *c),
-        UnexpectedToken(_, l, c) => (*l, *c),
-        DuplicateLabel(_, l) => (*l, 0),
-        MissingLabelName(l, c) => (*l, *c),
-        BadConstSyntax(l, c) => (*l, *c),
-    }
-}
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, Url};
+
+use chip8_asm::lexer::word_end;
+use chip8_asm::semantic;
+
+use crate::workspace::file_uri;
 
-pub fn translate(errors: &Option<Vec<chip8_asm::AssemblyError>>, _uri: &Url) -> Vec<Diagnostic> {
-    let Some(errs) = errors else { return Vec::new() };
-    errs.iter().map(|e| {
-        let line = e.line as u32;
-        let col = e.col as u32;
-        Diagnostic {
-            range: Range {
-                start: Position { line, character: col },
-                end: Position { line, character: col + 1 },
-            },
+/// Groups errors by the file they were reported in: errors in the buffer
+/// itself go to `root`, errors inside included files go to that file's URI.
+/// Errors in the buffer span the word they point at, looked up in `source`.
+pub fn by_file(errors: &[chip8_asm::AssemblyError], root: &Url, source: &str) -> HashMap<Url, Vec<Diagnostic>> {
+    let mut out: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+    for e in errors {
+        let uri = file_uri(e.file.as_deref().unwrap_or("<root>"), root);
+        let end = if uri == *root {
+            source.lines().nth(e.line).map_or(e.col + 1, |text| word_end(text, e.col))
+        } else {
+            e.col + 1
+        };
+        out.entry(uri).or_default().push(Diagnostic {
+            range: range(e.line, e.col, end),
             severity: Some(DiagnosticSeverity::ERROR),
             message: e.message.clone(),
             source: Some("chip8-asm".into()),
             ..Default::default()
-        }
-    }).collect()
+        });
+    }
+    out
+}
+
+/// Same as [`by_file`] for the positioned results of `semantic::check` and
+/// `lint::lint`. Lint findings carry their ID as the diagnostic code.
+pub fn semantic_by_file(diags: &[semantic::Diagnostic], root: &Url) -> HashMap<Url, Vec<Diagnostic>> {
+    let mut out: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+    for d in diags {
+        let severity = match d.severity {
+            semantic::Severity::Error => DiagnosticSeverity::ERROR,
+            semantic::Severity::Warning => DiagnosticSeverity::WARNING,
+        };
+        out.entry(file_uri(&d.file, root)).or_default().push(Diagnostic {
+            range: range(d.line, d.col, d.end_col),
+            severity: Some(severity),
+            code: d.code.map(|id| NumberOrString::String(id.into())),
+            message: d.message.clone(),
+            source: Some("chip8-asm".into()),
+            ..Default::default()
+        });
+    }
+    out
+}
+
+fn range(line: usize, col: usize, end_col: usize) -> Range {
+    let line = line as u32;
+    Range {
+        start: Position { line, character: col as u32 },
+        end: Position { line, character: end_col.max(col + 1) as u32 },
+    }
 }
@@ -1,8 +1,9 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use chip8_asm::lexer::Token;
-use chip8_asm::parser::Statement;
-use chip8_asm::sourcemap::SourceMap;
+use tower_lsp::lsp_types::{Position, TextDocumentContentChangeEvent};
+
+use chip8_asm::lexer::{self, Token};
 use chip8_asm::symbol::SymbolTable;
 use chip8_asm::AssemblyError;
 use chip8_asm::AnalysisResult;
@@ -12,11 +13,108 @@ pub struct Document {
     pub path: PathBuf,
     pub source: String,
     pub base_dir: PathBuf,
-    pub statements: Option<Vec<Statement>>,
+    /// Tokens of the buffer itself, so positions match the editor. Kept up
+    /// to date line by line as edits come in.
     pub tokens: Option<Vec<(Token, usize, usize)>>,
-    pub symbol_table: Option<SymbolTable>,
-    pub source_map: Option<SourceMap>,
-    pub addresses: Option<Vec<u16>>,
     pub errors: Option<Vec<AssemblyError>>,
-    pub analysis: Option<AnalysisResult>,
+    /// Shared with the workspace analysis cache.
+    pub analysis: Option<Arc<AnalysisResult>>,
+}
+
+impl Document {
+    pub fn new(path: PathBuf, source: String) -> Self {
+        let base_dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        Document {
+            tokens: Some(lexer::tokenize(&source)),
+            path,
+            source,
+            base_dir,
+            errors: None,
+            analysis: None,
+        }
+    }
+
+    pub fn symbol_table(&self) -> Option<&SymbolTable> {
+        self.analysis.as_ref().map(|a| &a.symbol_table)
+    }
+
+    /// Applies one `didChange` event. Ranged edits only re-lex the lines
+    /// they touch; a change without a range replaces the whole buffer.
+    pub fn apply_change(&mut self, change: TextDocumentContentChangeEvent) {
+        let Some(range) = change.range else {
+            self.source = change.text;
+            self.tokens = Some(lexer::tokenize(&self.source));
+            return;
+        };
+
+        let start = offset(&self.source, range.start);
+        let end = offset(&self.source, range.end).max(start);
+        let old_lines = self.source.lines().count();
+        self.source.replace_range(start..end, &change.text);
+        let new_lines = self.source.lines().count();
+
+        let first = range.start.line as usize;
+        let old_count = (range.end.line as usize + 1).min(old_lines).saturating_sub(first);
+        let added = change.text.matches('\n').count();
+        let new_count = (first + added + 1).min(new_lines).saturating_sub(first);
+        self.tokens = Some(match &self.tokens {
+            Some(tokens) => lexer::relex(tokens, &self.source, first, old_count, new_count),
+            None => lexer::tokenize(&self.source),
+        });
+    }
+}
+
+/// Byte offset of an LSP position (UTF-16 columns), clamped to the end of
+/// its line and of the text.
+fn offset(source: &str, pos: Position) -> usize {
+    let mut line_start = 0;
+    for (i, line) in source.split_inclusive('\n').enumerate() {
+        if i == pos.line as usize {
+            let content = line.trim_end_matches(['\n', '\r']);
+            let mut units = 0;
+            for (byte, ch) in content.char_indices() {
+                if units >= pos.character as usize {
+                    return line_start + byte;
+                }
+                units += ch.len_utf16();
+            }
+            return line_start + content.len();
+        }
+        line_start += line.len();
+    }
+    source.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position { line: start.0, character: start.1 },
+                end: Position { line: end.0, character: end.1 },
+            }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_incremental_edits() {
+        let mut doc = Document::new(PathBuf::from("/test.asm"), "start:\n    LD V0, 1\n    JP start\n".into());
+        let steps = [
+            edit((1, 11), (1, 12), "2"),
+            edit((2, 0), (2, 0), "    ADD V0, 1\n"),
+            edit((0, 6), (1, 4), " "),
+            edit((2, 12), (2, 12), "\n; — done"),
+            edit((3, 4), (3, 4), "ü"),
+        ];
+        for step in steps {
+            doc.apply_change(step);
+            assert_eq!(doc.tokens.as_ref().unwrap(), &lexer::tokenize(&doc.source));
+        }
+        assert_eq!(doc.source, "start: LD V0, 2\n    ADD V0, 1\n    JP start\n; — üdone\n");
+    }
 }
@@ -0,0 +1,56 @@
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::Token;
+
+use crate::workspace::Workspace;
+
+/// Links the path of every `include "file"` line to the file it resolves to.
+pub fn document_links(ws: &Workspace, uri: &Url) -> Option<Vec<DocumentLink>> {
+    let doc = ws.get_document(uri)?;
+    let tokens = doc.tokens.as_deref()?;
+    let includes = ws.include_graph.get(&doc.path)?;
+    let mut links = Vec::new();
+
+    for (i, window) in tokens.windows(2).enumerate() {
+        let [(Token::Word(w), line, _), (Token::String(file), _, col)] = window else { continue };
+        let at_line_start = i == 0 || matches!(tokens[i - 1].0, Token::Newline);
+        if !at_line_start || !w.eq_ignore_ascii_case("include") {
+            continue;
+        }
+        let Some(target) = includes.iter().find(|p| p.ends_with(file)) else { continue };
+        let Ok(target_uri) = Url::from_file_path(target) else { continue };
+        links.push(DocumentLink {
+            range: Range {
+                start: Position { line: *line as u32, character: *col as u32 },
+                end: Position { line: *line as u32, character: (col + file.len()) as u32 },
+            },
+            target: Some(target_uri),
+            tooltip: Some(target.display().to_string()),
+            data: None,
+        });
+    }
+    Some(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_include_links() {
+        let source = "include \"lib/sprites.asm\"\nstart:\n    JP start ; include \"no.asm\"\n";
+        let uri = Url::parse("file:///game/main.asm").unwrap();
+        let path = PathBuf::from("/game/main.asm");
+        let mut ws = Workspace::new();
+        ws.index_file(&path, source);
+        ws.documents.insert(uri.clone(), Document::new(path, source.to_string()));
+
+        let links = document_links(&ws, &uri).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].target.as_ref().unwrap().as_str(), "file:///game/lib/sprites.asm");
+        assert_eq!(links[0].range.start, Position { line: 0, character: 9 });
+        assert_eq!(links[0].range.end, Position { line: 0, character: 24 });
+    }
+}
@@ -0,0 +1,143 @@
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::Token;
+
+use crate::workspace::Workspace;
+
+/// Tokens of each buffer line, without the Newline/Eof markers.
+pub(crate) fn tokens_by_line(source: &str, tokens: &[(Token, usize, usize)]) -> Vec<Vec<(Token, usize)>> {
+    let mut by_line = vec![Vec::new(); source.lines().count()];
+    for (tok, line, col) in tokens {
+        if !matches!(tok, Token::Newline | Token::Eof) && *line < by_line.len() {
+            by_line[*line].push((tok.clone(), *col));
+        }
+    }
+    by_line
+}
+
+/// `(first, last)` lines of every top-level label's section: from the label
+/// to the last code line before the next label or declaration at column 0.
+pub(crate) fn label_sections(by_line: &[Vec<(Token, usize)>]) -> Vec<(usize, usize)> {
+    let mut sections = Vec::new();
+    let mut open: Option<(usize, usize)> = None;
+    let mut depth = 0usize;
+    for (line, tokens) in by_line.iter().enumerate() {
+        let boundary = depth == 0
+            && match tokens.as_slice() {
+                [(Token::Word(_), 0), (Token::Colon, _), ..] => true,
+                [(Token::Word(w), 0), ..] => matches!(w.as_str(), "macro" | "MACRO" | "struct" | "include"),
+                [(Token::Dot, 0), (Token::Word(w), _), ..] => matches!(w.to_lowercase().as_str(), "const" | "org"),
+                _ => false,
+            };
+        if boundary {
+            sections.extend(open.take().filter(|(first, last)| last > first));
+            if matches!(tokens.as_slice(), [_, (Token::Colon, _), ..]) {
+                open = Some((line, line));
+            }
+        }
+        // Comment-only and blank lines don't extend a section
+        if let Some((_, last)) = open.as_mut() {
+            if !tokens.is_empty() {
+                *last = line;
+            }
+        }
+        for (tok, _) in tokens {
+            match tok {
+                Token::LBrace => depth += 1,
+                Token::RBrace => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    sections.extend(open.filter(|(first, last)| last > first));
+    sections
+}
+
+pub fn folding_ranges(ws: &Workspace, uri: &Url) -> Option<Vec<FoldingRange>> {
+    let doc = ws.get_document(uri)?;
+    let tokens = doc.tokens.as_deref()?;
+    let by_line = tokens_by_line(&doc.source, tokens);
+    let mut ranges = Vec::new();
+
+    // `macro` and `struct` bodies, up to the line before the closing brace
+    let mut stack: Vec<Option<usize>> = Vec::new();
+    let mut pending: Option<usize> = None;
+    for (line, tokens) in by_line.iter().enumerate() {
+        if let Some((Token::Word(w), _)) = tokens.first() {
+            if matches!(w.as_str(), "macro" | "MACRO" | "struct") {
+                pending = Some(line);
+            }
+        }
+        for (tok, _) in tokens {
+            match tok {
+                Token::LBrace => stack.push(pending.take()),
+                Token::RBrace => {
+                    if let Some(Some(start)) = stack.pop() {
+                        if line > start + 1 {
+                            ranges.push(fold(start, line - 1));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for (first, last) in label_sections(&by_line) {
+        ranges.push(fold(first, last));
+    }
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    Some(ranges)
+}
+
+fn fold(start: usize, end: usize) -> FoldingRange {
+    FoldingRange {
+        start_line: start as u32,
+        start_character: None,
+        end_line: end as u32,
+        end_character: None,
+        kind: Some(FoldingRangeKind::Region),
+        collapsed_text: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_macro_struct_and_label_folds() {
+        let source = "\
+macro twice r {
+    ADD r, 1
+    ADD r, 1
+}
+struct Pos {
+    x byte
+    y byte
+}
+
+start:
+    CLS
+    JP start
+
+; ── Data ──
+sprite:
+.byte 0x80, 0x40
+.byte 0x20
+";
+        let uri = Url::parse("file:///test.asm").unwrap();
+        let mut ws = Workspace::new();
+        ws.documents.insert(uri.clone(), Document::new(PathBuf::from("/test.asm"), source.to_string()));
+
+        let folds: Vec<(u32, u32)> = folding_ranges(&ws, &uri)
+            .unwrap()
+            .iter()
+            .map(|r| (r.start_line, r.end_line))
+            .collect();
+        // the section ends before the separator comment
+        assert_eq!(folds, [(0, 2), (4, 6), (9, 11), (14, 16)]);
+    }
+}
@@ -0,0 +1,32 @@
+use tower_lsp::lsp_types::*;
+
+use crate::workspace::Workspace;
+
+/// Formats a document, or only the lines `range` touches. The formatter keeps
+/// lines one-to-one, so each changed line becomes its own edit.
+pub fn format_document(ws: &Workspace, uri: &Url, range: Option<Range>) -> Option<Vec<TextEdit>> {
+    let doc = ws.get_document(uri)?;
+    let formatted = chip8_asm::format::format(&doc.source);
+    let (first, last) = match range {
+        // A selection ending at column 0 doesn't include that line
+        Some(r) if r.end.character == 0 && r.end.line > r.start.line => (r.start.line, r.end.line - 1),
+        Some(r) => (r.start.line, r.end.line),
+        None => (0, u32::MAX),
+    };
+
+    let edits = doc
+        .source
+        .lines()
+        .zip(formatted.lines())
+        .enumerate()
+        .filter(|(i, (old, new))| old != new && (first..=last).contains(&(*i as u32)))
+        .map(|(i, (old, new))| TextEdit {
+            range: Range {
+                start: Position { line: i as u32, character: 0 },
+                end: Position { line: i as u32, character: old.encode_utf16().count() as u32 },
+            },
+            new_text: new.to_string(),
+        })
+        .collect();
+    Some(edits)
+}
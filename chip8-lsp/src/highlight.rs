@@ -41,7 +41,9 @@ fn classify_token(word: &str) -> (SemanticTokenType, Vec<SemanticTokenModifier>)
     match upper.as_str() {
         "CLS" | "RET" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR"
         | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP"
-        | "SKNP" => (SemanticTokenType::OPERATOR, vec![]),
+        | "SKNP" | "NOT" | "NEG" | "INC" | "DEC" | "JEQ" | "JNE" => {
+            (SemanticTokenType::OPERATOR, vec![])
+        }
 
         "V0" | "V1" | "V2" | "V3" | "V4" | "V5" | "V6" | "V7"
         | "V8" | "V9" | "VA" | "VB" | "VC" | "VD" | "VE" | "VF" => {
@@ -52,13 +54,17 @@ fn classify_token(word: &str) -> (SemanticTokenType, Vec<SemanticTokenModifier>)
         "DT" | "ST" => (SemanticTokenType::VARIABLE, vec![SemanticTokenModifier::READONLY]),
         "K" | "F" | "B" => (SemanticTokenType::VARIABLE, vec![]),
 
-        "ORG" | "BYTE" | "WORD" | "ASCII" | "ASCIZ" | "ALIGN" | "SPACE" => {
+        "ORG" | "BYTE" | "WORD" | "ASCII" | "ASCIZ" | "ALIGN" | "SPACE"
+        | "REPT" | "ENDR" | "FOR" | "ENDFOR" | "INCBIN" | "IMAGE" | "SPRITE" => {
             (SemanticTokenType::KEYWORD, vec![])
         }
 
         "CONST" => (SemanticTokenType::KEYWORD, vec![SemanticTokenModifier::MODIFICATION]),
         "STRUCT" => (SemanticTokenType::KEYWORD, vec![SemanticTokenModifier::DECLARATION]),
         "MACRO" | "INCLUDE" => (SemanticTokenType::KEYWORD, vec![]),
+        "IF" | "ELSE" | "WHILE" | "LOOP" | "BREAK" | "CONTINUE" => {
+            (SemanticTokenType::KEYWORD, vec![])
+        }
 
         _ => (SemanticTokenType::FUNCTION, vec![]),
     }
@@ -3,7 +3,7 @@ use tower_lsp::lsp_types::*;
 use crate::workspace::Workspace;
 use chip8_asm::lexer::Token;
 
-fn find_token<'a>(tokens: &'a [(Token, usize, usize)], line: u32, col: u32) -> Option<(&'a Token, usize, usize)> {
+fn find_token(tokens: &[(Token, usize, usize)], line: u32, col: u32) -> Option<(&Token, usize, usize)> {
     for (tok, l, c) in tokens {
         let end = c + match tok {
             Token::Word(w) => w.len(),
@@ -18,20 +18,20 @@ fn find_token<'a>(tokens: &'a [(Token, usize, usize)], line: u32, col: u32) -> O
     None
 }
 
-fn instr_doc(mnemonic: &str) -> Option<&'static str> {
+pub(crate) fn instr_doc(mnemonic: &str) -> Option<&'static str> {
     Some(match mnemonic.to_uppercase().as_str() {
         "CLS" => "Clear the display.\n\n`00E0`",
         "RET" => "Return from subroutine.\n\n`00EE`",
         "JP" => "Jump to address.\n\n`1NNN` — Set PC = NNN\n\n`BNNN` — Jump to NNN + V0",
-        "CALL" => "Call subroutine at address.\n\n`2NNN` — Push PC, set PC = NNN",
+        "CALL" => "Call subroutine at address.\n\n`2NNN` — Push PC, set PC = NNN\n\nPseudo: `CALL addr, Vx, [save]` — Save V0..Vx to `save`, call, restore V0..Vx",
         "SE" => "Skip if equal.\n\n`3XKK` — Skip if Vx == KK\n`5XY0` — Skip if Vx == Vy",
         "SNE" => "Skip if not equal.\n\n`4XKK` — Skip if Vx != KK\n`9XY0` — Skip if Vx != Vy",
-        "LD" => "Load value.\n\n`6XKK` — Vx = KK\n`8XY0` — Vx = Vy\n`ANNN` — I = NNN\n`FX07` — Vx = DT\n`FX0A` — Wait key, Vx = key\n`FX15` — DT = Vx\n`FX18` — ST = Vx\n`FX29` — I = sprite(Vx)\n`FX33` — BCD of Vx\n`FX55` — Save V0..Vx\n`FX65` — Load V0..Vx",
+        "LD" => "Load value.\n\n`6XKK` — Vx = KK\n`8XY0` — Vx = Vy\n`ANNN` — I = NNN\n`FX07` — Vx = DT\n`FX0A` — Wait key, Vx = key\n`FX15` — DT = Vx\n`FX18` — ST = Vx\n`FX29` — I = sprite(Vx)\n`FX33` — BCD of Vx\n`FX55` — Save V0..Vx\n`FX65` — Load V0..Vx\n\nPseudo: `LD V0..Vx, [addr]` — I = addr, load V0..Vx\nPseudo: `LD [addr], V0..Vx` — I = addr, save V0..Vx\n\nThe pseudos leave I pointing at `addr`; use `V0` alone for a single register",
         "ADD" => "Add.\n\n`7XKK` — Vx += KK\n`8XY4` — Vx += Vy, VF = carry\n`FX1E` — I += Vx",
         "OR" => "Bitwise OR.\n\n`8XY1` — Vx |= Vy",
         "AND" => "Bitwise AND.\n\n`8XY2` — Vx &= Vy",
         "XOR" => "Bitwise XOR.\n\n`8XY3` — Vx ^= Vy",
-        "SUB" => "Subtract.\n\n`8XY5` — Vx -= Vy, VF = not borrow",
+        "SUB" => "Subtract.\n\n`8XY5` — Vx -= Vy, VF = not borrow\n\nPseudo: `SUB Vx, #KK` — `ADD Vx, -KK`",
         "SUBN" => "Reverse subtract.\n\n`8XY7` — Vx = Vy - Vx, VF = not borrow",
         "SHR" => "Shift right.\n\n`8XY6` — Vx >>= 1, VF = LSB",
         "SHL" => "Shift left.\n\n`8XYE` — Vx <<= 1, VF = MSB",
@@ -39,6 +39,12 @@ fn instr_doc(mnemonic: &str) -> Option<&'static str> {
         "DRW" => "Draw sprite.\n\n`DXYN` — Draw N-byte sprite at (Vx, Vy), VF = collision",
         "SKP" => "Skip if key pressed.\n\n`EX9E` — Skip if key Vx is pressed",
         "SKNP" => "Skip if key not pressed.\n\n`EXA1` — Skip if key Vx is not pressed",
+        "NOT" => "Bitwise NOT (pseudo).\n\n`LD VF, 0xFF` + `XOR Vx, VF` — Clobbers VF",
+        "NEG" => "Two's complement negate (pseudo).\n\n`LD VF, 0` + `SUBN Vx, VF` — Vx = -Vx, clobbers VF",
+        "INC" => "Increment (pseudo).\n\n`ADD Vx, 1` — VF unchanged",
+        "DEC" => "Decrement (pseudo).\n\n`ADD Vx, 0xFF` — VF unchanged",
+        "JEQ" => "Jump if equal (pseudo).\n\n`JEQ Vx, KK, addr` — `SNE Vx, KK` + `JP addr`\n`JEQ Vx, Vy, addr` — `SNE Vx, Vy` + `JP addr`",
+        "JNE" => "Jump if not equal (pseudo).\n\n`JNE Vx, KK, addr` — `SE Vx, KK` + `JP addr`\n`JNE Vx, Vy, addr` — `SE Vx, Vy` + `JP addr`",
         _ => return None,
     })
 }
@@ -67,6 +73,19 @@ fn dir_doc(name: &str) -> Option<&'static str> {
         "align" => "Pad with zeros to alignment boundary.\n\n`.align N` — Pad to next N-byte boundary",
         "space" => "Reserve N bytes of zero.\n\n`.space N`",
         "const" => "Define a numeric constant.\n\n`.const NAME = VALUE` — Constant usable as immediate operand",
+        "incbin" => "Include a binary file as raw bytes.\n\n`.incbin \"file\" [, offset [, length]]` — Path is relative to the current file",
+        "image" => "Import a monochrome PBM/PGM image as sprite rows.\n\n`.image \"file.pbm\" [, w, h]` — Slices the image into w×h sprites (w a multiple of 8), left to right then top to bottom",
+        "sprite" => "Emit sprite bitmap bytes from ASCII-art rows.\n\n`.sprite \"..##..##\", \"##..##..\"` — `#` lit, `.` dark; rows are 8 or 16 pixels wide",
+        "rept" => "Repeat a block of lines.\n\n```\n.rept N\n  ...\n.endr\n```\n`%%label` inside the block is unique per repetition.",
+        "for" => "Unroll a block for each value of a loop variable.\n\n```\n.for i = FROM to TO\n  .byte i * 8\n.endfor\n```\nThe variable can be used in constant expressions inside the block.",
+        "endr" => "End of a `.rept` block.",
+        "endfor" => "End of a `.for` block.",
+        "if" => "Structured conditional.\n\n```\nif Vx == KK {\n  ...\n} else {\n  ...\n}\n```\nCompares with `==` or `!=` against a byte or register; lowers to `SE`/`SNE` + `JP`.",
+        "else" => "Alternative branch of an `if` block, written `} else {`.",
+        "while" => "Structured loop.\n\n```\nwhile Vx != Vy {\n  ...\n}\n```\nTests the condition before each pass; lowers to `SE`/`SNE` + `JP`.",
+        "loop" => "Endless loop.\n\n```\nloop {\n  ...\n  break\n}\n```\nLeave with `break`, restart with `continue`.",
+        "break" => "Jump past the end of the innermost `while` or `loop` block.",
+        "continue" => "Jump back to the start of the innermost `while` or `loop` block.",
         "struct" => "Define a structure layout.\n\n```\nstruct Name {\n  field1 byte\n  field2 word\n}\n```\nGenerates Name.field1, Name.field2, Name.SIZE constants.",
         _ => return None,
     })
@@ -76,7 +95,7 @@ pub fn get_hover(ws: &Workspace, uri: &Url, pos: Position) -> Option<Hover> {
     let doc = ws.get_document(uri)?;
     let tokens = doc.tokens.as_ref()?;
     let analysis = doc.analysis.as_ref()?;
-    let sym = doc.symbol_table.as_ref()?;
+    let sym = doc.symbol_table()?;
 
     let (tok, _line, _col) = find_token(tokens, pos.line, pos.character)?;
 
@@ -100,8 +119,8 @@ pub fn get_hover(ws: &Workspace, uri: &Url, pos: Position) -> Option<Hover> {
                 });
             }
 
-            // Check directives and keywords
-            if let Some(doc) = dir_doc(&upper) {
+            // Check directives and keywords (a symbol named `loop` wins)
+            if let Some(doc) = dir_doc(&upper).filter(|_| sym.resolve(w).is_none()) {
                 return Some(Hover {
                     contents: HoverContents::Scalar(MarkedString::String(doc.to_string())),
                     range: None,
@@ -110,18 +129,24 @@ pub fn get_hover(ws: &Workspace, uri: &Url, pos: Position) -> Option<Hover> {
 
             // Check symbols (labels and constants)
             if let Some(val) = sym.resolve(w) {
-                let kind = if analysis.source.contains(&format!("\n{}:", w))
-                    || analysis.source.contains(&format!("{}:", w))
-                    || analysis.source.contains(&format!("{} LABEL", w))
-                {
+                let kind = if sym.labels().any(|(name, _)| name == w) {
                     "Label"
                 } else {
                     "Constant"
                 };
+                let mut text = format!("**{}** `{}`\n\nValue: `0x{:03X}` ({})", kind, w, val, val);
+                // Point at the defining file when the symbol comes from an include
+                if let Some((file, line, _)) = crate::definition::find_definition(analysis, w) {
+                    if file != "<root>" && std::path::Path::new(file) != doc.path {
+                        let name = std::path::Path::new(file)
+                            .file_name()
+                            .map(|n| n.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| file.to_string());
+                        text.push_str(&format!("\n\nDefined in `{}:{}`", name, line + 1));
+                    }
+                }
                 return Some(Hover {
-                    contents: HoverContents::Scalar(MarkedString::String(
-                        format!("**{}** `{}`\n\nValue: `0x{:03X}` ({})", kind, w, val, val)
-                    )),
+                    contents: HoverContents::Scalar(MarkedString::String(text)),
                     range: None,
                 });
             }
@@ -148,6 +173,7 @@ mod tests {
     use crate::workspace::Workspace;
     use crate::document::Document;
     use std::path::PathBuf;
+    use std::sync::Arc;
 
     #[test]
     fn test_find_token_works() {
@@ -189,18 +215,9 @@ mod tests {
         let path = PathBuf::from("/test.asm");
 
         let mut ws = Workspace::new();
-        ws.documents.insert(uri.clone(), Document {
-            path: path.clone(),
-            source: source.to_string(),
-            base_dir: PathBuf::from("/"),
-            statements: Some(analysis.statements.clone()),
-            tokens: Some(analysis.tokens.clone()),
-            symbol_table: Some(analysis.symbol_table.clone()),
-            source_map: Some(analysis.source_map.clone()),
-            addresses: Some(analysis.addresses.clone()),
-            errors: None,
-            analysis: Some(analysis),
-        });
+        let mut doc = Document::new(path.clone(), source.to_string());
+        doc.analysis = Some(Arc::new(analysis));
+        ws.documents.insert(uri.clone(), doc);
 
         let result = get_hover(&ws, &uri, Position { line: 1, character: 5 });
         assert!(result.is_some(), "hover CLS should return Some");
@@ -214,4 +231,11 @@ mod tests {
             assert!(has_cls, "hover should contain 'Clear'");
         }
     }
+
+    #[test]
+    fn test_pseudo_instruction_docs() {
+        assert!(instr_doc("inc").unwrap().contains("ADD Vx, 1"));
+        assert!(instr_doc("JEQ").unwrap().contains("SNE"));
+        assert!(instr_doc("LD").unwrap().contains("[addr]"));
+    }
 }
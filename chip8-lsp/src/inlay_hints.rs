@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::Token;
+use chip8_asm::parser::Statement;
+
+use crate::workspace::Workspace;
+
+/// Which inlay hints to show, from the `inlayHints` initialization option:
+/// `{"inlayHints": {"addresses": true, "opcodes": true, "values": false}}`.
+/// Everything is on by default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct InlayHintConfig {
+    /// Address of the first instruction at the end of each instruction line.
+    pub addresses: bool,
+    /// Encoded opcodes at the end of each instruction line.
+    pub opcodes: bool,
+    /// Resolved value after each symbolic operand.
+    pub values: bool,
+}
+
+impl Default for InlayHintConfig {
+    fn default() -> Self {
+        InlayHintConfig {
+            addresses: true,
+            opcodes: true,
+            values: true,
+        }
+    }
+}
+
+impl InlayHintConfig {
+    /// Reads the config from `initializationOptions`, keeping the defaults
+    /// for anything missing or malformed.
+    pub fn from_init_options(options: Option<&serde_json::Value>) -> Self {
+        options
+            .and_then(|o| o.get("inlayHints"))
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Opcodes shown per line before the rest are elided (long macro expansions).
+const MAX_OPCODES: usize = 4;
+
+pub fn inlay_hints(ws: &Workspace, uri: &Url, range: Range) -> Option<Vec<InlayHint>> {
+    let doc = ws.get_document(uri)?;
+    let analysis = doc.analysis.as_ref()?;
+    let config = &ws.inlay_hints;
+    let lines: Vec<&str> = doc.source.lines().collect();
+    let visible = |line: usize| (range.start.line as usize..=range.end.line as usize).contains(&line);
+    let mut hints = Vec::new();
+
+    if config.addresses || config.opcodes {
+        // Buffer line -> address of its first instruction and all opcodes;
+        // a macro invocation collects everything it expands to
+        let mut per_line: BTreeMap<usize, (u16, Vec<[u8; 2]>)> = BTreeMap::new();
+        for (idx, stmt) in analysis.statements.iter().enumerate() {
+            let insts = match stmt {
+                Statement::Inst(inst) => std::slice::from_ref(inst),
+                Statement::Pseudo { insts, .. } => insts.as_slice(),
+                _ => continue,
+            };
+            let Some(&expanded) = analysis.statement_lines.get(idx) else { continue };
+            let (file, line) = analysis.source_map.resolve(expanded);
+            if !is_buffer(file, &doc.path) || !visible(line) {
+                continue;
+            }
+            let Some(&addr) = analysis.addresses.get(idx) else { continue };
+            let entry = per_line.entry(line).or_insert((addr, Vec::new()));
+            for inst in insts {
+                // Undefined symbols are already reported as diagnostics
+                entry.1.push(chip8_asm::encode_inst(inst, &analysis.symbol_table).unwrap_or([0, 0]));
+            }
+        }
+
+        for (line, (addr, opcodes)) in per_line {
+            let mut parts = Vec::new();
+            if config.addresses {
+                parts.push(format!("0x{:03X}", addr));
+            }
+            if config.opcodes {
+                let mut ops: Vec<String> = opcodes
+                    .iter()
+                    .take(MAX_OPCODES)
+                    .map(|b| format!("{:02X}{:02X}", b[0], b[1]))
+                    .collect();
+                if opcodes.len() > MAX_OPCODES {
+                    ops.push("…".into());
+                }
+                parts.push(ops.join(" "));
+            }
+            let text = lines.get(line).copied().unwrap_or("");
+            hints.push(hint(line, text.encode_utf16().count(), parts.join(": ")));
+        }
+    }
+
+    if config.values {
+        let tokens = doc.tokens.as_deref().unwrap_or(&[]);
+        for (i, (tok, line, col)) in tokens.iter().enumerate() {
+            let Token::Word(name) = tok else { continue };
+            if !visible(*line) || is_definition(tokens, i) {
+                continue;
+            }
+            let Some(value) = analysis.symbol_table.resolve(name) else { continue };
+            let label = if analysis.symbol_table.labels().any(|(l, _)| l == name) {
+                format!("= 0x{:03X}", value)
+            } else {
+                format!("= {}", value)
+            };
+            hints.push(hint(*line, col + name.len(), label));
+        }
+    }
+
+    hints.sort_by_key(|h| (h.position.line, h.position.character));
+    Some(hints)
+}
+
+fn hint(line: usize, character: usize, label: String) -> InlayHint {
+    InlayHint {
+        position: Position { line: line as u32, character: character as u32 },
+        label: InlayHintLabel::String(label),
+        kind: None,
+        text_edits: None,
+        tooltip: None,
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    }
+}
+
+fn is_buffer(file: &str, path: &std::path::Path) -> bool {
+    file == "<root>" || std::path::Path::new(file) == path
+}
+
+/// A label definition (`name:`) or the name in `.const NAME = ...`.
+fn is_definition(tokens: &[(Token, usize, usize)], i: usize) -> bool {
+    let next_is_colon = matches!(tokens.get(i + 1), Some((Token::Colon, ..)));
+    let after_const = i >= 2
+        && matches!(&tokens[i - 1].0, Token::Word(w) if w.eq_ignore_ascii_case("const"))
+        && tokens[i - 2].0 == Token::Dot;
+    next_is_colon || after_const
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use chip8_asm::analyze;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_operand_values() {
+        let source = ".const SPEED = 3\nstart:\n    ADD V0, SPEED\n    JP start\n";
+        let analysis = analyze(source).unwrap();
+        let uri = Url::parse("file:///test.asm").unwrap();
+        let mut ws = Workspace::new();
+        ws.inlay_hints = InlayHintConfig { addresses: false, opcodes: false, values: true };
+        let mut doc = Document::new(PathBuf::from("/test.asm"), source.to_string());
+        doc.analysis = Some(Arc::new(analysis));
+        ws.documents.insert(uri.clone(), doc);
+
+        let all = Range { start: Position { line: 0, character: 0 }, end: Position { line: 9, character: 0 } };
+        let hints = inlay_hints(&ws, &uri, all).unwrap();
+        let found: Vec<(u32, u32, String)> = hints
+            .iter()
+            .map(|h| match &h.label {
+                InlayHintLabel::String(s) => (h.position.line, h.position.character, s.clone()),
+                _ => unreachable!(),
+            })
+            .collect();
+        // definitions get no hint
+        assert_eq!(found, [(2, 17, "= 3".to_string()), (3, 12, "= 0x200".to_string())]);
+    }
+
+    #[test]
+    fn test_config_from_init_options() {
+        let opts = serde_json::json!({ "inlayHints": { "opcodes": false } });
+        let config = InlayHintConfig::from_init_options(Some(&opts));
+        assert!(config.addresses && !config.opcodes && config.values);
+        assert!(InlayHintConfig::from_init_options(None).opcodes);
+    }
+}
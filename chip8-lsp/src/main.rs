@@ -9,6 +9,15 @@ mod definition;
 mod references;
 mod highlight;
 mod rename;
+mod formatting;
+mod code_actions;
+mod inlay_hints;
+mod signature_help;
+mod folding_ranges;
+mod document_links;
+mod selection_ranges;
+mod call_hierarchy;
+mod code_lens;
 
 use server::LspServer;
 use tower_lsp::LspService;
@@ -18,7 +27,7 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| LspServer::new(client));
+    let (service, socket) = LspService::new(LspServer::new);
     tower_lsp::Server::new(stdin, stdout, socket)
         .serve(service)
         .await;
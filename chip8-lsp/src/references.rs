@@ -3,7 +3,7 @@ use tower_lsp::lsp_types::*;
 use crate::workspace::Workspace;
 use chip8_asm::lexer::Token;
 
-fn find_token_at<'a>(tokens: &'a [(Token, usize, usize)], line: u32, col: u32) -> Option<(&'a Token, usize, usize)> {
+fn find_token_at(tokens: &[(Token, usize, usize)], line: u32, col: u32) -> Option<(&Token, usize, usize)> {
     for (tok, l, c) in tokens {
         let end = c + match tok {
             Token::Word(w) => w.len(),
@@ -3,7 +3,7 @@ use tower_lsp::lsp_types::*;
 use crate::workspace::Workspace;
 use chip8_asm::lexer::Token;
 
-fn find_token_at<'a>(tokens: &'a [(Token, usize, usize)], line: u32, col: u32) -> Option<(&'a Token, usize, usize)> {
+fn find_token_at(tokens: &[(Token, usize, usize)], line: u32, col: u32) -> Option<(&Token, usize, usize)> {
     for (tok, l, c) in tokens {
         let end = c + match tok {
             Token::Word(w) => w.len(),
@@ -25,10 +25,8 @@ pub fn prepare_rename(ws: &Workspace, uri: &Url, pos: Position) -> Option<Prepar
 
     match tok {
         Token::Word(w) => {
-            let sym = doc.symbol_table.as_ref()?;
-            if sym.resolve(w).is_none() {
-                return None;
-            }
+            let sym = doc.symbol_table()?;
+            sym.resolve(w)?;
             let upper = w.to_uppercase();
             let is_instr = matches!(
                 upper.as_str(),
@@ -56,15 +54,13 @@ pub fn perform_rename(ws: &Workspace, uri: &Url, pos: Position, new_name: &str)
 
     match tok {
         Token::Word(w) => {
-            let sym = doc.symbol_table.as_ref()?;
-            if sym.resolve(w).is_none() {
-                return None;
-            }
+            let sym = doc.symbol_table()?;
+            sym.resolve(w)?;
 
             let target = w.to_uppercase();
             let mut changes: Vec<TextEdit> = Vec::new();
 
-            for (_doc_uri, other_doc) in &ws.documents {
+            for other_doc in ws.documents.values() {
                 let Some(other_tokens) = &other_doc.tokens else { continue };
 
                 for (tok, line, col) in other_tokens {
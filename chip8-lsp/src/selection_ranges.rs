@@ -0,0 +1,85 @@
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::{span, Token};
+
+use crate::folding_ranges::{label_sections, tokens_by_line};
+use crate::workspace::Workspace;
+
+/// For each position: the token under it, then the instruction on its line
+/// (without label and comment), then the label section it belongs to.
+pub fn selection_ranges(ws: &Workspace, uri: &Url, positions: &[Position]) -> Option<Vec<SelectionRange>> {
+    let doc = ws.get_document(uri)?;
+    let tokens = doc.tokens.as_deref()?;
+    let texts: Vec<&str> = doc.source.lines().collect();
+    let by_line = tokens_by_line(&doc.source, tokens);
+    let sections = label_sections(&by_line);
+
+    let ranges = positions
+        .iter()
+        .map(|&pos| {
+            let line = pos.line as usize;
+            let text = texts.get(line).copied().unwrap_or("");
+            let line_tokens = by_line.get(line).map(Vec::as_slice).unwrap_or(&[]);
+            let spans: Vec<(usize, usize)> = line_tokens.iter().map(|(t, c)| span(text, t, *c)).collect();
+
+            let mut nested = Vec::new();
+            let col = pos.character as usize;
+            if let Some(&(start, end)) = spans.iter().find(|(s, e)| (*s..=*e).contains(&col)) {
+                nested.push(range(line, start, line, end));
+            }
+            let body = match line_tokens {
+                [(Token::Word(_), _), (Token::Colon, _), ..] => 2,
+                _ => 0,
+            };
+            if let (Some(first), Some(last)) = (spans.get(body), spans.last()) {
+                if col >= first.0 {
+                    nested.push(range(line, first.0, line, last.1));
+                }
+            }
+            if let Some(&(first, last)) = sections.iter().find(|(f, l)| (*f..=*l).contains(&line)) {
+                let end = texts.get(last).map_or(0, |t| t.len());
+                nested.push(range(first, 0, last, end));
+            }
+            nested.dedup();
+
+            // Innermost first, each one pointing at the range around it
+            let mut parent: Option<Box<SelectionRange>> = None;
+            for r in nested.into_iter().rev() {
+                parent = Some(Box::new(SelectionRange { range: r, parent }));
+            }
+            parent.map(|b| *b).unwrap_or(SelectionRange { range: range(line, col, line, col), parent: None })
+        })
+        .collect();
+    Some(ranges)
+}
+
+fn range(start_line: usize, start: usize, end_line: usize, end: usize) -> Range {
+    Range {
+        start: Position { line: start_line as u32, character: start as u32 },
+        end: Position { line: end_line as u32, character: end as u32 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_operand_instruction_section() {
+        let source = "start:\n    LD V0, 0x10 ; init\n    JP start\n";
+        let uri = Url::parse("file:///test.asm").unwrap();
+        let mut ws = Workspace::new();
+        ws.documents.insert(uri.clone(), Document::new(PathBuf::from("/test.asm"), source.to_string()));
+
+        let ranges = selection_ranges(&ws, &uri, &[Position { line: 1, character: 13 }]).unwrap();
+        let mut chain = Vec::new();
+        let mut current = Some(&ranges[0]);
+        while let Some(r) = current {
+            chain.push((r.range.start.line, r.range.start.character, r.range.end.line, r.range.end.character));
+            current = r.parent.as_deref();
+        }
+        assert_eq!(chain, [(1, 11, 1, 15), (1, 4, 1, 15), (0, 0, 2, 12)]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use tower_lsp::jsonrpc::Result;
@@ -6,16 +6,25 @@ use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 use tokio::sync::RwLock;
 
+use crate::call_hierarchy;
+use crate::code_actions;
+use crate::code_lens;
 use crate::completion;
 use crate::definition;
 use crate::diagnostics;
+use crate::formatting;
 use crate::document::Document;
+use crate::document_links;
+use crate::folding_ranges;
 use crate::highlight;
 use crate::hover;
+use crate::inlay_hints::{self, InlayHintConfig};
 use crate::references;
 use crate::rename;
+use crate::selection_ranges;
+use crate::signature_help;
 use crate::symbols;
-use crate::workspace::Workspace;
+use crate::workspace::{content_hash, file_uri, CachedAnalysis, Workspace};
 
 pub struct LspServer {
     client: Client,
@@ -34,90 +43,142 @@ impl LspServer {
         let doc = {
             let ws = self.workspace.read().await;
             ws.documents.get(uri).map(|d| {
-                let path = d.path.clone();
-                let source = d.source.clone();
-                let base_dir = d.base_dir.clone();
-                (path, source, base_dir)
+                let includes = ws.include_graph.get(&d.path).cloned().unwrap_or_default();
+                let cached = ws.cached_analysis(&d.path, &d.source).cloned();
+                // Only a cache miss needs the tokens and the other buffers
+                let (tokens, files) = match cached {
+                    Some(_) => (None, Default::default()),
+                    None => (d.tokens.clone(), ws.overlay_files()),
+                };
+                (d.source.clone(), d.base_dir.clone(), tokens, includes, cached, files)
             })
         };
 
-        let Some((_path, source, base_dir)) = doc else { return };
+        let Some((source, base_dir, tokens, includes, cached, files)) = doc else { return };
+        let source = &source;
 
-        // Do partial analysis: tokenize first, then parse, then compute layout
-        // This way, even with errors we keep the partial state
-        let tokens = chip8_asm::lexer::tokenize(&source);
-
-        let mut errors = Vec::new();
-        let has_lex_errors: Vec<(String, usize, usize)> = tokens.iter()
-            .filter_map(|(t, l, c)| if let chip8_asm::lexer::Token::Error(s) = t {
-                Some((s.clone(), *l, *c))
-            } else { None })
-            .collect();
-
-        for (msg, line, col) in has_lex_errors {
-            errors.push(chip8_asm::AssemblyError::from_string(format!("lex error at {}:{}: {}", line + 1, col + 1, msg)));
-        }
-
-        let (statements, parse_errors) = match chip8_asm::parser::parse(&tokens) {
-            Ok(stmts) => (stmts, Vec::new()),
-            Err(parse_errs) => {
-                let errs: Vec<chip8_asm::AssemblyError> = parse_errs.iter().map(|e| {
-                    let (l, c) = crate::diagnostics::extract_parse_pos(e);
-                    chip8_asm::AssemblyError {
-                        message: e.to_string(),
-                        file: Some("<root>".into()),
-                        line: l,
-                        col: c,
+        let cached = match cached {
+            Some(cached) => cached,
+            None => {
+                // Run the full pipeline (includes, macros, loops, ...) with open
+                // buffers taking precedence over the files on disk
+                let opts = chip8_asm::AssemblyOptions {
+                    base_dir,
+                    files,
+                };
+                let (analysis, errors, semantic) = match chip8_asm::analyze_with(source, &opts) {
+                    Ok(analysis) => {
+                        let mut semantic = chip8_asm::semantic::check(&analysis);
+                        semantic.extend(chip8_asm::lint::lint(&analysis));
+                        (analysis, Vec::new(), semantic)
+                    }
+                    Err(errors) => {
+                        // Keep a best-effort view of the buffer alone so hover and
+                        // completion still work while the file doesn't assemble
+                        let tokens = tokens.unwrap_or_else(|| chip8_asm::lexer::tokenize(source));
+                        let (statements, statement_lines) =
+                            chip8_asm::parser::parse_with_lines(&tokens).unwrap_or_default();
+                        // Without a layout there are no addresses to pair with the
+                        // statements, so drop them rather than leave the two out of step
+                        let (statements, statement_lines, symbol_table, addresses) =
+                            match chip8_asm::compute_layout(&statements) {
+                                Ok((symbol_table, addresses)) if !statements.is_empty() => {
+                                    (statements, statement_lines, symbol_table, addresses)
+                                }
+                                _ => (Vec::new(), Vec::new(), Default::default(), Vec::new()),
+                            };
+                        let mut source_map = chip8_asm::sourcemap::SourceMap::new();
+                        for (i, _) in source.lines().enumerate() {
+                            source_map.add_line("<root>", i);
+                        }
+                        let analysis = chip8_asm::AnalysisResult {
+                            source: source.clone(),
+                            expanded_source: source.clone(),
+                            source_map,
+                            tokens,
+                            statements,
+                            statement_lines,
+                            addresses,
+                            symbol_table,
+                            macro_defs: Vec::new(),
+                        };
+                        (analysis, errors, Vec::new())
                     }
-                }).collect();
-                (Vec::new(), errs)
+                };
+                CachedAnalysis {
+                    hash: content_hash(source),
+                    analysis: Arc::new(analysis),
+                    errors,
+                    semantic,
+                }
             }
         };
 
-        errors.extend(parse_errors);
+        // Files whose diagnostics this analysis owns: the buffer itself plus
+        // included files that aren't open (open ones publish their own)
+        let mut targets: Vec<Url> = vec![uri.clone()];
+        let included = cached
+            .analysis
+            .source_map
+            .files()
+            .into_iter()
+            .map(|f| file_uri(f, uri))
+            .chain(includes.iter().filter_map(|p| Url::from_file_path(p).ok()));
+        for target in included {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
 
-        let (symbol_table, addresses) = if !statements.is_empty() {
-            chip8_asm::compute_layout(&statements).unwrap_or_default()
-        } else {
-            (Default::default(), Vec::new())
-        };
+        let mut by_file = diagnostics::by_file(&cached.errors, uri, source);
+        for (target, diags) in diagnostics::semantic_by_file(&cached.semantic, uri) {
+            by_file.entry(target).or_default().extend(diags);
+        }
 
         let mut ws = self.workspace.write().await;
-        let doc = ws.documents.get_mut(uri).unwrap();
-
-        doc.tokens = Some(tokens);
-        doc.statements = Some(statements);
-        doc.symbol_table = Some(symbol_table);
-        doc.addresses = Some(addresses);
-
-        let empty_errors: Vec<chip8_asm::AssemblyError> = Vec::new();
-        doc.errors = if errors.is_empty() { None } else { Some(errors) };
-        doc.analysis = doc.tokens.as_ref().map(|_| chip8_asm::AnalysisResult {
-            source: source.clone(),
-            expanded_source: source.clone(),
-            source_map: chip8_asm::sourcemap::SourceMap::new(),
-            tokens: doc.tokens.as_ref().unwrap().clone(),
-            statements: doc.statements.as_ref().unwrap_or(&Vec::new()).clone(),
-            addresses: doc.addresses.as_ref().unwrap_or(&Vec::new()).clone(),
-            symbol_table: doc.symbol_table.as_ref().unwrap().clone(),
-            macro_defs: Vec::new(),
-        });
-        doc.source_map = doc.analysis.as_ref().map(|a| a.source_map.clone());
+        targets.retain(|t| t == uri || !ws.is_open(t));
+        let Some(doc) = ws.documents.get_mut(uri) else { return };
+        // The buffer changed while this analysis ran; the newer one publishes
+        if doc.source != *source {
+            return;
+        }
+        doc.errors = if cached.errors.is_empty() { None } else { Some(cached.errors.clone()) };
+        doc.analysis = Some(cached.analysis.clone());
+        let path = doc.path.clone();
+        ws.analysis_cache.insert(path, cached);
 
-        let publish_errors = doc.errors.clone().unwrap_or(empty_errors);
-        let doc_uri = uri.clone();
         let client = self.client.clone();
-
         tokio::spawn(async move {
-            let diags = diagnostics::translate(&Some(publish_errors), &doc_uri);
-            client.publish_diagnostics(doc_uri, diags, None).await;
+            for target in targets {
+                let diags = by_file.remove(&target).unwrap_or_default();
+                client.publish_diagnostics(target, diags, None).await;
+            }
         });
     }
+
+    /// Re-analyzes the open files among `paths` after something they include
+    /// changed.
+    async fn analyze_dependents(&self, paths: &[PathBuf]) {
+        let uris: Vec<Url> = {
+            let ws = self.workspace.read().await;
+            ws.documents
+                .iter()
+                .filter(|(_, d)| paths.contains(&d.path))
+                .map(|(uri, _)| uri.clone())
+                .collect()
+        };
+        for uri in uris {
+            self.analyze_file(&uri).await;
+        }
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for LspServer {
-    async fn initialize(&self, _params: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        self.workspace.write().await.inlay_hints =
+            InlayHintConfig::from_init_options(params.initialization_options.as_ref());
+
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
                 name: "chip8-lsp".into(),
@@ -125,7 +186,7 @@ impl LanguageServer for LspServer {
             }),
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 completion_provider: Some(CompletionOptions {
@@ -148,6 +209,31 @@ impl LanguageServer for LspServer {
                     }),
                 ),
                 rename_provider: Some(OneOf::Left(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec![" ".into(), ",".into()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions { resolve_provider: Some(false) }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                    code_action_kinds: Some(vec![
+                        CodeActionKind::QUICKFIX,
+                        CodeActionKind::REFACTOR_EXTRACT,
+                        CodeActionKind::REFACTOR_INLINE,
+                        CodeActionKind::REFACTOR_REWRITE,
+                    ]),
+                    ..Default::default()
+                })),
                 ..Default::default()
             },
         })
@@ -165,64 +251,37 @@ impl LanguageServer for LspServer {
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let uri = params.text_document.uri;
-        let source = params.text_document.text;
-
         let path = uri.to_file_path().unwrap_or_default();
-        let base_dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
 
-        {
+        let dependents = {
             let mut ws = self.workspace.write().await;
-            ws.documents.insert(
-                uri.clone(),
-                Document {
-                    path: path.clone(),
-                    source: source.clone(),
-                    base_dir,
-                    statements: None,
-                    tokens: None,
-                    symbol_table: None,
-                    source_map: None,
-                    addresses: None,
-                    errors: None,
-                    analysis: None,
-                },
-            );
-            ws.index_file(&path, &source);
-        }
+            let doc = Document::new(path.clone(), params.text_document.text);
+            ws.index_file(&path, &doc.source);
+            ws.documents.insert(uri.clone(), doc);
+            // The buffer now shadows the file on disk for everything including it
+            ws.invalidate_dependents(&path)
+        };
 
         self.analyze_file(&uri).await;
+        self.analyze_dependents(&dependents).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
-        let source = params.content_changes.into_iter().next()
-            .map(|c| c.text)
-            .unwrap_or_default();
 
-        let path = uri.to_file_path().unwrap_or_default();
-        let base_dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
-
-        {
+        let dependents = {
             let mut ws = self.workspace.write().await;
-            ws.documents.insert(
-                uri.clone(),
-                Document {
-                    path: path.clone(),
-                    source: source.clone(),
-                    base_dir,
-                    statements: None,
-                    tokens: None,
-                    symbol_table: None,
-                    source_map: None,
-                    addresses: None,
-                    errors: None,
-                    analysis: None,
-                },
-            );
+            let Some(doc) = ws.documents.get_mut(&uri) else { return };
+            for change in params.content_changes {
+                doc.apply_change(change);
+            }
+            let (path, source) = (doc.path.clone(), doc.source.clone());
             ws.index_file(&path, &source);
-        }
+            ws.invalidate_dependents(&path)
+        };
 
         self.analyze_file(&uri).await;
+        self.analyze_dependents(&dependents).await;
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
@@ -235,8 +294,15 @@ impl LanguageServer for LspServer {
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         let uri = params.text_document.uri;
-        let mut ws = self.workspace.write().await;
-        ws.documents.remove(&uri);
+        let dependents = {
+            let mut ws = self.workspace.write().await;
+            match ws.documents.remove(&uri) {
+                // Files including it go back to reading it from disk
+                Some(doc) => ws.invalidate_dependents(&doc.path),
+                None => Vec::new(),
+            }
+        };
+        self.analyze_dependents(&dependents).await;
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
@@ -310,4 +376,85 @@ impl LanguageServer for LspServer {
         let ws = self.workspace.read().await;
         Ok(rename::perform_rename(&ws, &uri, pos, &new_name))
     }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let ws = self.workspace.read().await;
+        Ok(formatting::format_document(&ws, &params.text_document.uri, None))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let ws = self.workspace.read().await;
+        Ok(formatting::format_document(&ws, &params.text_document.uri, Some(params.range)))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let ws = self.workspace.read().await;
+        Ok(code_actions::code_actions(
+            &ws,
+            &params.text_document.uri,
+            params.range,
+            &params.context,
+        ))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let ws = self.workspace.read().await;
+        Ok(inlay_hints::inlay_hints(&ws, &params.text_document.uri, params.range))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+        let ws = self.workspace.read().await;
+        Ok(signature_help::signature_help(&ws, &uri, pos))
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let ws = self.workspace.read().await;
+        Ok(folding_ranges::folding_ranges(&ws, &params.text_document.uri))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let ws = self.workspace.read().await;
+        Ok(document_links::document_links(&ws, &params.text_document.uri))
+    }
+
+    async fn selection_range(&self, params: SelectionRangeParams) -> Result<Option<Vec<SelectionRange>>> {
+        let ws = self.workspace.read().await;
+        Ok(selection_ranges::selection_ranges(&ws, &params.text_document.uri, &params.positions))
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+        let ws = self.workspace.read().await;
+        Ok(call_hierarchy::prepare(&ws, &uri, pos))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let ws = self.workspace.read().await;
+        Ok(call_hierarchy::incoming_calls(&ws, &params.item))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let ws = self.workspace.read().await;
+        Ok(call_hierarchy::outgoing_calls(&ws, &params.item))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let ws = self.workspace.read().await;
+        Ok(code_lens::code_lenses(&ws, &params.text_document.uri))
+    }
 }
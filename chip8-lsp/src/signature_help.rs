@@ -0,0 +1,263 @@
+use tower_lsp::lsp_types::*;
+
+use chip8_asm::lexer::{tokenize, Token};
+use chip8_asm::macroexpand::{collect_definitions, MacroDef};
+
+use crate::hover::instr_doc;
+use crate::workspace::Workspace;
+
+/// Operand forms per mnemonic with their encodings, in the order of the
+/// hover table.
+fn instr_forms(mnemonic: &str) -> Option<&'static [(&'static str, &'static str)]> {
+    Some(match mnemonic {
+        "CLS" => &[("CLS", "`00E0`")],
+        "RET" => &[("RET", "`00EE`")],
+        "JP" => &[("JP addr", "`1NNN` — Set PC = NNN"), ("JP V0, addr", "`BNNN` — Jump to NNN + V0")],
+        "CALL" => &[
+            ("CALL addr", "`2NNN` — Push PC, set PC = NNN"),
+            ("CALL addr, Vx, [save]", "Pseudo — Save V0..Vx to `save`, call, restore V0..Vx"),
+        ],
+        "SE" => &[("SE Vx, byte", "`3XKK` — Skip if Vx == KK"), ("SE Vx, Vy", "`5XY0` — Skip if Vx == Vy")],
+        "SNE" => &[("SNE Vx, byte", "`4XKK` — Skip if Vx != KK"), ("SNE Vx, Vy", "`9XY0` — Skip if Vx != Vy")],
+        "LD" => &[
+            ("LD Vx, byte", "`6XKK` — Vx = KK"),
+            ("LD Vx, Vy", "`8XY0` — Vx = Vy"),
+            ("LD I, addr", "`ANNN` — I = NNN"),
+            ("LD Vx, DT", "`FX07` — Vx = DT"),
+            ("LD Vx, K", "`FX0A` — Wait key, Vx = key"),
+            ("LD DT, Vx", "`FX15` — DT = Vx"),
+            ("LD ST, Vx", "`FX18` — ST = Vx"),
+            ("LD F, Vx", "`FX29` — I = sprite(Vx)"),
+            ("LD B, Vx", "`FX33` — BCD of Vx"),
+            ("LD [I], Vx", "`FX55` — Save V0..Vx"),
+            ("LD Vx, [I]", "`FX65` — Load V0..Vx"),
+            ("LD V0, [addr]", "Pseudo — I = addr, load V0"),
+            ("LD [addr], V0", "Pseudo — I = addr, save V0"),
+            ("LD V0..Vx, [addr]", "Pseudo — I = addr, load V0..Vx"),
+            ("LD [addr], V0..Vx", "Pseudo — I = addr, save V0..Vx"),
+        ],
+        "ADD" => &[
+            ("ADD Vx, byte", "`7XKK` — Vx += KK"),
+            ("ADD Vx, Vy", "`8XY4` — Vx += Vy, VF = carry"),
+            ("ADD I, Vx", "`FX1E` — I += Vx"),
+        ],
+        "OR" => &[("OR Vx, Vy", "`8XY1` — Vx |= Vy")],
+        "AND" => &[("AND Vx, Vy", "`8XY2` — Vx &= Vy")],
+        "XOR" => &[("XOR Vx, Vy", "`8XY3` — Vx ^= Vy")],
+        "SUB" => &[
+            ("SUB Vx, Vy", "`8XY5` — Vx -= Vy, VF = not borrow"),
+            ("SUB Vx, byte", "Pseudo — `ADD Vx, -KK`"),
+        ],
+        "SUBN" => &[("SUBN Vx, Vy", "`8XY7` — Vx = Vy - Vx, VF = not borrow")],
+        "SHR" => &[("SHR Vx", "`8XY6` — Vx >>= 1, VF = LSB")],
+        "SHL" => &[("SHL Vx", "`8XYE` — Vx <<= 1, VF = MSB")],
+        "RND" => &[("RND Vx, byte", "`CXKK` — Vx = random & KK")],
+        "DRW" => &[("DRW Vx, Vy, nibble", "`DXYN` — Draw N-byte sprite at (Vx, Vy), VF = collision")],
+        "SKP" => &[("SKP Vx", "`EX9E` — Skip if key Vx is pressed")],
+        "SKNP" => &[("SKNP Vx", "`EXA1` — Skip if key Vx is not pressed")],
+        "NOT" => &[("NOT Vx", "`LD VF, 0xFF` + `XOR Vx, VF` — Clobbers VF")],
+        "NEG" => &[("NEG Vx", "`LD VF, 0` + `SUBN Vx, VF` — Clobbers VF")],
+        "INC" => &[("INC Vx", "`ADD Vx, 1`")],
+        "DEC" => &[("DEC Vx", "`ADD Vx, 0xFF`")],
+        "JEQ" => &[
+            ("JEQ Vx, byte, addr", "`SNE Vx, KK` + `JP addr`"),
+            ("JEQ Vx, Vy, addr", "`SNE Vx, Vy` + `JP addr`"),
+        ],
+        "JNE" => &[
+            ("JNE Vx, byte, addr", "`SE Vx, KK` + `JP addr`"),
+            ("JNE Vx, Vy, addr", "`SE Vx, Vy` + `JP addr`"),
+        ],
+        _ => return None,
+    })
+}
+
+pub fn signature_help(ws: &Workspace, uri: &Url, pos: Position) -> Option<SignatureHelp> {
+    let doc = ws.get_document(uri)?;
+    let line = doc.source.lines().nth(pos.line as usize).unwrap_or("");
+    let prefix = line.get(..pos.character as usize).unwrap_or(line);
+    if prefix.contains(';') {
+        return None;
+    }
+
+    let mut tokens: Vec<Token> = tokenize(prefix)
+        .into_iter()
+        .map(|(t, _, _)| t)
+        .filter(|t| !matches!(t, Token::Newline | Token::Eof))
+        .collect();
+    if let [Token::Word(_), Token::Colon, ..] = tokens.as_slice() {
+        tokens.drain(..2);
+    }
+    let Some(Token::Word(name)) = tokens.first() else { return None };
+    // Still typing the mnemonic itself
+    if tokens.len() == 1 && !prefix.ends_with(char::is_whitespace) {
+        return None;
+    }
+    let operands = split_operands(&tokens[1..]);
+    let active = operands.len() - 1;
+
+    if let Some(forms) = instr_forms(&name.to_uppercase()) {
+        let summary = instr_doc(name).and_then(|d| d.split("\n\n").next()).unwrap_or("");
+        let (typed, partial) = (&operands[..active], &operands[active]);
+        let signatures: Vec<SignatureInformation> = forms
+            .iter()
+            .map(|(label, doc)| signature(label, format!("{}\n\n{}", summary, doc)))
+            .collect();
+        // The first form the completed operands fit that has room for the
+        // one being typed
+        let best = forms
+            .iter()
+            .position(|(label, _)| {
+                let params = form_params(label);
+                params.len() > active
+                    && typed.iter().zip(&params).all(|(t, p)| fits(t, p))
+                    && (partial.is_empty() || fits(partial, params[active]) || (partial == "[" && params[active].starts_with('[')))
+            })
+            .unwrap_or(0);
+        return Some(SignatureHelp {
+            signatures,
+            active_signature: Some(best as u32),
+            active_parameter: Some(active as u32),
+        });
+    }
+
+    let macros = collect_definitions(&doc.source).map(|(_, defs)| defs).unwrap_or_default();
+    let mac: &MacroDef = macros
+        .iter()
+        .chain(doc.analysis.iter().flat_map(|a| &a.macro_defs))
+        .find(|m| &m.name == name)?;
+    let label = if mac.params.is_empty() {
+        mac.name.clone()
+    } else {
+        format!("{} {}", mac.name, mac.params.join(", "))
+    };
+    let body = format!("Macro, expands to:\n```\n{}\n```", mac.body.join("\n"));
+    Some(SignatureHelp {
+        signatures: vec![signature(&label, body)],
+        active_signature: Some(0),
+        active_parameter: Some(active as u32),
+    })
+}
+
+fn signature(label: &str, doc: String) -> SignatureInformation {
+    let parameters = form_params(label)
+        .into_iter()
+        .map(|p| ParameterInformation {
+            label: ParameterLabel::Simple(p.to_string()),
+            documentation: None,
+        })
+        .collect();
+    SignatureInformation {
+        label: label.to_string(),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: doc,
+        })),
+        parameters: Some(parameters),
+        active_parameter: None,
+    }
+}
+
+/// `"LD Vx, [I]"` -> `["Vx", "[I]"]`.
+fn form_params(label: &str) -> Vec<&str> {
+    match label.split_once(' ') {
+        Some((_, rest)) => rest.split(", ").collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Operands typed so far, split at commas. The last one is being typed and
+/// may be empty.
+fn split_operands(tokens: &[Token]) -> Vec<String> {
+    let mut operands = vec![String::new()];
+    for tok in tokens {
+        let text = match tok {
+            Token::Comma => {
+                operands.push(String::new());
+                continue;
+            }
+            Token::Word(w) => w.clone(),
+            Token::Number(n) => n.to_string(),
+            Token::LBracket => "[".into(),
+            Token::RBracket => "]".into(),
+            _ => "?".into(),
+        };
+        operands.last_mut().unwrap().push_str(&text);
+    }
+    operands
+}
+
+/// Whether a typed operand can stand in for a form's parameter.
+fn fits(typed: &str, param: &str) -> bool {
+    let t = typed.to_uppercase();
+    let is_vreg = t.len() == 2 && t.starts_with('V') && t[1..].chars().all(|c| c.is_ascii_hexdigit());
+    match param {
+        "Vx" | "Vy" => is_vreg,
+        "V0" | "I" | "DT" | "ST" | "K" | "F" | "B" | "[I]" => t == param,
+        "[addr]" | "[save]" => t.starts_with('[') && t != "[I]",
+        "V0..Vx" => t.starts_with("V0.."),
+        _ => !is_vreg && !t.starts_with('[') && !matches!(t.as_str(), "I" | "DT" | "ST" | "K" | "F" | "B"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use chip8_asm::analyze;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn workspace(source: &str) -> (Workspace, Url) {
+        let uri = Url::parse("file:///test.asm").unwrap();
+        let analysis = analyze(source).ok();
+        let mut ws = Workspace::new();
+        let mut doc = Document::new(PathBuf::from("/test.asm"), source.to_string());
+        doc.analysis = analysis.map(Arc::new);
+        ws.documents.insert(uri.clone(), doc);
+        (ws, uri)
+    }
+
+    fn at(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn test_instruction_forms() {
+        let (ws, uri) = workspace("start:\n    LD I, \n    ld v1, [\n    DRW V0, V1\n    LD");
+        let help = signature_help(&ws, &uri, at(1, 10)).unwrap();
+        assert_eq!(help.signatures.len(), 15);
+        assert_eq!(help.signatures[help.active_signature.unwrap() as usize].label, "LD I, addr");
+        assert_eq!(help.active_parameter, Some(1));
+
+        let help = signature_help(&ws, &uri, at(2, 13)).unwrap();
+        assert_eq!(help.signatures[help.active_signature.unwrap() as usize].label, "LD Vx, [I]");
+        assert_eq!(help.active_parameter, Some(1));
+
+        let help = signature_help(&ws, &uri, at(3, 14)).unwrap();
+        assert_eq!(help.signatures[0].label, "DRW Vx, Vy, nibble");
+        assert_eq!(help.active_parameter, Some(1));
+
+        // still typing the mnemonic
+        assert!(signature_help(&ws, &uri, at(4, 6)).is_none());
+    }
+
+    #[test]
+    fn test_macro_params() {
+        let (ws, uri) = workspace("macro move x, y {\n    ADD x, y\n}\nstart:\n    move V0, \n");
+        let help = signature_help(&ws, &uri, at(4, 13)).unwrap();
+        assert_eq!(help.signatures[0].label, "move x, y");
+        assert_eq!(help.active_parameter, Some(1));
+    }
+
+    #[test]
+    fn test_forms_match_typed_operands() {
+        assert!(fits("V3", "Vx"));
+        assert!(fits("sprite", "addr"));
+        assert!(!fits("V3", "byte"));
+        assert!(fits("[table]", "[addr]"));
+        assert!(!fits("[I]", "[addr]"));
+        assert!(fits("V0..V3", "V0..Vx"));
+        assert!(!fits("V3", "V0..Vx"));
+        assert_eq!(form_params("DRW Vx, Vy, nibble"), ["Vx", "Vy", "nibble"]);
+    }
+}
@@ -5,7 +5,7 @@ use crate::workspace::Workspace;
 #[allow(deprecated)]
 pub fn document_symbols(ws: &Workspace, uri: &Url) -> Option<DocumentSymbolResponse> {
     let doc = ws.get_document(uri)?;
-    let sym = doc.symbol_table.as_ref()?;
+    let sym = doc.symbol_table()?;
 
     let mut symbols = Vec::new();
 
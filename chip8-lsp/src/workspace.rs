@@ -1,15 +1,35 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use tower_lsp::lsp_types::Url;
 
+use chip8_asm::semantic;
+use chip8_asm::{AnalysisResult, AssemblyError};
+
 use crate::document::Document;
+use crate::inlay_hints::InlayHintConfig;
 
 #[derive(Default)]
 pub struct Workspace {
     pub documents: HashMap<Url, Document>,
+    /// File -> files it includes, resolved against the including file.
     pub include_graph: HashMap<PathBuf, Vec<PathBuf>>,
     pub included_by: HashMap<PathBuf, Vec<PathBuf>>,
+    pub analysis_cache: HashMap<PathBuf, CachedAnalysis>,
+    pub inlay_hints: InlayHintConfig,
+}
+
+/// Outcome of analyzing a file, reused while its text hashes the same and
+/// none of its includes has changed since.
+#[derive(Clone)]
+pub struct CachedAnalysis {
+    pub hash: u64,
+    pub analysis: Arc<AnalysisResult>,
+    pub errors: Vec<AssemblyError>,
+    pub semantic: Vec<semantic::Diagnostic>,
 }
 
 impl Workspace {
@@ -18,7 +38,8 @@ impl Workspace {
     }
 
     pub fn index_file(&mut self, path: &PathBuf, source: &str) {
-        let includes = extract_includes(source);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let includes: Vec<PathBuf> = extract_includes(source).iter().map(|inc| dir.join(inc)).collect();
         let prev = self.include_graph.insert(path.clone(), includes.clone());
 
         if let Some(old) = prev {
@@ -34,9 +55,61 @@ impl Workspace {
         }
     }
 
+    /// The cached analysis of `path` if it was made from exactly `source`.
+    pub fn cached_analysis(&self, path: &Path, source: &str) -> Option<&CachedAnalysis> {
+        self.analysis_cache.get(path).filter(|c| c.hash == content_hash(source))
+    }
+
+    /// Forgets the analyses of every file that includes `path`, directly or
+    /// through other includes, and returns those files.
+    pub fn invalidate_dependents(&mut self, path: &Path) -> Vec<PathBuf> {
+        let mut dependents: Vec<PathBuf> = Vec::new();
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(file) = pending.pop() {
+            for parent in self.included_by.get(&file).into_iter().flatten() {
+                if parent != path && !dependents.contains(parent) {
+                    dependents.push(parent.clone());
+                    pending.push(parent.clone());
+                }
+            }
+        }
+        for dep in &dependents {
+            self.analysis_cache.remove(dep);
+        }
+        dependents
+    }
+
     pub fn get_document(&self, uri: &Url) -> Option<&Document> {
         self.documents.get(uri)
     }
+
+    /// Contents of every open buffer keyed by path, so the assembler sees
+    /// unsaved edits when it resolves `include`, `.incbin` and `.image`.
+    pub fn overlay_files(&self) -> HashMap<PathBuf, String> {
+        self.documents
+            .values()
+            .map(|d| (d.path.clone(), d.source.clone()))
+            .collect()
+    }
+
+    pub fn is_open(&self, uri: &Url) -> bool {
+        self.documents.contains_key(uri)
+    }
+}
+
+/// Maps a file name from a `SourceMap` or `AssemblyError` to a URI. The root
+/// buffer (`<root>`) and unknown origins map to `root`.
+pub fn file_uri(file: &str, root: &Url) -> Url {
+    match file {
+        "<root>" | "<unknown>" | "" => root.clone(),
+        path => Url::from_file_path(path).unwrap_or_else(|_| root.clone()),
+    }
+}
+
+pub fn content_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
 }
 
 fn extract_includes(source: &str) -> Vec<PathBuf> {
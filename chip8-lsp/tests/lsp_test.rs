@@ -117,5 +117,421 @@ fn test_lsp_diagnostics() {
     let _ = std::thread::spawn(move || {
         std::thread::sleep(Duration::from_secs(2));
         let _ = child.kill();
+        let _ = child.wait();
+    });
+}
+
+fn read_diagnostics_for(reader: &mut BufReader<impl Read>, uri: &str) -> serde_json::Value {
+    loop {
+        let msg = read_notification(reader);
+        if msg["method"] == "textDocument/publishDiagnostics" && msg["params"]["uri"] == uri {
+            return msg;
+        }
+    }
+}
+
+#[test]
+fn test_lsp_includes_and_macros() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip8-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let mut reader = BufReader::new(stdout);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "initialize",
+        "params": { "processId": null, "capabilities": {}, "rootUri": null }
+    }));
+    let _resp = read_response(&mut reader, 1);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "initialized", "params": {}
+    }));
+
+    // The included file only exists as an open buffer
+    let lib_uri = "file:///chip8-lsp-test/lib.asm";
+    let main_uri = "file:///chip8-lsp-test/main.asm";
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": {
+            "uri": lib_uri, "languageId": "chip8", "version": 1,
+            "text": "draw:\n    CLS\n    RET\n"
+        } }
+    }));
+    let _ = read_diagnostics_for(&mut reader, lib_uri);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": {
+            "uri": main_uri, "languageId": "chip8", "version": 1,
+            "text": "include \"lib.asm\"\nmacro twice r {\n    ADD r, 1\n    ADD r, 1\n}\nstart:\n    twice V0\n    CALL draw\n    JP start\n"
+        } }
+    }));
+    let diag = read_diagnostics_for(&mut reader, main_uri);
+    let diags = diag["params"]["diagnostics"].as_array().unwrap();
+    assert!(diags.is_empty(), "expected no diagnostics, got {:?}", diags);
+
+    // Go to definition of `draw` lands in the included file
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "textDocument/definition",
+        "params": {
+            "textDocument": { "uri": main_uri },
+            "position": { "line": 7, "character": 10 }
+        }
+    }));
+    let resp = read_response(&mut reader, 2);
+    assert_eq!(resp["result"]["uri"], lib_uri, "got: {:?}", resp);
+    assert_eq!(resp["result"]["range"]["start"]["line"], 0);
+
+    // An error inside a macro expansion is reported on the invocation line
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": main_uri, "version": 2 },
+            "contentChanges": [{ "text": "include \"lib.asm\"\nmacro bad {\n    BOGUS\n}\nstart:\n    bad\n" }]
+        }
+    }));
+    let diag = read_diagnostics_for(&mut reader, main_uri);
+    let diags = diag["params"]["diagnostics"].as_array().unwrap();
+    assert!(!diags.is_empty(), "expected diagnostics");
+    assert_eq!(diags[0]["range"]["start"]["line"], 5, "got: {:?}", diags);
+
+    // Semantic problems carry a severity and span the offending operand
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": main_uri, "version": 3 },
+            "contentChanges": [{ "text": "start:\n    LD I, missing\n    DRW V0, V1, 0\n    JP start\n" }]
+        }
+    }));
+    let diag = read_diagnostics_for(&mut reader, main_uri);
+    let diags = diag["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diags.len(), 2, "got: {:?}", diags);
+    assert_eq!(diags[0]["severity"], 1);
+    assert_eq!(diags[0]["range"]["start"]["character"], 10);
+    assert_eq!(diags[0]["range"]["end"]["character"], 17);
+    assert_eq!(diags[1]["severity"], 2);
+    assert_eq!(diags[1]["range"]["start"]["line"], 2);
+
+    // Lint findings are warnings tagged with their lint ID
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": main_uri, "version": 4 },
+            "contentChanges": [{ "text": "start:\n    JP start\nidle:\n    CLS\n" }]
+        }
+    }));
+    let diag = read_diagnostics_for(&mut reader, main_uri);
+    let diags = diag["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diags.len(), 1, "got: {:?}", diags);
+    assert_eq!(diags[0]["severity"], 2);
+    assert_eq!(diags[0]["code"], "unused-label");
+    assert_eq!(diags[0]["range"]["start"]["line"], 2);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 3, "method": "shutdown", "params": null
+    }));
+    let _resp = read_response(&mut reader, 3);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "exit", "params": null
+    }));
+
+    let _ = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(2));
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+}
+
+#[test]
+fn test_lsp_formatting() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip8-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let mut reader = BufReader::new(stdout);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "initialize",
+        "params": { "processId": null, "capabilities": {}, "rootUri": null }
+    }));
+    let resp = read_response(&mut reader, 1);
+    assert_eq!(resp["result"]["capabilities"]["documentFormattingProvider"], true);
+    assert_eq!(resp["result"]["capabilities"]["documentRangeFormattingProvider"], true);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "initialized", "params": {}
+    }));
+
+    let uri = "file:///chip8-lsp-test/fmt.asm";
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": {
+            "uri": uri, "languageId": "chip8", "version": 1,
+            "text": "start:\n  ld v0,1 ; one\n    drw v0, v1, 5\n    JP start\n"
+        } }
+    }));
+    let _ = read_diagnostics_for(&mut reader, uri);
+
+    let options = serde_json::json!({ "tabSize": 4, "insertSpaces": true });
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "textDocument/formatting",
+        "params": { "textDocument": { "uri": uri }, "options": options }
+    }));
+    let resp = read_response(&mut reader, 2);
+    let edits = resp["result"].as_array().unwrap();
+    assert_eq!(edits.len(), 3, "got: {:?}", edits);
+    assert_eq!(edits[0]["range"]["start"]["line"], 1);
+    assert_eq!(edits[0]["newText"], "    LD  V0, 1  ; one");
+    assert_eq!(edits[1]["newText"], "    DRW V0, V1, 5");
+    assert_eq!(edits[2]["newText"], "    JP  start");
+
+    // Range formatting only touches the selected lines
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 3, "method": "textDocument/rangeFormatting",
+        "params": {
+            "textDocument": { "uri": uri },
+            "range": { "start": { "line": 2, "character": 0 }, "end": { "line": 3, "character": 0 } },
+            "options": options
+        }
+    }));
+    let resp = read_response(&mut reader, 3);
+    let edits = resp["result"].as_array().unwrap();
+    assert_eq!(edits.len(), 1, "got: {:?}", edits);
+    assert_eq!(edits[0]["range"]["start"]["line"], 2);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 4, "method": "shutdown", "params": null
+    }));
+    let _resp = read_response(&mut reader, 4);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "exit", "params": null
+    }));
+
+    let _ = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(2));
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+}
+
+#[test]
+fn test_lsp_inlay_hints() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip8-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let mut reader = BufReader::new(stdout);
+
+    // Operand values are switched off through the initialization options
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "initialize",
+        "params": {
+            "processId": null, "capabilities": {}, "rootUri": null,
+            "initializationOptions": { "inlayHints": { "values": false } }
+        }
+    }));
+    let resp = read_response(&mut reader, 1);
+    assert_eq!(resp["result"]["capabilities"]["inlayHintProvider"], true);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "initialized", "params": {}
+    }));
+
+    let uri = "file:///chip8-lsp-test/hints.asm";
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": {
+            "uri": uri, "languageId": "chip8", "version": 1,
+            "text": ".const SPEED = 3\nstart:\n    ADD V0, SPEED\n    INC V1 ; pseudo\n    JP start\n"
+        } }
+    }));
+    let _ = read_diagnostics_for(&mut reader, uri);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "textDocument/inlayHint",
+        "params": {
+            "textDocument": { "uri": uri },
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 5, "character": 0 } }
+        }
+    }));
+    let resp = read_response(&mut reader, 2);
+    let hints = resp["result"].as_array().unwrap();
+    let labels: Vec<&str> = hints.iter().map(|h| h["label"].as_str().unwrap()).collect();
+    assert_eq!(labels, ["0x200: 7003", "0x202: 7101", "0x204: 1200"]);
+    assert_eq!(hints[1]["position"]["line"], 3);
+    assert_eq!(hints[1]["position"]["character"], 19);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 3, "method": "shutdown", "params": null
+    }));
+    let _resp = read_response(&mut reader, 3);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "exit", "params": null
+    }));
+
+    let _ = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(2));
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+}
+
+#[test]
+fn test_lsp_inlay_hints_without_layout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip8-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let mut reader = BufReader::new(stdout);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "initialize",
+        "params": { "processId": null, "capabilities": {}, "rootUri": null }
+    }));
+    let _resp = read_response(&mut reader, 1);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "initialized", "params": {}
+    }));
+
+    // A duplicate constant makes layout fail, leaving no addresses at all
+    let uri = "file:///chip8-lsp-test/dup.asm";
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": {
+            "uri": uri, "languageId": "chip8", "version": 1,
+            "text": ".const A = 1\n.const A = 2\nstart:\n    CLS\n"
+        } }
+    }));
+    let _ = read_diagnostics_for(&mut reader, uri);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "textDocument/inlayHint",
+        "params": {
+            "textDocument": { "uri": uri },
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 4, "character": 0 } }
+        }
+    }));
+    let resp = read_response(&mut reader, 2);
+    assert!(resp.get("error").is_none(), "inlay hints failed: {resp}");
+    assert_eq!(resp["result"], serde_json::json!([]));
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 3, "method": "shutdown", "params": null
+    }));
+    let _resp = read_response(&mut reader, 3);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "exit", "params": null
+    }));
+
+    let _ = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(2));
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+}
+
+#[test]
+fn test_lsp_incremental_sync() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip8-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let mut reader = BufReader::new(stdout);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "initialize",
+        "params": { "processId": null, "capabilities": {}, "rootUri": null }
+    }));
+    let resp = read_response(&mut reader, 1);
+    assert_eq!(resp["result"]["capabilities"]["textDocumentSync"], 2);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "initialized", "params": {}
+    }));
+
+    let lib_uri = "file:///chip8-lsp-sync-test/lib.asm";
+    let main_uri = "file:///chip8-lsp-sync-test/main.asm";
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": {
+            "uri": lib_uri, "languageId": "chip8", "version": 1,
+            "text": "draw:\n    CLS\n    RET\n"
+        } }
+    }));
+    let _ = read_diagnostics_for(&mut reader, lib_uri);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": {
+            "uri": main_uri, "languageId": "chip8", "version": 1,
+            "text": "include \"lib.asm\"\nstart:\n    CALL draw\n    JP start\n"
+        } }
+    }));
+    let diag = read_diagnostics_for(&mut reader, main_uri);
+    assert!(diag["params"]["diagnostics"].as_array().unwrap().is_empty(), "got: {:?}", diag);
+
+    // Renaming the label in the included file re-analyzes the file including it
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": lib_uri, "version": 2 },
+            "contentChanges": [{
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 4 } },
+                "text": "blit"
+            }]
+        }
+    }));
+    let diag = read_diagnostics_for(&mut reader, main_uri);
+    let diags = diag["params"]["diagnostics"].as_array().unwrap();
+    assert!(!diags.is_empty(), "expected diagnostics");
+    assert_eq!(diags[0]["range"]["start"]["line"], 2, "got: {:?}", diags);
+
+    // A ranged edit in the including file itself
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": main_uri, "version": 2 },
+            "contentChanges": [{
+                "range": { "start": { "line": 2, "character": 9 }, "end": { "line": 2, "character": 13 } },
+                "text": "blit"
+            }]
+        }
+    }));
+    let diag = read_diagnostics_for(&mut reader, main_uri);
+    assert!(diag["params"]["diagnostics"].as_array().unwrap().is_empty(), "got: {:?}", diag);
+
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "shutdown", "params": null
+    }));
+    let _resp = read_response(&mut reader, 2);
+    send_msg(&mut stdin, &serde_json::json!({
+        "jsonrpc": "2.0", "method": "exit", "params": null
+    }));
+
+    let _ = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(2));
+        let _ = child.kill();
+        let _ = child.wait();
     });
 }
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, oneshot};
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>;
+
+/// A long-lived connection to the emulator's debug server. Replies are
+/// matched to requests by `id`, so calls can overlap; events pushed by the
+/// server go to every `events()` receiver.
+pub struct Connection {
+    writer: tokio::sync::Mutex<OwnedWriteHalf>,
+    pending: Pending,
+    events: broadcast::Sender<Value>,
+    next_id: AtomicU64,
+    closed: Arc<AtomicBool>,
+}
+
+impl Connection {
+    /// Connects and subscribes to all events.
+    pub async fn open(port: u16) -> std::io::Result<Arc<Self>> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+        let (read, write) = stream.into_split();
+        let pending = Pending::default();
+        let (events, _) = broadcast::channel(256);
+        let closed = Arc::new(AtomicBool::new(false));
+        tokio::spawn(read_loop(read, pending.clone(), events.clone(), closed.clone()));
+
+        let conn = Arc::new(Connection {
+            writer: tokio::sync::Mutex::new(write),
+            pending,
+            events,
+            next_id: AtomicU64::new(1),
+            closed,
+        });
+        conn.request(json!({"cmd": "subscribe"}))
+            .await
+            .map_err(std::io::Error::other)?;
+        Ok(conn)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Events pushed from now on.
+    pub fn events(&self) -> broadcast::Receiver<Value> {
+        self.events.subscribe()
+    }
+
+    /// Sends `cmd` and waits for the reply carrying the same id.
+    pub async fn request(&self, mut cmd: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        cmd["id"] = id.into();
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            // The read loop marks the connection closed before failing what is
+            // pending, so nothing would ever answer a request added after that
+            if self.is_closed() {
+                return Err("connection closed".to_string());
+            }
+            pending.insert(id, tx);
+        }
+
+        let mut line = cmd.to_string();
+        line.push('\n');
+        if let Err(e) = self.writer.lock().await.write_all(line.as_bytes()).await {
+            self.pending.lock().unwrap().remove(&id);
+            self.closed.store(true, Ordering::Relaxed);
+            return Err(e.to_string());
+        }
+
+        let mut resp = rx.await.map_err(|_| "connection closed".to_string())?;
+        if let Some(obj) = resp.as_object_mut() {
+            obj.remove("id");
+        }
+        Ok(resp)
+    }
+}
+
+/// Routes incoming lines until the server hangs up, then fails whatever is
+/// still waiting for a reply.
+async fn read_loop(read: OwnedReadHalf, pending: Pending, events: broadcast::Sender<Value>, closed: Arc<AtomicBool>) {
+    let mut lines = BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(msg) = serde_json::from_str::<Value>(&line) else { continue };
+        if msg.get("event").is_some() {
+            // No receivers is fine, nobody is waiting right now
+            let _ = events.send(msg);
+            continue;
+        }
+        let Some(id) = msg.get("id").and_then(Value::as_u64) else { continue };
+        if let Some(tx) = pending.lock().unwrap().remove(&id) {
+            let _ = tx.send(msg);
+        }
+    }
+    closed.store(true, Ordering::Relaxed);
+    pending.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_replies_routed_by_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // A server that answers the subscribe, then holds the next two
+        // requests and answers them in reverse order with an event between
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut held = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let req: Value = serde_json::from_str(&line).unwrap();
+                let reply = json!({"id": req["id"], "cmd": req["cmd"]});
+                if req["cmd"] == "subscribe" {
+                    write.write_all(format!("{reply}\n").as_bytes()).await.unwrap();
+                    continue;
+                }
+                held.push(reply);
+                if held.len() == 2 {
+                    let out = format!("{}\n{}\n{}\n", held[1], json!({"event": "paused"}), held[0]);
+                    write.write_all(out.as_bytes()).await.unwrap();
+                    break;
+                }
+            }
+        });
+
+        let conn = Connection::open(port).await.unwrap();
+        let mut events = conn.events();
+        let (a, b) = tokio::join!(conn.request(json!({"cmd": "a"})), conn.request(json!({"cmd": "b"})));
+        assert_eq!(a.unwrap(), json!({"cmd": "a"}));
+        assert_eq!(b.unwrap(), json!({"cmd": "b"}));
+        assert_eq!(events.recv().await.unwrap(), json!({"event": "paused"}));
+
+        // Once the server hangs up, new requests fail instead of waiting forever
+        server.await.unwrap();
+        assert!(conn.request(json!({"cmd": "c"})).await.is_err());
+        assert!(conn.is_closed());
+    }
+}
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// Quirk names accepted by the emulator's `--quirks` flag.
+pub const QUIRKS: &[&str] = &["shift", "load-store", "jump", "clip", "no-vf-reset"];
+
+/// How to start a headless emulator; kept so `reset` can start it again.
+#[derive(Debug, Clone)]
+pub struct LaunchConfig {
+    pub rom: PathBuf,
+    pub quirks: Vec<String>,
+    pub seed: Option<u64>,
+    pub speed: Option<usize>,
+    /// Cheat list file passed as `--cheats`.
+    pub cheats: Option<PathBuf>,
+}
+
+/// A headless emulator process started by this server. It is killed when
+/// dropped so no emulator outlives the session that started it.
+pub struct Emulator {
+    child: Child,
+    pub config: LaunchConfig,
+}
+
+impl Emulator {
+    /// Starts the emulator with its debug server on `port` and waits until
+    /// that accepts connections.
+    pub async fn spawn(config: LaunchConfig, port: u16) -> Result<Self, String> {
+        if !config.rom.is_file() {
+            return Err(format!("ROM not found: {}", config.rom.display()));
+        }
+        if let Some(bad) = config.quirks.iter().find(|q| !QUIRKS.contains(&q.as_str())) {
+            return Err(format!("unknown quirk '{bad}' (expected one of: {})", QUIRKS.join(", ")));
+        }
+
+        let binary = emulator_binary();
+        let mut cmd = Command::new(&binary);
+        cmd.arg(&config.rom)
+            .arg("--headless")
+            .arg("--debug-port")
+            .arg(port.to_string());
+        if !config.quirks.is_empty() {
+            cmd.arg("--quirks").arg(config.quirks.join(","));
+        }
+        if let Some(seed) = config.seed {
+            cmd.arg("--seed").arg(seed.to_string());
+        }
+        if let Some(speed) = config.speed {
+            cmd.arg("--speed").arg(speed.to_string());
+        }
+        if let Some(cheats) = &config.cheats {
+            cmd.arg("--cheats").arg(cheats);
+        }
+        let child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {e}", binary.display()))?;
+
+        let mut emulator = Emulator { child, config };
+        for _ in 0..50 {
+            if let Ok(Some(status)) = emulator.child.try_wait() {
+                return Err(format!("emulator exited during startup ({status})"));
+            }
+            if tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                return Ok(emulator);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Err(format!("emulator did not open port {port} within 5s"))
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// `CHIP8_EMULATOR` if set, else the `rusty_chip8` binary next to this one
+/// (both land in the same `target/` directory), else whatever is on `PATH`.
+fn emulator_binary() -> PathBuf {
+    if let Some(path) = std::env::var_os("CHIP8_EMULATOR") {
+        return PathBuf::from(path);
+    }
+    let name = format!("rusty_chip8{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&name)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
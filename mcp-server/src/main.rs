@@ -1,3 +1,7 @@
+mod connection;
+mod emulator;
+mod script;
+
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::*;
 use rmcp::transport::stdio;
@@ -5,10 +9,19 @@ use rmcp::{ErrorData as McpError, ServerHandler, ServiceExt, tool, tool_handler,
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde_json::json;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 
-const ERR_MSG: &str = "Emulator not running. Start with: `cargo run -- <path_to_rom>`";
+use connection::Connection;
+use emulator::{Emulator, LaunchConfig};
+
+/// A tap holds the key for `TAP_HOLD_FRAMES` frames, then lets the game run
+/// until `TAP_FRAMES` frames have passed in total.
+const TAP_HOLD_FRAMES: u64 = 3;
+const TAP_FRAMES: u64 = 6;
+
+const ERR_MSG: &str = "Emulator not running. Start one with the `load_rom` tool or `cargo run -- <path_to_rom>`";
 
 // ----- input structs for tools with parameters -----
 
@@ -20,10 +33,47 @@ struct MemoryRange {
     end: u16,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SpritesParam {
+    /// Address of the first sprite
+    address: u16,
+    /// Rows per sprite, 1-15, or 0 for a 16x16 SCHIP sprite (default 5)
+    height: Option<u8>,
+    /// Number of sprites stored back to back to decode (1-64, default 1)
+    count: Option<u8>,
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 struct AddressParam {
-    /// Address for breakpoint
+    /// Address of the breakpoint or cheat
+    address: u16,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SearchStartParam {
+    /// First address to search (default 0x200, where programs start)
+    start: Option<u16>,
+    /// End address, exclusive (default 0x1000)
+    end: Option<u16>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SearchFilterParam {
+    /// How each byte must compare with the previous snapshot to stay a
+    /// candidate: equal, changed, increased, decreased, or value
+    filter: String,
+    /// The value to match, for the `value` filter
+    value: Option<u8>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CheatParam {
+    /// Address of the byte to hold
     address: u16,
+    /// Value written back every frame (default: the byte's current value)
+    value: Option<u8>,
+    /// Optional label, e.g. "lives"
+    name: Option<String>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -38,8 +88,8 @@ struct KeyTapParam {
     /// Hex key value (0x0-0xF). CHIP-8 hex keyboard layout:
     /// 1 2 3 C, 4 5 6 D, 7 8 9 E, A 0 B F
     key: u8,
-    /// Optional path to Python script. If provided, script receives full state
-    /// as JSON on stdin. If omitted, full emulator state is returned as JSON.
+    /// Optional path to a Rhai script (see `run_script`) to run after the
+    /// taps. If omitted, full emulator state is returned as JSON.
     path: Option<String>,
     /// Number of times to press the key (1-10, default 1).
     #[serde(default = "one")]
@@ -48,11 +98,91 @@ struct KeyTapParam {
 
 fn one() -> u8 { 1 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+struct FramesParam {
+    /// Number of frames to run (each is `speed` instructions and one timer tick)
+    frames: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PressForFramesParam {
+    /// Hex key value (0x0-0xF). CHIP-8 hex keyboard layout:
+    /// 1 2 3 C, 4 5 6 D, 7 8 9 E, A 0 B F
+    key: u8,
+    /// Frames to hold the key down for
+    hold: u64,
+    /// Frames to run in total, at least `hold` (default `hold`)
+    frames: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct FrameHistoryParam {
+    /// Number of most recent frames to list (default 20)
+    count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct DiffFramesParam {
+    /// Earlier frame number (default: the frame before `b`)
+    a: Option<u64>,
+    /// Later frame number (default: the last completed frame)
+    b: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ScreenshotParam {
+    /// Image pixels per CHIP-8 pixel, 1-32 (default 10)
+    scale: Option<u64>,
+    /// Off and on colours as `RRGGBB,RRGGBB` (default `000000,FFFFFF`)
+    palette: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RecordGifParam {
+    /// Number of frames to run and record, 1-600 (default 60, one second)
+    frames: Option<u64>,
+    /// Image pixels per CHIP-8 pixel, 1-32 (default 10)
+    scale: Option<u64>,
+    /// Off and on colours as `RRGGBB,RRGGBB` (default `000000,FFFFFF`)
+    palette: Option<String>,
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 struct ScriptParam {
-    /// Path to Python script file. Script receives screen pixels + regs
-    /// as JSON on stdin. Store reusable scripts in /tmp and pass path.
+    /// Path to a Rhai script file, e.g. scripts_games/caveexplorer/board_map.rhai
+    path: Option<String>,
+    /// Script source, instead of `path`
+    code: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct WaitEventParam {
+    /// Events to wait for: breakpoint_hit, paused, fault, frame_drawn
+    /// (default: any of them)
+    #[serde(default)]
+    events: Vec<String>,
+    /// Give up after this many milliseconds (default 5000)
+    timeout_ms: Option<u64>,
+    /// Resume execution first, e.g. to run until the next breakpoint
+    #[serde(default)]
+    resume: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct LoadRomParam {
+    /// Path to the ROM file
     path: String,
+    /// Quirks to enable: shift, load-store, jump, clip, no-vf-reset.
+    /// load-store makes FX65 advance I like FX55 does, as on the COSMAC VIP;
+    /// without it FX65 leaves I unchanged.
+    #[serde(default)]
+    quirks: Vec<String>,
+    /// Seed for RND, to make runs reproducible
+    seed: Option<u64>,
+    /// Instructions per frame (default 100)
+    speed: Option<usize>,
+    /// Cheat list file, one `ADDR VALUE [name]` per line in hex
+    cheats: Option<String>,
 }
 
 // ----- MCP server state -----
@@ -60,34 +190,140 @@ struct ScriptParam {
 #[derive(Clone)]
 struct Chip8Debug {
     port: u16,
+    /// Headless emulator started by `load_rom`, if any.
+    emulator: Arc<Mutex<Option<Emulator>>>,
+    /// Connection to the debug server, opened on first use and reopened
+    /// after the emulator goes away.
+    conn: Arc<tokio::sync::Mutex<Option<Arc<Connection>>>>,
 }
 
 impl Chip8Debug {
-    /// Opens fresh TCP connection per call. On failure returns McpError.
-    async fn send_cmd(&self, cmd: serde_json::Value) -> Result<serde_json::Value, McpError> {
-        let mut stream = tokio::net::TcpStream::connect(format!("127.0.0.1:{}", self.port))
+    async fn connection(&self) -> Result<Arc<Connection>, McpError> {
+        let mut conn = self.conn.lock().await;
+        if let Some(c) = conn.as_ref().filter(|c| !c.is_closed()) {
+            return Ok(c.clone());
+        }
+        let c = Connection::open(self.port)
             .await
             .map_err(|_| McpError::internal_error(ERR_MSG, None))?;
+        *conn = Some(c.clone());
+        Ok(c)
+    }
 
-        let mut line = serde_json::to_string(&cmd)
-            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
-        line.push('\n');
-        stream
-            .write_all(line.as_bytes())
+    /// Sends one command over the shared connection. On failure returns McpError.
+    async fn send_cmd(&self, cmd: serde_json::Value) -> Result<serde_json::Value, McpError> {
+        self.connection()
+            .await?
+            .request(cmd)
             .await
-            .map_err(|_| McpError::internal_error(ERR_MSG, None))?;
+            .map_err(|_| McpError::internal_error(ERR_MSG, None))
+    }
 
-        let mut reader = BufReader::new(&mut stream);
-        let mut resp = String::new();
-        reader
-            .read_line(&mut resp)
-            .await
-            .map_err(|_| McpError::internal_error(ERR_MSG, None))?;
+    fn describe_event(event: &serde_json::Value) -> String {
+        let pc = event["pc"].as_u64().unwrap_or(0);
+        match event["event"].as_str().unwrap_or("") {
+            "breakpoint_hit" => format!("Breakpoint hit at 0x{pc:03X}"),
+            "paused" => format!("Paused at 0x{pc:03X} ({})", event["reason"].as_str().unwrap_or("?")),
+            "fault" => format!("Fault at 0x{pc:03X}: {}", event["message"].as_str().unwrap_or("")),
+            "frame_drawn" => format!("Frame {} drawn", event["frame"].as_u64().unwrap_or(0)),
+            other => format!("Event {other}: {event}"),
+        }
+    }
+
+    /// Runs exactly `frames` frames, holding `key` for the first `hold` of
+    /// them, and leaves the emulator paused. Returns the debugger's reply.
+    async fn advance(&self, frames: u64, key: Option<(u8, u64)>) -> Result<serde_json::Value, McpError> {
+        let cmd = match key {
+            Some((key, hold)) => json!({"cmd": "press_for_frames", "key": key, "hold": hold, "n": frames}),
+            None => json!({"cmd": "run_frames", "n": frames}),
+        };
+        let resp = self.send_cmd(cmd).await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::internal_error(err.to_string(), None));
+        }
+        Ok(resp)
+    }
 
-        serde_json::from_str(resp.trim())
+    /// Runs a Rhai script on a blocking thread, calling back into the
+    /// emulator over the shared connection.
+    async fn eval_script(&self, script: String) -> Result<String, McpError> {
+        let conn = self.connection().await?;
+        let runtime = tokio::runtime::Handle::current();
+        tokio::task::spawn_blocking(move || script::run(conn, runtime, &script))
+            .await
             .map_err(|e| McpError::internal_error(e.to_string(), None))
     }
 
+    /// A `screenshot`/`record_gif` command with the options that were given.
+    fn capture_cmd(cmd: &str, scale: Option<u64>, palette: Option<String>) -> serde_json::Value {
+        let mut cmd = json!({"cmd": cmd});
+        if let Some(scale) = scale {
+            cmd["scale"] = scale.into();
+        }
+        if let Some(palette) = palette {
+            cmd["palette"] = palette.into();
+        }
+        cmd
+    }
+
+    /// Lists the candidates in a search reply, with how each changed since
+    /// the snapshot before.
+    fn format_candidates(resp: &serde_json::Value) -> String {
+        let count = resp["count"].as_u64().unwrap_or(0);
+        let list = resp["candidates"].as_array().cloned().unwrap_or_default();
+        let mut out = format!("{count} candidate{}", if count == 1 { "" } else { "s" });
+        if list.len() < count as usize {
+            out.push_str(&format!(", first {} shown", list.len()));
+        }
+        for c in &list {
+            let addr = c["addr"].as_u64().unwrap_or(0);
+            let before = c["before"].as_u64().unwrap_or(0);
+            let value = c["value"].as_u64().unwrap_or(0);
+            out.push_str(&format!("\n  0x{addr:03X}: {before:02X} -> {value:02X} ({value})"));
+        }
+        out
+    }
+
+    fn describe_run(resp: &serde_json::Value) -> String {
+        let frames = resp["frames"].as_u64().unwrap_or(0);
+        let frame = resp["frame"].as_u64().unwrap_or(0);
+        let mut out = format!("Ran {frames} frame{} (now at frame {frame})", if frames == 1 { "" } else { "s" });
+        if let Some(reason) = resp["stopped"].as_str() {
+            out.push_str(&format!(", stopped early ({reason})"));
+        }
+        out
+    }
+
+    /// Replaces the emulator this server owns with a fresh one.
+    async fn launch(&self, config: LaunchConfig) -> Result<CallToolResult, McpError> {
+        // Stop the previous one first so its port is free again
+        drop(self.emulator.lock().unwrap().take());
+        self.conn.lock().await.take();
+        if tokio::net::TcpStream::connect(("127.0.0.1", self.port)).await.is_ok() {
+            return Err(McpError::internal_error(
+                format!("Port {} is used by an emulator not started with load_rom; stop it first", self.port),
+                None,
+            ));
+        }
+
+        let emulator = Emulator::spawn(config, self.port)
+            .await
+            .map_err(|e| McpError::internal_error(e, None))?;
+        let config = &emulator.config;
+        let mut out = format!("Loaded {} (headless, port {})", config.rom.display(), self.port);
+        if !config.quirks.is_empty() {
+            out.push_str(&format!("\nQuirks: {}", config.quirks.join(", ")));
+        }
+        if let Some(seed) = config.seed {
+            out.push_str(&format!("\nSeed: {seed}"));
+        }
+        if let Some(cheats) = &config.cheats {
+            out.push_str(&format!("\nCheats: {}", cheats.display()));
+        }
+        *self.emulator.lock().unwrap() = Some(emulator);
+        Ok(CallToolResult::success(vec![Content::text(out)]))
+    }
+
     fn render_screen(pixels: &[Vec<bool>]) -> String {
         let mut out = String::with_capacity(64 * 33 + 4);
         out.push('┌');
@@ -152,57 +388,90 @@ impl Chip8Debug {
         out
     }
 
-    fn diff_screens(before: &[Vec<bool>], after: &[Vec<bool>]) -> (Vec<(usize, usize, bool)>, String, String) {
-        let mut changes = Vec::new();
-        let mut min_x = 64usize; let mut max_x = 0usize;
-        let mut min_y = 32usize; let mut max_y = 0usize;
+    /// A sprite as rows of `█` and `·`, under an `addr (WxH)` header.
+    fn render_sprite(sprite: &serde_json::Value) -> String {
+        let pixels: Vec<Vec<bool>> = serde_json::from_value(sprite["pixels"].clone()).unwrap_or_default();
+        let mut out = format!(
+            "0x{:03X} ({}x{}):",
+            sprite["addr"].as_u64().unwrap_or(0),
+            sprite["width"].as_u64().unwrap_or(0),
+            sprite["height"].as_u64().unwrap_or(0),
+        );
+        for row in pixels {
+            out.push('\n');
+            out.extend(row.iter().map(|&lit| if lit { '█' } else { '·' }));
+        }
+        out
+    }
 
-        for y in 0..32 {
-            for x in 0..64 {
-                if before[y][x] != after[y][x] {
-                    changes.push((x, y, after[y][x]));
-                    if x < min_x { min_x = x; }
-                    if x > max_x { max_x = x; }
-                    if y < min_y { min_y = y; }
-                    if y > max_y { max_y = y; }
-                }
-            }
+    /// Renders a `diff_frames` reply sent with `pixels`: counts, changed
+    /// regions, every changed pixel, a close-up of the changes and the whole
+    /// screen with them marked.
+    fn describe_diff(resp: &serde_json::Value) -> String {
+        let changes: Vec<(usize, usize, bool)> = serde_json::from_value(resp["changes"].clone()).unwrap_or_default();
+        let before: Vec<Vec<bool>> = serde_json::from_value(resp["before"].clone()).unwrap_or_default();
+        let after: Vec<Vec<bool>> = serde_json::from_value(resp["after"].clone()).unwrap_or_default();
+        let (Some(bbox), false) = (resp["bbox"].as_object(), after.is_empty()) else {
+            return "No pixels changed.\n".to_string();
+        };
+        let at = |obj: &serde_json::Map<String, serde_json::Value>, key: &str| obj[key].as_u64().unwrap_or(0) as usize;
+
+        let on_count = changes.iter().filter(|c| c.2).count();
+        let mut out = format!(
+            "Pixels changed: {} ({} on, {} off) in {} cycles\n\nRegions:\n",
+            changes.len(),
+            on_count,
+            changes.len() - on_count,
+            resp["cycles"].as_u64().unwrap_or(0)
+        );
+        for region in resp["regions"].as_array().into_iter().flatten().filter_map(|r| r.as_object()) {
+            out.push_str(&format!(
+                "  {}x{} at ({},{}): {} on, {} off\n",
+                at(region, "width"),
+                at(region, "height"),
+                at(region, "x"),
+                at(region, "y"),
+                at(region, "on"),
+                at(region, "off")
+            ));
+        }
+        out.push_str("\nCoordinates:\n");
+        for (x, y, on) in &changes {
+            out.push_str(&format!("  ({x:2},{y:2}) -> {}\n", if *on { "ON " } else { "OFF" }));
         }
 
         // Mini-map: bounding box of changes
-        let mut mini = String::new();
-        if !changes.is_empty() {
-            mini.push_str(&format!("Bbox {}-{} x {}-{}:\n", min_x, max_x, min_y, max_y));
-            for y in min_y..=max_y {
-                mini.push_str(&format!("{:2}|", y));
-                for x in min_x..=max_x {
-                    mini.push(if after[y][x] { '█' } else { if before[y][x] { '·' } else { ' ' } });
-                }
-                mini.push('\n');
+        let (x0, y0) = (at(bbox, "x"), at(bbox, "y"));
+        let (x1, y1) = (x0 + at(bbox, "width") - 1, y0 + at(bbox, "height") - 1);
+        out.push_str(&format!("\nBbox {x0}-{x1} x {y0}-{y1}:\n"));
+        for y in y0..=y1 {
+            out.push_str(&format!("{y:2}|"));
+            for x in x0..=x1 {
+                out.push(if after[y][x] { '█' } else if before[y][x] { '·' } else { ' ' });
             }
+            out.push('\n');
         }
 
         // Full screen with highlights
-        let mut highlighted = String::with_capacity(64 * 33 + 4);
-        highlighted.push('┌');
-        for _ in 0..64 { highlighted.push('─'); }
-        highlighted.push_str("┐\n");
-        for y in 0..32 {
-            highlighted.push('│');
-            for x in 0..64 {
-                if before[y][x] != after[y][x] {
-                    highlighted.push(if after[y][x] { '@' } else { '·' });
-                } else {
-                    highlighted.push(if before[y][x] { '█' } else { ' ' });
-                }
+        out.push_str("\n\nHighlighted screen (@=new ·=off █=unchanged):\n```\n┌");
+        out.push_str(&"─".repeat(64));
+        out.push_str("┐\n");
+        for (old, new) in before.iter().zip(&after) {
+            out.push('│');
+            for (&was, &now) in old.iter().zip(new) {
+                out.push(match (was, now) {
+                    (false, true) => '@',
+                    (true, false) => '·',
+                    (_, true) => '█',
+                    _ => ' ',
+                });
             }
-            highlighted.push_str("│\n");
+            out.push_str("│\n");
         }
-        highlighted.push('└');
-        for _ in 0..64 { highlighted.push('─'); }
-        highlighted.push('┘');
-
-        (changes, mini, highlighted)
+        out.push('└');
+        out.push_str(&"─".repeat(64));
+        out.push_str("┘\n```");
+        out
     }
 }
 
@@ -210,6 +479,48 @@ impl Chip8Debug {
 
 #[tool_router]
 impl Chip8Debug {
+    #[tool(
+        description = "Start a headless emulator (no window or audio) running a ROM, with optional quirks, RND seed and cheat list. Replaces any emulator started earlier with this tool."
+    )]
+    async fn load_rom(
+        &self,
+        Parameters(LoadRomParam { path, quirks, seed, speed, cheats }): Parameters<LoadRomParam>,
+    ) -> Result<CallToolResult, McpError> {
+        self.launch(LaunchConfig {
+            rom: PathBuf::from(path),
+            quirks,
+            seed,
+            speed,
+            cheats: cheats.map(PathBuf::from),
+        })
+        .await
+    }
+
+    #[tool(description = "Restart the emulator started by load_rom from power-on, with the same ROM, quirks and seed")]
+    async fn reset(&self) -> Result<CallToolResult, McpError> {
+        let config = self.emulator.lock().unwrap().as_ref().map(|e| e.config.clone());
+        match config {
+            Some(config) => self.launch(config).await,
+            None => Err(McpError::invalid_request("No emulator was started with load_rom", None)),
+        }
+    }
+
+    #[tool(description = "Shut down the emulator started by load_rom")]
+    async fn shutdown(&self) -> Result<CallToolResult, McpError> {
+        let emulator = self.emulator.lock().unwrap().take();
+        match emulator {
+            Some(emulator) => {
+                drop(emulator);
+                self.conn.lock().await.take();
+                Ok(CallToolResult::success(vec![Content::text("Emulator shut down")]))
+            }
+            None => Err(McpError::invalid_request(
+                "No emulator was started with load_rom; use `stop` for one started by hand",
+                None,
+            )),
+        }
+    }
+
     #[tool(description = "Render CHIP-8 display as ASCII art (64x32). On=`█` Off=` `")]
     async fn get_screen(&self) -> Result<CallToolResult, McpError> {
         let resp = self.send_cmd(json!({"cmd": "get_screen"})).await?;
@@ -221,6 +532,41 @@ impl Chip8Debug {
         ))]))
     }
 
+    #[tool(description = "Capture the display as a PNG image, at a chosen scale and palette")]
+    async fn screenshot(
+        &self,
+        Parameters(ScreenshotParam { scale, palette }): Parameters<ScreenshotParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let resp = self.send_cmd(Self::capture_cmd("screenshot", scale, palette)).await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
+        }
+        let png = resp["png"].as_str().unwrap_or_default();
+        Ok(CallToolResult::success(vec![Content::image(png, "image/png")]))
+    }
+
+    #[tool(
+        description = "Run N frames and return them as an animated GIF, then pause. Stops early at a breakpoint."
+    )]
+    async fn record_gif(
+        &self,
+        Parameters(RecordGifParam { frames, scale, palette }): Parameters<RecordGifParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut cmd = Self::capture_cmd("record_gif", scale, palette);
+        if let Some(frames) = frames {
+            cmd["n"] = frames.into();
+        }
+        let resp = self.send_cmd(cmd).await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
+        }
+        let gif = resp["gif"].as_str().unwrap_or_default();
+        Ok(CallToolResult::success(vec![
+            Content::image(gif, "image/gif"),
+            Content::text(Self::describe_run(&resp)),
+        ]))
+    }
+
     #[tool(
         description = "Dump all registers: V0-VF, I, PC, stack, delay timer, sound timer"
     )]
@@ -249,6 +595,73 @@ impl Chip8Debug {
         Ok(CallToolResult::success(vec![Content::text(formatted)]))
     }
 
+    #[tool(
+        description = "Decode memory as sprites and show them as pixel grids: 8 pixels wide and 1-15 rows tall, or 16x16 (SCHIP) with height 0"
+    )]
+    async fn get_sprites(
+        &self,
+        Parameters(SpritesParam { address, height, count }): Parameters<SpritesParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let resp = self
+            .send_cmd(json!({"cmd": "get_sprites", "a": address, "h": height.unwrap_or(5), "n": count.unwrap_or(1)}))
+            .await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
+        }
+        let sprites = resp["sprites"].as_array().cloned().unwrap_or_default();
+        let text = sprites.iter().map(Self::render_sprite).collect::<Vec<_>>().join("\n\n");
+        Ok(CallToolResult::success(vec![Content::text(format!("```\n{text}\n```"))]))
+    }
+
+    #[tool(
+        description = "Run one frame and list every sprite it drew (DXYN: address in I, x, y, height, collision) with its pixels, then pause"
+    )]
+    async fn get_frame_draws(&self) -> Result<CallToolResult, McpError> {
+        self.send_cmd(json!({"cmd": "record_draws", "enabled": true})).await?;
+        let run = self.advance(1, None).await;
+        let resp = self.send_cmd(json!({"cmd": "get_draws"})).await;
+        self.send_cmd(json!({"cmd": "record_draws", "enabled": false})).await?;
+        let (run, resp) = (run?, resp?);
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::internal_error(err.to_string(), None));
+        }
+
+        let draws = resp["draws"].as_array().cloned().unwrap_or_default();
+        let mut out = format!(
+            "Frame {}: {} draw{}",
+            resp["frame"].as_u64().unwrap_or(0),
+            draws.len(),
+            if draws.len() == 1 { "" } else { "s" }
+        );
+        if let Some(reason) = run["stopped"].as_str() {
+            out.push_str(&format!(" (stopped early: {reason})"));
+        }
+        let mut shown = Vec::new();
+        for draw in &draws {
+            let at = |key: &str| draw[key].as_u64().unwrap_or(0);
+            out.push_str(&format!(
+                "\n  0x{:03X}: DRW I=0x{:03X} at ({}, {}) height {}{}",
+                at("pc"),
+                at("i"),
+                at("x"),
+                at("y"),
+                at("height"),
+                if draw["collision"].as_bool().unwrap_or(false) { ", collision" } else { "" }
+            ));
+            if !shown.contains(&(at("i"), at("height"))) {
+                shown.push((at("i"), at("height")));
+            }
+        }
+        // Each distinct sprite once, as it is in memory now
+        for (addr, height) in shown {
+            let resp = self.send_cmd(json!({"cmd": "get_sprites", "a": addr, "h": height})).await?;
+            if let Some(sprite) = resp["sprites"].get(0) {
+                out.push_str(&format!("\n\n{}", Self::render_sprite(sprite)));
+            }
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!("```\n{out}\n```"))]))
+    }
+
     #[tool(description = "Set breakpoint at memory address")]
     async fn set_breakpoint(
         &self,
@@ -273,6 +686,109 @@ impl Chip8Debug {
         ))]))
     }
 
+    #[tool(
+        description = "Start a RAM search: snapshot memory and make every address in the range a candidate. Narrow it down with search_memory_filter."
+    )]
+    async fn search_memory_start(
+        &self,
+        Parameters(SearchStartParam { start, end }): Parameters<SearchStartParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut cmd = json!({"cmd": "search_start"});
+        if let Some(start) = start {
+            cmd["s"] = start.into();
+        }
+        if let Some(end) = end {
+            cmd["e"] = end.into();
+        }
+        let resp = self.send_cmd(cmd).await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Search started, {} candidates",
+            resp["count"].as_u64().unwrap_or(0)
+        ))]))
+    }
+
+    #[tool(
+        description = "Keep the RAM search candidates whose value is equal/changed/increased/decreased since the last snapshot, or now equals a value, then snapshot again. E.g. lose a life, filter decreased."
+    )]
+    async fn search_memory_filter(
+        &self,
+        Parameters(SearchFilterParam { filter, value }): Parameters<SearchFilterParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let resp = self
+            .send_cmd(json!({"cmd": "search_filter", "op": filter, "value": value}))
+            .await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
+        }
+        Ok(CallToolResult::success(vec![Content::text(Self::format_candidates(&resp))]))
+    }
+
+    #[tool(description = "List the remaining RAM search candidates with their current values")]
+    async fn search_memory_results(&self) -> Result<CallToolResult, McpError> {
+        let resp = self.send_cmd(json!({"cmd": "search_results"})).await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
+        }
+        Ok(CallToolResult::success(vec![Content::text(Self::format_candidates(&resp))]))
+    }
+
+    #[tool(
+        description = "Add a cheat: write a value to an address every frame, or freeze it at its current value if none is given"
+    )]
+    async fn add_cheat(
+        &self,
+        Parameters(CheatParam { address, value, name }): Parameters<CheatParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let resp = self
+            .send_cmd(json!({"cmd": "add_cheat", "a": address, "value": value, "name": name}))
+            .await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
+        }
+        let value = resp["cheat"]["value"].as_u64().unwrap_or(0);
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Cheat set: 0x{address:03X} = 0x{value:02X}"
+        ))]))
+    }
+
+    #[tool(description = "Remove the cheat on an address")]
+    async fn remove_cheat(
+        &self,
+        Parameters(AddressParam { address }): Parameters<AddressParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let resp = self.send_cmd(json!({"cmd": "remove_cheat", "a": address})).await?;
+        let text = if resp["removed"].as_bool().unwrap_or(false) {
+            format!("Cheat removed from 0x{address:03X}")
+        } else {
+            format!("No cheat on 0x{address:03X}")
+        };
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    #[tool(description = "List active cheats")]
+    async fn list_cheats(&self) -> Result<CallToolResult, McpError> {
+        let resp = self.send_cmd(json!({"cmd": "list_cheats"})).await?;
+        let cheats = resp["cheats"].as_array().cloned().unwrap_or_default();
+        if cheats.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text("No cheats")]));
+        }
+        let lines: Vec<String> = cheats
+            .iter()
+            .map(|c| {
+                let addr = c["addr"].as_u64().unwrap_or(0);
+                let value = c["value"].as_u64().unwrap_or(0);
+                match c["name"].as_str() {
+                    Some(name) => format!("0x{addr:03X} = 0x{value:02X}  {name}"),
+                    None => format!("0x{addr:03X} = 0x{value:02X}"),
+                }
+            })
+            .collect();
+        Ok(CallToolResult::success(vec![Content::text(lines.join("\n"))]))
+    }
+
     #[tool(description = "Execute a single instruction, then pause again")]
     async fn step(&self) -> Result<CallToolResult, McpError> {
         self.send_cmd(json!({"cmd": "step"})).await?;
@@ -324,6 +840,87 @@ impl Chip8Debug {
         Ok(CallToolResult::success(vec![Content::text(out)]))
     }
 
+    #[tool(
+        description = "Run exactly N frames (N * speed instructions, N timer ticks), then pause. Stops early at a breakpoint."
+    )]
+    async fn run_frames(
+        &self,
+        Parameters(FramesParam { frames }): Parameters<FramesParam>,
+    ) -> Result<CallToolResult, McpError> {
+        if frames == 0 {
+            return Err(McpError::invalid_params("frames must be at least 1", None));
+        }
+        let resp = self.advance(frames, None).await?;
+        Ok(CallToolResult::success(vec![Content::text(Self::describe_run(&resp))]))
+    }
+
+    #[tool(
+        description = "Hold a CHIP-8 hex key (0x0-0xF) for exactly `hold` frames, run until `frames` frames have passed, then pause. Stops early at a breakpoint."
+    )]
+    async fn press_for_frames(
+        &self,
+        Parameters(PressForFramesParam { key, hold, frames }): Parameters<PressForFramesParam>,
+    ) -> Result<CallToolResult, McpError> {
+        if key > 0x0F {
+            return Err(McpError::invalid_params("key must be 0x0-0xF", None));
+        }
+        let frames = frames.unwrap_or(hold);
+        if hold == 0 || frames < hold {
+            return Err(McpError::invalid_params("hold must be at least 1 and frames at least hold", None));
+        }
+        let resp = self.advance(frames, Some((key, hold))).await?;
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Key 0x{key:X} held for {hold} frame{}\n{}",
+            if hold == 1 { "" } else { "s" },
+            Self::describe_run(&resp)
+        ))]))
+    }
+
+    #[tool(
+        description = "Wait for the next debug event (breakpoint_hit, paused, fault, frame_drawn) instead of polling. With resume=true, resumes execution first, e.g. to run to the next breakpoint."
+    )]
+    async fn wait_event(
+        &self,
+        Parameters(WaitEventParam { events, timeout_ms, resume }): Parameters<WaitEventParam>,
+    ) -> Result<CallToolResult, McpError> {
+        const EVENTS: &[&str] = &["breakpoint_hit", "paused", "fault", "frame_drawn"];
+        if let Some(bad) = events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+            return Err(McpError::invalid_params(
+                format!("unknown event '{bad}' (expected one of: {})", EVENTS.join(", ")),
+                None,
+            ));
+        }
+        // Listen before resuming so an event right after it isn't missed
+        let mut rx = self.connection().await?.events();
+        if resume {
+            self.send_cmd(json!({"cmd": "continue"})).await?;
+        }
+
+        let timeout = Duration::from_millis(timeout_ms.unwrap_or(5000));
+        let wait = async {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => {
+                        let name = event["event"].as_str().unwrap_or("");
+                        if events.is_empty() || events.iter().any(|e| e == name) {
+                            return Some(event);
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        };
+        match tokio::time::timeout(timeout, wait).await {
+            Ok(Some(event)) => Ok(CallToolResult::success(vec![Content::text(Self::describe_event(&event))])),
+            Ok(None) => Err(McpError::internal_error(ERR_MSG, None)),
+            Err(_) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "No event within {} ms",
+                timeout.as_millis()
+            ))])),
+        }
+    }
+
     #[tool(description = "Press and hold a CHIP-8 hex key (0x0-0xF). Use key_release to release.")]
     async fn key_press(
         &self,
@@ -355,7 +952,7 @@ impl Chip8Debug {
     }
 
     #[tool(
-        description = "Press and release a CHIP-8 hex key (0x0-0xF) after a short delay. Use this for single key taps."
+        description = "Tap a CHIP-8 hex key (0x0-0xF): hold it for 3 frames and run 6 frames in total, then pause. Use this for single key taps."
     )]
     async fn key_press_and_release(
         &self,
@@ -364,18 +961,15 @@ impl Chip8Debug {
         if key > 0x0F {
             return Err(McpError::invalid_params("key must be 0x0-0xF", None));
         }
-        self.send_cmd(json!({"cmd": "key_press", "key": key}))
-            .await?;
-        tokio::time::sleep(Duration::from_millis(200)).await;
-        self.send_cmd(json!({"cmd": "key_release", "key": key}))
-            .await?;
+        let resp = self.advance(TAP_FRAMES, Some((key, TAP_HOLD_FRAMES))).await?;
         Ok(CallToolResult::success(vec![Content::text(format!(
-            "Key 0x{key:X} pressed and released"
+            "Key 0x{key:X} pressed and released\n{}",
+            Self::describe_run(&resp)
         ))]))
     }
 
     #[tool(
-        description = "Press and release a CHIP-8 hex key (0x0-0xF) with a delay, optionally repeating, then run a script or return full state JSON."
+        description = "Tap a CHIP-8 hex key (0x0-0xF) for 3 of 6 frames, optionally repeating, then pause and run a Rhai script or return full state JSON."
     )]
     async fn key_tap_and_get_screen(
         &self,
@@ -386,12 +980,15 @@ impl Chip8Debug {
         }
         let n = repeat.clamp(1, 10);
         for _ in 0..n {
-            self.send_cmd(json!({"cmd": "key_press", "key": key}))
-                .await?;
-            tokio::time::sleep(Duration::from_millis(50)).await;
-            self.send_cmd(json!({"cmd": "key_release", "key": key}))
-                .await?;
-            tokio::time::sleep(Duration::from_millis(30)).await;
+            self.advance(TAP_FRAMES, Some((key, TAP_HOLD_FRAMES))).await?;
+        }
+
+        if let Some(script_path) = path {
+            let script = std::fs::read_to_string(&script_path)
+                .map_err(|e| McpError::internal_error(format!("read script {script_path}: {e}"), None))?;
+            let mut result = format!("Key 0x{key:X} pressed x{n}\n");
+            result.push_str(&self.eval_script(script).await?);
+            return Ok(CallToolResult::success(vec![Content::text(result)]));
         }
 
         let resp = self.send_cmd(json!({"cmd": "get_state"})).await?;
@@ -404,73 +1001,24 @@ impl Chip8Debug {
         let dt = resp["dt"].as_u64().unwrap_or(0) as u8;
         let st = resp["st"].as_u64().unwrap_or(0) as u8;
 
-        let mut result = format!("Key 0x{key:X} pressed x{n}\n");
-
-        if let Some(script_path) = path {
-            let script = std::fs::read_to_string(&script_path)
-                .map_err(|e| McpError::internal_error(format!("read script {script_path}: {e}"), None))?;
-
-            let input = serde_json::json!({
-                "pixels": pixels,
-                "v_regs": v,
-                "pc": pc,
-                "i": i,
-                "delay": dt,
-                "sound": st,
-            });
-
-            let mut child = std::process::Command::new("python3")
-                .arg("-c")
-                .arg(&script)
-                .stdin(std::process::Stdio::piped())
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::piped())
-                .spawn()
-                .map_err(|e| McpError::internal_error(format!("python3 spawn failed: {e}"), None))?;
-
-            use std::io::Write;
-            if let Some(mut stdin) = child.stdin.take() {
-                let input_str = serde_json::to_string(&input)
-                    .map_err(|e| McpError::internal_error(e.to_string(), None))?;
-                stdin.write_all(input_str.as_bytes())
-                    .map_err(|e| McpError::internal_error(e.to_string(), None))?;
-            }
-
-            let output = child.wait_with_output()
-                .map_err(|e| McpError::internal_error(e.to_string(), None))?;
-
-            if !output.stdout.is_empty() {
-                result.push_str(&String::from_utf8_lossy(&output.stdout));
-            }
-            if !output.stderr.is_empty() {
-                result.push_str("\n--- stderr ---\n");
-                result.push_str(&String::from_utf8_lossy(&output.stderr));
-            }
-            if !output.status.success() {
-                result.insert_str(0, &format!("[exit code {}]\n", output.status.code().unwrap_or(-1)));
-            }
-
-            Ok(CallToolResult::success(vec![Content::text(result)]))
-        } else {
-            let state_json = serde_json::json!({
-                "pixels": pixels,
-                "v_regs": v,
-                "pc": pc,
-                "i": i,
-                "stack": stack,
-                "delay": dt,
-                "sound": st,
-            });
-            let formatted = serde_json::to_string_pretty(&state_json)
-                .map_err(|e| McpError::internal_error(e.to_string(), None))?;
-            Ok(CallToolResult::success(vec![Content::text(format!(
-                "Key 0x{key:X} pressed and released\n\n```json\n{formatted}\n```"
-            ))]))
-        }
+        let state_json = serde_json::json!({
+            "pixels": pixels,
+            "v_regs": v,
+            "pc": pc,
+            "i": i,
+            "stack": stack,
+            "delay": dt,
+            "sound": st,
+        });
+        let formatted = serde_json::to_string_pretty(&state_json)
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Key 0x{key:X} pressed and released\n\n```json\n{formatted}\n```"
+        ))]))
     }
 
     #[tool(
-        description = "Press and release a CHIP-8 hex key (0x0-0xF) with a delay, then return SCREEN DIFF. Shows only pixels that changed. Use this to see exactly what a key press does."
+        description = "Tap a CHIP-8 hex key (0x0-0xF) for 3 of 6 frames, pause, then return SCREEN DIFF. Shows only pixels that changed. Use this to see exactly what a key press does."
     )]
     async fn key_tap_and_get_diff(
         &self,
@@ -480,103 +1028,97 @@ impl Chip8Debug {
             return Err(McpError::invalid_params("key must be 0x0-0xF", None));
         }
 
-        // 1. Screen before
-        let before_resp = self.send_cmd(json!({"cmd": "get_screen"})).await?;
-        let before: Vec<Vec<bool>> =
-            serde_json::from_value(before_resp["pixels"].clone()).unwrap_or_default();
-
-        // 2. Key press
-        self.send_cmd(json!({"cmd": "key_press", "key": key})).await?;
-        tokio::time::sleep(Duration::from_millis(200)).await;
-        self.send_cmd(json!({"cmd": "key_release", "key": key})).await?;
-        // Give emulator time to process key and settle at next FX0A
-        tokio::time::sleep(Duration::from_millis(100)).await;
-
-        // 3. Screen after
-        let after_resp = self.send_cmd(json!({"cmd": "get_screen"})).await?;
-        let after: Vec<Vec<bool>> =
-            serde_json::from_value(after_resp["pixels"].clone()).unwrap_or_default();
+        // Key tap, frame-accurate so the game sees exactly one press
+        let run = self.advance(TAP_FRAMES, Some((key, TAP_HOLD_FRAMES))).await?;
 
-        // 4. Compute diff
-        let (changes, mini_map, highlighted) = Self::diff_screens(&before, &after);
+        // Diff the frame before the tap with the last one the debugger kept
+        let after = run["frame"].as_u64().unwrap_or(0);
+        let before = after.saturating_sub(run["frames"].as_u64().unwrap_or(0));
+        let resp = self
+            .send_cmd(json!({"cmd": "diff_frames", "a": before, "b": after, "pixels": true}))
+            .await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::internal_error(err.to_string(), None));
+        }
 
         let mut out = format!("Key 0x{key:X} pressed and released\n");
-
-        if changes.is_empty() {
+        if resp["changed"].as_u64() == Some(0) {
             out.push_str("No pixels changed. Key not registered or no-op.\n");
         } else {
-            let on_count = changes.iter().filter(|c| c.2).count();
-            let off_count = changes.len() - on_count;
-            out.push_str(&format!("Pixels changed: {} ({} on, {} off)\n\n", changes.len(), on_count, off_count));
-            out.push_str("Coordinates:\n");
-            for (x, y, on) in &changes {
-                out.push_str(&format!("  ({x:2},{y:2}) -> {}\n", if *on { "ON " } else { "OFF" }));
-            }
-            out.push('\n');
-            out.push_str(&mini_map);
-            out.push_str("\n\nHighlighted screen (@=new ·=off █=unchanged):\n");
-            out.push_str(&format!("```\n{highlighted}\n```"));
+            out.push_str(&Self::describe_diff(&resp));
         }
 
         Ok(CallToolResult::success(vec![Content::text(out)]))
     }
 
     #[tool(
-        description = "Run a Python script file to analyze the current screen. Screen pixels (64x32 bool array) + regs passed as JSON stdin. Script path: /tmp/foo.py or wherever you save it."
+        description = "List the last frames the debugger kept (default 120 of them): frame number, instructions run since power-on, whether it drew, and how many pixels changed from the frame before"
     )]
-    async fn screen_script(
+    async fn get_frame_history(
         &self,
-        Parameters(ScriptParam { path }): Parameters<ScriptParam>,
+        Parameters(FrameHistoryParam { count }): Parameters<FrameHistoryParam>,
     ) -> Result<CallToolResult, McpError> {
-        let script = std::fs::read_to_string(&path)
-            .map_err(|e| McpError::internal_error(format!("read script {path}: {e}"), None))?;
-
-        let resp = self.send_cmd(json!({"cmd": "get_screen"})).await?;
-        let pixels: Vec<Vec<bool>> =
-            serde_json::from_value(resp["pixels"].clone()).unwrap_or_default();
-        let regs = &resp;
-
-        let input = serde_json::json!({
-            "pixels": pixels,
-            "v_regs": regs.get("v_regs"),
-            "pc": regs.get("pc"),
-            "i": regs.get("i"),
-            "delay": regs.get("delay"),
-            "sound": regs.get("sound"),
-        });
-
-        let mut child = std::process::Command::new("python3")
-            .arg("-c")
-            .arg(&script)
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .spawn()
-            .map_err(|e| McpError::internal_error(format!("python3 spawn failed: {e}"), None))?;
-
-        use std::io::Write;
-        if let Some(mut stdin) = child.stdin.take() {
-            let input_str = serde_json::to_string(&input)
-                .map_err(|e| McpError::internal_error(e.to_string(), None))?;
-            stdin.write_all(input_str.as_bytes())
-                .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        let resp = self
+            .send_cmd(json!({"cmd": "get_frame_history", "n": count.unwrap_or(20)}))
+            .await?;
+        let frames = resp["frames"].as_array().cloned().unwrap_or_default();
+        if frames.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text("Frame history is off")]));
         }
+        let mut out = String::from(" Frame     Cycles  Drawn  Changed");
+        for f in &frames {
+            out.push_str(&format!(
+                "\n{:6} {:10}  {:5}  {:7}",
+                f["frame"].as_u64().unwrap_or(0),
+                f["cycles"].as_u64().unwrap_or(0),
+                if f["drawn"].as_bool().unwrap_or(false) { "yes" } else { "" },
+                f["changed"].as_u64().unwrap_or(0)
+            ));
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!("```\n{out}\n```"))]))
+    }
 
-        let output = child.wait_with_output()
-            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
-
-        let mut result = String::new();
-        if !output.stdout.is_empty() {
-            result.push_str(&String::from_utf8_lossy(&output.stdout));
+    #[tool(
+        description = "Diff two frames from the debugger's frame history: changed pixels, bounding boxes of changed regions, and the screen with changes marked. Defaults to the last frame against the one before."
+    )]
+    async fn diff_frames(
+        &self,
+        Parameters(DiffFramesParam { a, b }): Parameters<DiffFramesParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut cmd = json!({"cmd": "diff_frames", "pixels": true});
+        if let Some(a) = a {
+            cmd["a"] = a.into();
         }
-        if !output.stderr.is_empty() {
-            result.push_str("\n--- stderr ---\n");
-            result.push_str(&String::from_utf8_lossy(&output.stderr));
+        if let Some(b) = b {
+            cmd["b"] = b.into();
         }
-        if !output.status.success() {
-            result.insert_str(0, &format!("[exit code {}]\n", output.status.code().unwrap_or(-1)));
+        let resp = self.send_cmd(cmd).await?;
+        if let Some(err) = resp["error"].as_str() {
+            return Err(McpError::invalid_params(err.to_string(), None));
         }
+        let mut out = format!(
+            "Frame {} -> {}\n",
+            resp["a"].as_u64().unwrap_or(0),
+            resp["b"].as_u64().unwrap_or(0)
+        );
+        out.push_str(&Self::describe_diff(&resp));
+        Ok(CallToolResult::success(vec![Content::text(out)]))
+    }
 
+    #[tool(
+        description = "Run a Rhai script (from `path`, or inline `code`) that reads and drives the emulator. Reading: pixels() (32 rows of 64 bools), regs() (v, i, pc, stack, dt, st, frame), memory(start, end), peek(addr). Driving: tap(key), press(key)/release(key), press_for_frames(key, hold, n), run_frames(n), step(), pause(), resume(), poke(addr, value), set_breakpoint(addr)/clear_breakpoint(addr), wait_event(events, timeout_ms[, resume]). Returns what the script prints and its final value."
+    )]
+    async fn run_script(
+        &self,
+        Parameters(ScriptParam { path, code }): Parameters<ScriptParam>,
+    ) -> Result<CallToolResult, McpError> {
+        let script = match (path, code) {
+            (_, Some(code)) => code,
+            (Some(path), None) => std::fs::read_to_string(&path)
+                .map_err(|e| McpError::internal_error(format!("read script {path}: {e}"), None))?,
+            (None, None) => return Err(McpError::invalid_params("give a script `path` or `code`", None)),
+        };
+        let result = self.eval_script(script).await?;
         Ok(CallToolResult::success(vec![Content::text(result)]))
     }
 }
@@ -612,7 +1154,11 @@ async fn main() -> Result<(), anyhow::Error> {
         .and_then(|p| p.parse().ok())
         .unwrap_or(9876);
 
-    let server = Chip8Debug { port };
+    let server = Chip8Debug {
+        port,
+        emulator: Default::default(),
+        conn: Default::default(),
+    };
     server.serve(stdio()).await?.waiting().await?;
 
     Ok(())
@@ -0,0 +1,312 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map};
+use serde_json::{json, Value};
+use tokio::runtime::Handle;
+
+use crate::connection::Connection;
+use crate::{TAP_FRAMES, TAP_HOLD_FRAMES};
+
+/// Scripts running longer than this are stopped. The limit is checked
+/// between script operations, so it cannot cut short a call already waiting
+/// on the emulator: a long `run_frames(n)` finishes before the script stops.
+const TIME_LIMIT: Duration = Duration::from_secs(60);
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// The machine as scripts see it. Every call is a request to the emulator,
+/// made from the blocking thread the script runs on.
+#[derive(Clone)]
+struct Machine {
+    conn: Arc<Connection>,
+    runtime: Handle,
+}
+
+impl Machine {
+    fn request(&self, cmd: Value) -> ScriptResult<Value> {
+        let resp = self.runtime.block_on(self.conn.request(cmd))?;
+        match resp["error"].as_str() {
+            Some(err) => Err(err.into()),
+            None => Ok(resp),
+        }
+    }
+
+    fn pixels(&self) -> ScriptResult<Array> {
+        let resp = self.request(json!({"cmd": "get_screen"}))?;
+        let pixels: Vec<Vec<bool>> = serde_json::from_value(resp["pixels"].clone()).unwrap_or_default();
+        Ok(pixels
+            .into_iter()
+            .map(|row| Dynamic::from_array(row.into_iter().map(Dynamic::from).collect()))
+            .collect())
+    }
+
+    fn regs(&self) -> ScriptResult<Map> {
+        let resp = self.request(json!({"cmd": "get_registers"}))?;
+        let mut regs = Map::new();
+        for (name, key) in [("v", "v_regs"), ("i", "i"), ("pc", "pc"), ("stack", "stack"), ("dt", "delay"), ("st", "sound"), ("frame", "frame")] {
+            regs.insert(name.into(), rhai::serde::to_dynamic(&resp[key])?);
+        }
+        Ok(regs)
+    }
+
+    fn memory(&self, start: i64, end: i64) -> ScriptResult<Array> {
+        if !(0..=end).contains(&start) || end > 4096 {
+            return Err(format!("invalid memory range {start}..{end}").into());
+        }
+        let resp = self.request(json!({"cmd": "get_memory", "s": start, "e": end}))?;
+        Ok(rhai::serde::to_dynamic(&resp["data"])?.into_array()?)
+    }
+
+    fn poke(&self, addr: i64, value: i64) -> ScriptResult<()> {
+        if !(0..4096).contains(&addr) {
+            return Err(format!("address {addr:#x} outside memory").into());
+        }
+        let value = u8::try_from(value).map_err(|_| format!("value must be 0x00-0xFF, got {value:#x}"))?;
+        self.request(json!({"cmd": "set_memory", "a": addr, "data": [value]}))?;
+        Ok(())
+    }
+
+    /// Runs `frames` frames, holding `key` for the first `hold`, and returns
+    /// how many ran before a breakpoint or fault stopped them.
+    fn advance(&self, frames: i64, key: Option<(i64, i64)>) -> ScriptResult<i64> {
+        if frames < 1 {
+            return Err("frames must be at least 1".into());
+        }
+        let cmd = match key {
+            Some((key, hold)) => json!({"cmd": "press_for_frames", "key": check_key(key)?, "hold": hold, "n": frames}),
+            None => json!({"cmd": "run_frames", "n": frames}),
+        };
+        Ok(self.request(cmd)?["frames"].as_i64().unwrap_or(0))
+    }
+
+    /// The next of `events` (any if empty) within `timeout_ms`, or `()`.
+    /// Listens before resuming, so nothing is missed.
+    fn wait_event(&self, events: Array, timeout_ms: i64, resume: bool) -> ScriptResult<Dynamic> {
+        let names: Vec<String> = events.into_iter().map(|e| e.to_string()).collect();
+        let mut rx = self.conn.events();
+        if resume {
+            self.request(json!({"cmd": "continue"}))?;
+        }
+        let wait = async {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => {
+                        let name = event["event"].as_str().unwrap_or("");
+                        if names.is_empty() || names.iter().any(|n| n == name) {
+                            return Some(event);
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(_) => return None,
+                }
+            }
+        };
+        let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+        match self.runtime.block_on(async { tokio::time::timeout(timeout, wait).await }) {
+            Ok(Some(event)) => Ok(rhai::serde::to_dynamic(&event)?),
+            _ => Ok(Dynamic::UNIT),
+        }
+    }
+}
+
+fn check_key(key: i64) -> ScriptResult<i64> {
+    if (0..=0xF).contains(&key) {
+        Ok(key)
+    } else {
+        Err(format!("key must be 0x0-0xF, got {key:#x}").into())
+    }
+}
+
+fn check_addr(addr: i64) -> ScriptResult<i64> {
+    if (0..4096).contains(&addr) {
+        Ok(addr)
+    } else {
+        Err(format!("address {addr:#x} outside memory").into())
+    }
+}
+
+/// Runs a Rhai script against the emulator behind `conn`. Blocks, so call
+/// it from a blocking task. Returns what the script printed, its value if it
+/// has one, and the error that stopped it, if any.
+pub fn run(conn: Arc<Connection>, runtime: Handle, source: &str) -> String {
+    let output = Arc::new(Mutex::new(String::new()));
+    let mut engine = Engine::new();
+    let out = output.clone();
+    engine.on_print(move |text| {
+        let mut out = out.lock().unwrap();
+        out.push_str(text);
+        out.push('\n');
+    });
+    let out = output.clone();
+    engine.on_debug(move |text, _, pos| {
+        let mut out = out.lock().unwrap();
+        out.push_str(&format!("[{pos}] {text}\n"));
+    });
+    let deadline = Instant::now() + TIME_LIMIT;
+    engine.on_progress(move |_| (Instant::now() > deadline).then(|| Dynamic::from("time limit exceeded")));
+    register(&mut engine, Machine { conn, runtime });
+
+    let result = engine.eval::<Dynamic>(source);
+    let mut out = std::mem::take(&mut *output.lock().unwrap());
+    match result {
+        Ok(value) if value.is_unit() => {}
+        Ok(value) => out.push_str(&format!("{value}\n")),
+        Err(err) => {
+            out.push_str("\n--- error ---\n");
+            out.push_str(&err.to_string());
+        }
+    }
+    out
+}
+
+fn register(engine: &mut Engine, machine: Machine) {
+    let m = machine.clone();
+    engine.register_fn("pixels", move || m.pixels());
+    let m = machine.clone();
+    engine.register_fn("regs", move || m.regs());
+    let m = machine.clone();
+    engine.register_fn("memory", move |start: i64, end: i64| m.memory(start, end));
+    let m = machine.clone();
+    engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> {
+        Ok(m.memory(addr, addr + 1)?[0].as_int()?)
+    });
+    let m = machine.clone();
+    engine.register_fn("poke", move |addr: i64, value: i64| m.poke(addr, value));
+
+    let m = machine.clone();
+    engine.register_fn("press", move |key: i64| -> ScriptResult<()> {
+        m.request(json!({"cmd": "key_press", "key": check_key(key)?}))?;
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("release", move |key: i64| -> ScriptResult<()> {
+        m.request(json!({"cmd": "key_release", "key": check_key(key)?}))?;
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("tap", move |key: i64| {
+        m.advance(TAP_FRAMES as i64, Some((key, TAP_HOLD_FRAMES as i64)))
+    });
+    let m = machine.clone();
+    engine.register_fn("press_for_frames", move |key: i64, hold: i64, frames: i64| {
+        m.advance(frames, Some((key, hold)))
+    });
+    let m = machine.clone();
+    engine.register_fn("run_frames", move |frames: i64| m.advance(frames, None));
+
+    let m = machine.clone();
+    engine.register_fn("step", move || -> ScriptResult<()> {
+        let mut rx = m.conn.events();
+        m.request(json!({"cmd": "step"}))?;
+        // The step is done once the emulator reports the pause after it
+        let paused = async {
+            while let Ok(event) = rx.recv().await {
+                if event["event"] == "paused" {
+                    break;
+                }
+            }
+        };
+        m.runtime
+            .block_on(async { tokio::time::timeout(Duration::from_secs(1), paused).await })
+            .map_err(|_| "step did not complete".into())
+    });
+    let m = machine.clone();
+    engine.register_fn("pause", move || -> ScriptResult<()> {
+        m.request(json!({"cmd": "pause"}))?;
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("resume", move || -> ScriptResult<()> {
+        m.request(json!({"cmd": "continue"}))?;
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("set_breakpoint", move |addr: i64| -> ScriptResult<()> {
+        m.request(json!({"cmd": "set_bp", "a": check_addr(addr)?}))?;
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("clear_breakpoint", move |addr: i64| -> ScriptResult<()> {
+        m.request(json!({"cmd": "clear_bp", "a": check_addr(addr)?}))?;
+        Ok(())
+    });
+
+    let m = machine.clone();
+    engine.register_fn("wait_event", move |events: Array, timeout_ms: i64| m.wait_event(events, timeout_ms, false));
+    engine.register_fn("wait_event", move |events: Array, timeout_ms: i64, resume: bool| {
+        machine.wait_event(events, timeout_ms, resume)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Runs `source` against a server that answers `pause` with an error and
+    /// anything else with `{"frames": 6}`. Returns the script output and the
+    /// commands sent.
+    async fn run_script(source: &str) -> (String, Vec<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut cmds = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let mut req: Value = serde_json::from_str(&line).unwrap();
+                let reply = match req["cmd"].as_str() {
+                    Some("pause") => json!({"id": req["id"], "error": "already paused"}),
+                    _ => json!({"id": req["id"], "frames": 6}),
+                };
+                write.write_all(format!("{reply}\n").as_bytes()).await.unwrap();
+                req.as_object_mut().unwrap().remove("id");
+                if req["cmd"] != "subscribe" {
+                    cmds.push(req);
+                }
+            }
+            cmds
+        });
+
+        let conn = Connection::open(port).await.unwrap();
+        let source = source.to_string();
+        let runtime = Handle::current();
+        let out = tokio::task::spawn_blocking(move || run(conn, runtime, &source)).await.unwrap();
+        (out, server.await.unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_script_commands() {
+        let (out, cmds) = run_script("tap(5); print(run_frames(10)); poke(0x300, 0xAB)").await;
+        assert_eq!(out, "6\n");
+        assert_eq!(
+            cmds,
+            [
+                json!({"cmd": "press_for_frames", "key": 5, "hold": TAP_HOLD_FRAMES, "n": TAP_FRAMES}),
+                json!({"cmd": "run_frames", "n": 10}),
+                json!({"cmd": "set_memory", "a": 0x300, "data": [0xAB]}),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_script_error() {
+        let (out, cmds) = run_script("print(\"before\"); poke(0x300, 256); print(\"after\")").await;
+        assert!(out.starts_with("before\n\n--- error ---\n"), "{out}");
+        assert!(out.contains("value must be 0x00-0xFF, got 0x100"), "{out}");
+        assert!(!out.contains("after"));
+        assert!(cmds.is_empty());
+
+        let (out, cmds) = run_script("tap(16)").await;
+        assert!(out.contains("key must be 0x0-0xF, got 0x10"), "{out}");
+        assert!(cmds.is_empty());
+
+        // Errors from the emulator stop the script too
+        let (out, cmds) = run_script("pause(); run_frames(1)").await;
+        assert!(out.contains("already paused"), "{out}");
+        assert_eq!(cmds, [json!({"cmd": "pause"})]);
+    }
+}
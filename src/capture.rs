@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+
+/// Largest upscaling accepted for captures, 64x32 pixels becoming 2048x1024.
+pub const MAX_SCALE: usize = 32;
+
+/// Colours of unlit and lit pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub off: [u8; 3],
+    pub on: [u8; 3],
+}
+
+impl Default for Palette {
+    /// Black and white, like the window.
+    fn default() -> Self {
+        Palette { off: [0x00; 3], on: [0xFF; 3] }
+    }
+}
+
+impl Palette {
+    /// Parses `off,on` with each colour as `RRGGBB`, optionally `#`-prefixed.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let colour = |c: &str| -> Result<[u8; 3], String> {
+            let hex = c.trim().trim_start_matches('#');
+            let value = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .ok_or_else(|| format!("invalid colour '{c}', expected RRGGBB"))?;
+            let [_, r, g, b] = value.to_be_bytes();
+            Ok([r, g, b])
+        };
+        let (off, on) = text
+            .split_once(',')
+            .ok_or_else(|| format!("invalid palette '{text}', expected off,on"))?;
+        Ok(Palette { off: colour(off)?, on: colour(on)? })
+    }
+
+    fn table(&self) -> Vec<u8> {
+        [self.off, self.on].concat()
+    }
+}
+
+/// Palette indices of `pixels` with every pixel grown to `scale`x`scale`.
+fn indices(pixels: &[Vec<bool>], scale: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(pixels.len() * pixels.first().map_or(0, Vec::len) * scale * scale);
+    for row in pixels {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|&lit| std::iter::repeat_n(lit as u8, scale))
+            .collect();
+        for _ in 0..scale {
+            out.extend_from_slice(&line);
+        }
+    }
+    out
+}
+
+fn size(pixels: &[Vec<bool>], scale: usize) -> (usize, usize) {
+    (pixels.first().map_or(0, Vec::len) * scale, pixels.len() * scale)
+}
+
+/// Encodes a screen as an indexed PNG.
+pub fn png(pixels: &[Vec<bool>], scale: usize, palette: Palette) -> Result<Vec<u8>, String> {
+    let (width, height) = size(pixels, scale);
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette.table());
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&indices(pixels, scale)))
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// Encodes screens captured once per frame at 60 Hz as a looping GIF.
+/// Runs of identical frames become one longer frame.
+pub fn gif(frames: &[Vec<Vec<bool>>], scale: usize, palette: Palette) -> Result<Vec<u8>, String> {
+    let Some(first) = frames.first() else {
+        return Err("no frames to encode".to_string());
+    };
+    let (width, height) = size(first, scale);
+    let mut out = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut out, width as u16, height as u16, &palette.table())
+            .map_err(|e| e.to_string())?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| e.to_string())?;
+
+        // GIF delays are in hundredths of a second, frames last 1/60 s; the
+        // rounding error is carried so the total length stays right
+        let mut elapsed = 0;
+        let mut shown = 0;
+        let mut start = 0;
+        while start < frames.len() {
+            let end = (start..frames.len()).find(|&i| frames[i] != frames[start]).unwrap_or(frames.len());
+            elapsed += end - start;
+            let until = elapsed * 100 / 60;
+            let frame = gif::Frame {
+                width: width as u16,
+                height: height as u16,
+                delay: (until - shown) as u16,
+                buffer: Cow::Owned(indices(&frames[start], scale)),
+                ..Default::default()
+            };
+            shown = until;
+            encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+            start = end;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_parse() {
+        let palette = Palette::parse("#102030, ffcc00").unwrap();
+        assert_eq!((palette.off, palette.on), ([0x10, 0x20, 0x30], [0xFF, 0xCC, 0x00]));
+        assert!(Palette::parse("102030").is_err());
+        assert!(Palette::parse("12345,ffffff").is_err());
+        assert!(Palette::parse("1020zz,ffffff").is_err());
+    }
+
+    #[test]
+    fn test_capture_sizes() {
+        let screen = vec![vec![false; 64]; 32];
+
+        // IHDR holds the big-endian width and height right after the signature
+        let data = png(&screen, 4, Palette::default()).unwrap();
+        assert_eq!(&data[..4], b"\x89PNG");
+        assert_eq!(u32::from_be_bytes(data[16..20].try_into().unwrap()), 256);
+        assert_eq!(u32::from_be_bytes(data[20..24].try_into().unwrap()), 128);
+
+        // The logical screen size follows the 6-byte GIF header, little-endian
+        let data = gif(&[screen.clone(), screen], 2, Palette::default()).unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+        assert_eq!(u16::from_le_bytes([data[6], data[7]]), 128);
+        assert_eq!(u16::from_le_bytes([data[8], data[9]]), 64);
+        assert!(gif(&[], 1, Palette::default()).is_err());
+    }
+}
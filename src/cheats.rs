@@ -0,0 +1,173 @@
+use serde::Serialize;
+
+/// Where programs live; searches cover this much of memory by default.
+pub const PROGRAM_START: u16 = 0x200;
+
+/// How a byte must compare with its value in the previous snapshot to stay
+/// a candidate.
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    /// Now holds exactly this value.
+    Value(u8),
+}
+
+impl Filter {
+    /// Parses a filter name; `value` is only used, and then required, by
+    /// `value`.
+    pub fn parse(name: &str, value: Option<u8>) -> Result<Self, String> {
+        match name {
+            "equal" => Ok(Filter::Equal),
+            "changed" => Ok(Filter::Changed),
+            "increased" => Ok(Filter::Increased),
+            "decreased" => Ok(Filter::Decreased),
+            "value" => value.map(Filter::Value).ok_or_else(|| "filter 'value' needs a value".to_string()),
+            _ => Err(format!(
+                "unknown filter '{name}' (expected equal, changed, increased, decreased or value)"
+            )),
+        }
+    }
+
+    fn keeps(self, before: u8, now: u8) -> bool {
+        match self {
+            Filter::Equal => now == before,
+            Filter::Changed => now != before,
+            Filter::Increased => now > before,
+            Filter::Decreased => now < before,
+            Filter::Value(value) => now == value,
+        }
+    }
+}
+
+/// A RAM search: the addresses still in the running and the memory they
+/// are compared against next.
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+/// One remaining candidate, with its value in the last two snapshots.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Candidate {
+    pub addr: u16,
+    pub before: u8,
+    pub value: u8,
+}
+
+impl Search {
+    /// Starts a search over `start..end`, every address a candidate.
+    pub fn new(memory: &[u8], start: u16, end: u16) -> Self {
+        Search {
+            snapshot: memory.to_vec(),
+            candidates: (start..end).collect(),
+        }
+    }
+
+    /// Keeps the candidates whose value in `memory` passes `filter` against
+    /// the last snapshot, then makes `memory` the snapshot.
+    pub fn filter(&mut self, memory: &[u8], filter: Filter) -> Vec<Candidate> {
+        let before = std::mem::replace(&mut self.snapshot, memory.to_vec());
+        let mut kept = Vec::new();
+        self.candidates.retain(|&addr| {
+            let (was, now) = (before[addr as usize], memory[addr as usize]);
+            let keep = filter.keeps(was, now);
+            if keep {
+                kept.push(Candidate { addr, before: was, value: now });
+            }
+            keep
+        });
+        kept
+    }
+
+    /// The remaining candidates, with `memory` as their current value.
+    pub fn candidates(&self, memory: &[u8]) -> Vec<Candidate> {
+        self.candidates
+            .iter()
+            .map(|&addr| Candidate {
+                addr,
+                before: self.snapshot[addr as usize],
+                value: memory[addr as usize],
+            })
+            .collect()
+    }
+}
+
+/// A byte of memory forced to `value` at the end of every frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct Cheat {
+    pub addr: u16,
+    pub value: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Parses a cheat list: one `ADDR VALUE [name]` per line, both numbers hex
+/// with an optional `0x`. Blank lines and `#` comments are skipped.
+pub fn parse_cheats(text: &str) -> Result<Vec<Cheat>, String> {
+    let mut cheats = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(3, char::is_whitespace);
+        let mut number = |what: &str, max: u32| -> Result<u32, String> {
+            let field = fields.next().unwrap_or("");
+            let digits = field.trim_start_matches("0x").trim_start_matches("0X");
+            u32::from_str_radix(digits, 16)
+                .ok()
+                .filter(|&v| v <= max)
+                .ok_or_else(|| format!("line {}: invalid {what} '{field}'", n + 1))
+        };
+        let addr = number("address", 0xFFF)? as u16;
+        let value = number("value", 0xFF)? as u8;
+        let name = fields.next().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+        cheats.push(Cheat { addr, value, name });
+    }
+    Ok(cheats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cheats() {
+        let text = "# lives\n0x2F0 09 infinite lives\n\n  300 ff # max ammo\n";
+        let cheats = parse_cheats(text).unwrap();
+        let parsed: Vec<(u16, u8, Option<&str>)> =
+            cheats.iter().map(|c| (c.addr, c.value, c.name.as_deref())).collect();
+        assert_eq!(parsed, [(0x2F0, 0x09, Some("infinite lives")), (0x300, 0xFF, None)]);
+
+        assert_eq!(parse_cheats("1000 1").unwrap_err(), "line 1: invalid address '1000'");
+        assert_eq!(parse_cheats("\n200 100").unwrap_err(), "line 2: invalid value '100'");
+        assert_eq!(parse_cheats("200").unwrap_err(), "line 1: invalid value ''");
+    }
+
+    #[test]
+    fn test_search_filter() {
+        let mut memory = vec![0u8; 8];
+        memory[..4].copy_from_slice(&[5, 5, 5, 5]);
+        let mut search = Search::new(&memory, 0, 4);
+
+        memory[..4].copy_from_slice(&[5, 6, 4, 7]);
+        let changed = search.filter(&memory, Filter::Changed);
+        let addrs: Vec<u16> = changed.iter().map(|c| c.addr).collect();
+        assert_eq!(addrs, [1, 2, 3]);
+        assert_eq!((changed[0].before, changed[0].value), (5, 6));
+
+        memory[..4].copy_from_slice(&[0, 9, 4, 3]);
+        let increased: Vec<u16> = search.filter(&memory, Filter::Increased).iter().map(|c| c.addr).collect();
+        assert_eq!(increased, [1]);
+
+        // Candidates compare against the snapshot taken by the last filter
+        memory[1] = 2;
+        let now = search.candidates(&memory);
+        assert_eq!((now[0].addr, now[0].before, now[0].value), (1, 9, 2));
+        assert_eq!(search.filter(&memory, Filter::Value(9)).len(), 0);
+        assert!(Filter::parse("value", None).is_err());
+    }
+}
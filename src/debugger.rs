@@ -1,17 +1,24 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::{BufRead, BufReader, Write};
 use std::net::{TcpListener, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::Duration;
 
+use base64::prelude::{Engine, BASE64_STANDARD};
 use serde::Serialize;
 
-use crate::display::Display;
+use crate::capture::{self, Palette, MAX_SCALE};
+use crate::cheats::{Cheat, Filter, Search, PROGRAM_START};
+use crate::display::{Display, HEIGHT, WIDTH};
+use crate::history::{self, FrameRecord, History};
 use crate::interpreter::convert_num_to_key;
 use crate::keyboard::DataKeys;
 use crate::memory::Memory;
 use crate::registers::Registers;
+use crate::sprites;
 
 #[derive(Clone, Serialize)]
 pub struct SharedState {
@@ -23,22 +30,133 @@ pub struct SharedState {
     pub delay: u8,
     pub sound: u8,
     pub memory: Vec<u8>,
+    /// Frames emulated since power-on.
+    pub frame: u64,
+}
+
+/// A `run_frames`/`press_for_frames` request. The emulator thread starts it
+/// at a frame boundary and pauses again once `frames` frames have run.
+struct FrameRun {
+    frames: u64,
+    /// Key held from the first frame until `hold` frames have run.
+    key: Option<u8>,
+    hold: u64,
+    ran: u64,
+    started: bool,
+    /// Once the run is over: `None` if all frames ran, else why it stopped.
+    finished: Option<Option<&'static str>>,
+    /// The screen at the end of every frame, for `record_gif`.
+    screens: Option<Vec<Vec<Vec<bool>>>>,
+}
+
+/// Most frames `record_gif` captures, ten seconds at 60 Hz; every screen is
+/// kept until the GIF is encoded.
+const MAX_GIF_FRAMES: u64 = 600;
+
+/// Events pushed to clients that sent `subscribe`.
+pub const EVENTS: &[&str] = &["breakpoint_hit", "watchpoint_hit", "paused", "fault", "frame_drawn"];
+
+/// A change to the machine requested by a client, applied by the emulator
+/// thread before its next instruction.
+pub enum Patch {
+    Memory(u16, Vec<u8>),
+    V(usize, u8),
+    I(u16),
+    Pc(u16),
+    Delay(u8),
+    Sound(u8),
+}
+
+/// How an instruction touched memory, or what a watchpoint waits for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Watchpoints only: either of the above.
+    Any,
+}
+
+impl Access {
+    pub fn name(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Any => "access",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub len: u16,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: Access, addr: u16, len: u16) -> bool {
+        let (start, end) = (self.addr as u32, self.addr as u32 + self.len as u32);
+        (self.access == Access::Any || self.access == access)
+            && (addr as u32) < end
+            && start < addr as u32 + len as u32
+    }
+}
+
+/// One `DXYN` as executed: where from, the sprite it drew and where.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Draw {
+    pub pc: u16,
+    pub i: u16,
+    pub x: u8,
+    pub y: u8,
+    pub height: u8,
+    pub collision: bool,
+}
+
+/// Draws of the frame being run and of the last one completed, kept while
+/// `record_draws` is on.
+#[derive(Default)]
+struct DrawLog {
+    current: Vec<Draw>,
+    last: Vec<Draw>,
+    /// Frame number of `last`, `None` until a frame completes.
+    frame: Option<u64>,
+}
+
+/// A connection that asked for events: where its lines go and which events
+/// it wants (`None` for all of them).
+struct Subscriber {
+    tx: Sender<String>,
+    events: Option<HashSet<String>>,
 }
 
 pub struct Debugger {
     pub state: Arc<Mutex<SharedState>>,
     pub breakpoints: Arc<Mutex<HashSet<u16>>>,
+    pub watchpoints: Mutex<Vec<Watchpoint>>,
     pub paused: Arc<AtomicBool>,
     pub step_requested: Arc<AtomicBool>,
     pub running: Arc<AtomicBool>,
     pub keyboard: Option<Arc<DataKeys>>,
+    frame_run: Mutex<Option<FrameRun>>,
+    frame_run_done: Condvar,
+    subscribers: Mutex<HashMap<u64, Subscriber>>,
+    next_client: AtomicU64,
+    /// Patches waiting for the emulator thread to apply them.
+    patches: Mutex<Vec<Patch>>,
+    /// The RAM search in progress, if any.
+    search: Mutex<Option<Search>>,
+    cheats: Mutex<Vec<Cheat>>,
+    pub recording_draws: AtomicBool,
+    draws: Mutex<DrawLog>,
+    history: Mutex<History>,
 }
 
 impl Debugger {
     pub fn new(keyboard: Option<Arc<DataKeys>>) -> Self {
         Self {
             state: Arc::new(Mutex::new(SharedState {
-                pixels: vec![vec![false; 64]; 32],
+                pixels: blank_screen(),
                 v_regs: [0; 16],
                 i: 0,
                 pc: 0,
@@ -46,15 +164,33 @@ impl Debugger {
                 delay: 0,
                 sound: 0,
                 memory: vec![0; 4096],
+                frame: 0,
             })),
             breakpoints: Arc::new(Mutex::new(HashSet::new())),
+            watchpoints: Mutex::new(Vec::new()),
             paused: Arc::new(AtomicBool::new(false)),
             step_requested: Arc::new(AtomicBool::new(false)),
             running: Arc::new(AtomicBool::new(true)),
             keyboard,
+            frame_run: Mutex::new(None),
+            frame_run_done: Condvar::new(),
+            subscribers: Mutex::new(HashMap::new()),
+            next_client: AtomicU64::new(0),
+            patches: Mutex::new(Vec::new()),
+            search: Mutex::new(None),
+            cheats: Mutex::new(Vec::new()),
+            recording_draws: AtomicBool::new(false),
+            draws: Mutex::new(DrawLog::default()),
+            history: Mutex::new(History::new(history::DEFAULT_LEN, blank_screen())),
         }
     }
 
+    /// Keeps the last `len` frames for `get_frame_history`/`diff_frames`
+    /// instead of the default; 0 keeps none. Call before the first frame.
+    pub fn set_history_len(&self, len: usize) {
+        *self.history.lock().unwrap() = History::new(len, blank_screen());
+    }
+
     pub fn update_state(&self, display: &Display, regs: &Registers, memory: &Memory) {
         let mut state = self.state.lock().unwrap();
         state.pixels = display.buf();
@@ -67,6 +203,224 @@ impl Debugger {
         state.memory.copy_from_slice(memory.as_slice());
     }
 
+    /// Changes memory or a register. Readers see the change right away, the
+    /// emulator before its next instruction. Memory bounds are the caller's
+    /// to check.
+    pub fn patch(&self, patch: Patch) {
+        {
+            let mut state = self.state.lock().unwrap();
+            match &patch {
+                Patch::Memory(addr, data) => {
+                    let start = *addr as usize;
+                    state.memory[start..start + data.len()].copy_from_slice(data);
+                }
+                Patch::V(x, value) => state.v_regs[*x] = *value,
+                Patch::I(value) => state.i = *value,
+                Patch::Pc(value) => state.pc = *value,
+                Patch::Delay(value) => state.delay = *value,
+                Patch::Sound(value) => state.sound = *value,
+            }
+        }
+        self.patches.lock().unwrap().push(patch);
+    }
+
+    /// Patches requested since the last call, oldest first.
+    pub fn take_patches(&self) -> Vec<Patch> {
+        std::mem::take(&mut *self.patches.lock().unwrap())
+    }
+
+    /// Adds a cheat, replacing any other on the same address, and applies
+    /// it right away.
+    pub fn add_cheat(&self, cheat: Cheat) {
+        self.patch(Patch::Memory(cheat.addr, vec![cheat.value]));
+        let mut cheats = self.cheats.lock().unwrap();
+        cheats.retain(|c| c.addr != cheat.addr);
+        cheats.push(cheat);
+    }
+
+    /// Writes back every cheated byte the program has changed.
+    fn apply_cheats(&self) {
+        let changed: Vec<(u16, u8)> = {
+            let cheats = self.cheats.lock().unwrap();
+            let state = self.state.lock().unwrap();
+            cheats
+                .iter()
+                .filter(|c| state.memory[c.addr as usize] != c.value)
+                .map(|c| (c.addr, c.value))
+                .collect()
+        };
+        for (addr, value) in changed {
+            self.patch(Patch::Memory(addr, vec![value]));
+        }
+    }
+
+    /// Logs a `DXYN` for `get_draws`. Only called while `recording_draws`.
+    pub fn record_draw(&self, draw: Draw) {
+        self.draws.lock().unwrap().current.push(draw);
+    }
+
+    /// The first watchpoint an access of `len` bytes at `addr` triggers.
+    pub fn watchpoint_hit(&self, access: Access, addr: u16, len: u16) -> Option<Watchpoint> {
+        let watchpoints = self.watchpoints.lock().unwrap();
+        watchpoints.iter().find(|w| w.matches(access, addr, len)).copied()
+    }
+
+    /// Starts a pending frame run. Called by the emulator before each
+    /// instruction at a frame boundary, or whenever it is paused; returns
+    /// true if a run started, so a fresh frame begins with it.
+    pub fn start_frame_run(&self) -> bool {
+        let mut run = self.frame_run.lock().unwrap();
+        let Some(run) = run.as_mut().filter(|r| !r.started) else {
+            return false;
+        };
+        run.started = true;
+        if let Some(key) = run.key {
+            self.press(key);
+        }
+        self.paused.store(false, Ordering::Relaxed);
+        true
+    }
+
+    /// Called by the emulator once `speed` instructions of a frame have run;
+    /// `drawn` is whether the frame changed the display and `cycles` how
+    /// many instructions have run since power-on.
+    pub fn end_frame(&self, drawn: bool, cycles: u64) {
+        self.apply_cheats();
+        let (frame, pixels) = {
+            let mut state = self.state.lock().unwrap();
+            state.frame += 1;
+            (state.frame, state.pixels.clone())
+        };
+        if drawn {
+            self.emit("frame_drawn", serde_json::json!({"frame": frame}));
+        }
+        if self.recording_draws.load(Ordering::Relaxed) {
+            let mut log = self.draws.lock().unwrap();
+            log.last = std::mem::take(&mut log.current);
+            log.frame = Some(frame);
+        }
+        let record = FrameRecord { frame, cycles, drawn, changed: 0, pixels: pixels.clone() };
+        self.history.lock().unwrap().push(record);
+        let mut guard = self.frame_run.lock().unwrap();
+        let Some(run) = guard.as_mut().filter(|r| r.started && r.finished.is_none()) else {
+            return;
+        };
+        run.ran += 1;
+        if let Some(screens) = run.screens.as_mut() {
+            screens.push(pixels);
+        }
+        if run.ran == run.hold {
+            if let Some(key) = run.key.take() {
+                self.release(key);
+            }
+        }
+        if run.ran == run.frames {
+            run.finished = Some(None);
+            self.pause("frame_run");
+            self.frame_run_done.notify_all();
+        }
+    }
+
+    /// Ends the current frame run early, `reason` being what stopped it.
+    pub fn interrupt_frame_run(&self, reason: &'static str) {
+        let mut guard = self.frame_run.lock().unwrap();
+        let Some(run) = guard.as_mut().filter(|r| r.started && r.finished.is_none()) else {
+            return;
+        };
+        if let Some(key) = run.key.take() {
+            self.release(key);
+        }
+        run.finished = Some(Some(reason));
+        self.frame_run_done.notify_all();
+    }
+
+    pub fn frame_run_active(&self) -> bool {
+        self.frame_run.lock().unwrap().as_ref().is_some_and(|r| r.finished.is_none())
+    }
+
+    /// Hands a run to the emulator thread and blocks until it is over. With
+    /// `record`, also returns the screen after each frame.
+    fn run_frames(&self, frames: u64, key: Option<u8>, hold: u64, record: bool) -> (serde_json::Value, Vec<Vec<Vec<bool>>>) {
+        let mut guard = self.frame_run.lock().unwrap();
+        if guard.as_ref().is_some_and(|r| r.finished.is_none()) {
+            return (serde_json::json!({"error": "a frame run is already in progress"}), Vec::new());
+        }
+        let screens = record.then(Vec::new);
+        *guard = Some(FrameRun { frames, key, hold, ran: 0, started: false, finished: None, screens });
+        loop {
+            let (next, _) = self
+                .frame_run_done
+                .wait_timeout(guard, Duration::from_millis(100))
+                .unwrap();
+            guard = next;
+            if let Some(stopped) = guard.as_ref().and_then(|r| r.finished) {
+                let run = guard.take();
+                let ran = run.as_ref().map_or(0, |r| r.ran);
+                let frame = self.state.lock().unwrap().frame;
+                let mut resp = serde_json::json!({"ok": true, "frames": ran, "frame": frame});
+                if let Some(reason) = stopped {
+                    resp["stopped"] = reason.into();
+                }
+                return (resp, run.and_then(|r| r.screens).unwrap_or_default());
+            }
+            if !self.running.load(Ordering::Relaxed) {
+                *guard = None;
+                return (serde_json::json!({"error": "emulator stopped"}), Vec::new());
+            }
+        }
+    }
+
+    /// Pauses execution and tells subscribers why.
+    pub fn pause(&self, reason: &str) {
+        self.paused.store(true, Ordering::Relaxed);
+        let pc = self.state.lock().unwrap().pc;
+        self.emit("paused", serde_json::json!({"pc": pc, "reason": reason}));
+    }
+
+    /// Subscribes an in-process listener, as `subscribe` does for a client;
+    /// event lines arrive on the returned receiver.
+    pub fn subscribe(&self, events: &[&str]) -> (u64, Receiver<String>) {
+        let id = self.next_client.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        let events = Some(events.iter().map(|e| e.to_string()).collect());
+        self.subscribers.lock().unwrap().insert(id, Subscriber { tx, events });
+        (id, rx)
+    }
+
+    pub fn unsubscribe(&self, id: u64) {
+        self.subscribers.lock().unwrap().remove(&id);
+    }
+
+    /// Sends `{"event": name, ...fields}` to every client subscribed to it.
+    pub fn emit(&self, name: &str, fields: serde_json::Value) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        let mut event = serde_json::json!({"event": name});
+        if let (Some(event), serde_json::Value::Object(fields)) = (event.as_object_mut(), fields) {
+            event.extend(fields);
+        }
+        let line = event.to_string();
+        // A failed send means the connection is gone
+        subscribers.retain(|_, sub| {
+            let wanted = sub.events.as_ref().is_none_or(|events| events.contains(name));
+            !wanted || sub.tx.send(line.clone()).is_ok()
+        });
+    }
+
+    fn press(&self, key: u8) {
+        if let Some(ref kb) = self.keyboard {
+            kb.inject_key_press(convert_num_to_key(key));
+        }
+    }
+
+    fn release(&self, key: u8) {
+        if let Some(ref kb) = self.keyboard {
+            kb.inject_key_release(convert_num_to_key(key));
+        }
+    }
+
     pub fn spawn_listener(self: &Arc<Self>, port: u16) {
         let this = self.clone();
         thread::spawn(move || {
@@ -83,11 +437,14 @@ impl Debugger {
             for stream in listener.incoming() {
                 match stream {
                     Ok(stream) => {
-                        eprintln!("[DEBUGGER] client connected");
-                        if let Err(e) = this.handle_client(stream) {
-                            eprintln!("[DEBUGGER] client error: {e}");
-                        }
-                        eprintln!("[DEBUGGER] client disconnected");
+                        let this = this.clone();
+                        thread::spawn(move || {
+                            eprintln!("[DEBUGGER] client connected");
+                            if let Err(e) = this.handle_client(stream) {
+                                eprintln!("[DEBUGGER] client error: {e}");
+                            }
+                            eprintln!("[DEBUGGER] client disconnected");
+                        });
                     }
                     Err(e) => {
                         eprintln!("[DEBUGGER] accept error: {e}");
@@ -100,11 +457,24 @@ impl Debugger {
         });
     }
 
-    fn handle_client(&self, stream: TcpStream) -> std::io::Result<()> {
+    /// Serves one connection. Replies and events share one writer thread so
+    /// lines never interleave; a request's `id`, if any, is echoed in its
+    /// reply. Frame runs get their own thread so the connection stays usable
+    /// while they run, which makes their replies arrive out of order.
+    fn handle_client(self: &Arc<Self>, stream: TcpStream) -> std::io::Result<()> {
+        let client = self.next_client.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel::<String>();
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            for line in rx {
+                if writeln!(writer, "{line}").and_then(|_| writer.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
         let mut reader = BufReader::new(&stream);
-        let mut writer = &stream;
         let mut line = String::new();
-
         loop {
             line.clear();
             let n = reader.read_line(&mut line)?;
@@ -116,22 +486,43 @@ impl Debugger {
                 Ok(v) => v,
                 Err(e) => {
                     let err = serde_json::json!({"error": format!("invalid JSON: {e}")});
-                    writeln!(writer, "{err}")?;
-                    writer.flush()?;
+                    let _ = tx.send(err.to_string());
                     continue;
                 }
             };
 
-            let cmd = req.get("cmd").and_then(|c| c.as_str()).unwrap_or("");
-            let resp = self.handle_command(cmd, &req);
-            let json = serde_json::to_string(&resp)?;
-            writeln!(writer, "{json}")?;
-            writer.flush()?;
+            let cmd = req.get("cmd").and_then(|c| c.as_str()).unwrap_or("").to_string();
+            match cmd.as_str() {
+                "subscribe" => {
+                    let events = req.get("events").and_then(|v| v.as_array()).map(|list| {
+                        list.iter().filter_map(|e| e.as_str()).map(str::to_string).collect::<HashSet<_>>()
+                    });
+                    let resp = match events.iter().flatten().find(|e| !EVENTS.contains(&e.as_str())) {
+                        Some(bad) => serde_json::json!({"error": format!("unknown event: {bad}")}),
+                        None => {
+                            let sub = Subscriber { tx: tx.clone(), events };
+                            self.subscribers.lock().unwrap().insert(client, sub);
+                            serde_json::json!({"ok": true})
+                        }
+                    };
+                    reply(&tx, &req, resp);
+                }
+                "unsubscribe" => {
+                    self.subscribers.lock().unwrap().remove(&client);
+                    reply(&tx, &req, serde_json::json!({"ok": true}));
+                }
+                "run_frames" | "press_for_frames" | "record_gif" => {
+                    let (this, tx) = (self.clone(), tx.clone());
+                    thread::spawn(move || reply(&tx, &req, this.handle_command(&cmd, &req)));
+                }
+                _ => reply(&tx, &req, self.handle_command(&cmd, &req)),
+            }
 
             if !self.running.load(Ordering::Relaxed) {
                 break;
             }
         }
+        self.subscribers.lock().unwrap().remove(&client);
         Ok(())
     }
 
@@ -146,14 +537,129 @@ impl Debugger {
                 let end = req
                     .get("e")
                     .and_then(|v| v.as_u64())
-                    .unwrap_or(4096) as usize;
+                    .unwrap_or(4096)
+                    .min(4096) as usize;
+                if start > 4096 {
+                    return serde_json::json!({"error": "address out of range"});
+                }
+                if start > end {
+                    return serde_json::json!({"error": "s must not be past e"});
+                }
                 let state = self.state.lock().unwrap();
-                let data: Vec<u8> = if end > state.memory.len() {
-                    state.memory[start..].to_vec()
-                } else {
-                    state.memory[start..end].to_vec()
+                serde_json::json!({"data": state.memory[start..end]})
+            }
+            "get_sprites" => {
+                let addr = req.get("a").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                let height = req.get("h").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
+                let count = req.get("n").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
+                if height > 15 {
+                    return serde_json::json!({"error": "h must be 1-15, or 0 for 16x16"});
+                }
+                if !(1..=64).contains(&count) {
+                    return serde_json::json!({"error": "n must be 1-64"});
+                }
+                if addr >= 4096 {
+                    return serde_json::json!({"error": "address out of range"});
+                }
+                let (width, rows, bytes) = sprites::size(height);
+                let end = bytes.checked_mul(count).and_then(|len| addr.checked_add(len));
+                if end.is_none_or(|end| end > 4096) {
+                    return serde_json::json!({"error": "sprites run past the end of memory"});
+                }
+                let state = self.state.lock().unwrap();
+                let list: Vec<serde_json::Value> = (0..count)
+                    .map(|n| {
+                        let at = addr + n * bytes;
+                        serde_json::json!({
+                            "addr": at,
+                            "width": width,
+                            "height": rows,
+                            "pixels": sprites::decode(&state.memory, at, height),
+                        })
+                    })
+                    .collect();
+                serde_json::json!({"sprites": list})
+            }
+            "record_draws" => {
+                let enabled = req.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true);
+                self.recording_draws.store(enabled, Ordering::Relaxed);
+                if !enabled {
+                    *self.draws.lock().unwrap() = DrawLog::default();
+                }
+                serde_json::json!({"ok": true})
+            }
+            "get_draws" => {
+                if !self.recording_draws.load(Ordering::Relaxed) {
+                    return serde_json::json!({"error": "not recording draws, send record_draws first"});
+                }
+                let log = self.draws.lock().unwrap();
+                match log.frame {
+                    Some(frame) => serde_json::json!({"frame": frame, "draws": log.last}),
+                    None => serde_json::json!({"error": "no frame has completed since recording started"}),
+                }
+            }
+            "get_frame_history" => {
+                let n = req.get("n").and_then(|v| v.as_u64()).map_or(usize::MAX, |n| n as usize);
+                let with_pixels = req.get("pixels").and_then(|v| v.as_bool()).unwrap_or(false);
+                let history = self.history.lock().unwrap();
+                let frames: Vec<serde_json::Value> = history
+                    .last(n)
+                    .map(|record| {
+                        let mut entry = serde_json::to_value(record).unwrap_or_default();
+                        if with_pixels {
+                            entry["pixels"] = serde_json::json!(record.pixels);
+                        }
+                        entry
+                    })
+                    .collect();
+                serde_json::json!({"frames": frames})
+            }
+            "diff_frames" => {
+                let history = self.history.lock().unwrap();
+                let Some(latest) = history.latest() else {
+                    return serde_json::json!({"error": "frame history is off"});
+                };
+                let b = req.get("b").and_then(|v| v.as_u64()).unwrap_or(latest.frame);
+                let a = req.get("a").and_then(|v| v.as_u64()).unwrap_or(b.saturating_sub(1));
+                let record = |frame| {
+                    history
+                        .get(frame)
+                        .ok_or_else(|| format!("frame {frame} is not in the history (frames {})", history.range()))
                 };
-                serde_json::json!({"data": data})
+                let (before, after) = match (record(a), record(b)) {
+                    (Ok(before), Ok(after)) => (before, after),
+                    (Err(e), _) | (_, Err(e)) => return serde_json::json!({"error": e}),
+                };
+                let diff = history::diff(&before.pixels, &after.pixels);
+                let mut resp = serde_json::json!({
+                    "a": a,
+                    "b": b,
+                    "cycles": after.cycles.abs_diff(before.cycles),
+                    "changed": diff.changes.len(),
+                    "bbox": diff.bbox,
+                    "regions": diff.regions,
+                    "changes": diff.changes,
+                });
+                if req.get("pixels").and_then(|v| v.as_bool()).unwrap_or(false) {
+                    resp["before"] = serde_json::json!(before.pixels);
+                    resp["after"] = serde_json::json!(after.pixels);
+                }
+                resp
+            }
+            "set_memory" => {
+                let start = req.get("a").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                let data: Vec<u8> = req
+                    .get("data")
+                    .and_then(|v| serde_json::from_value(v.clone()).ok())
+                    .unwrap_or_default();
+                if start >= 4096 {
+                    return serde_json::json!({"error": "address out of range"});
+                }
+                if start.checked_add(data.len()).is_none_or(|end| end > 4096) {
+                    return serde_json::json!({"error": "write past the end of memory"});
+                }
+                self.patch(Patch::Memory(start as u16, data));
+                serde_json::json!({"ok": true})
             }
             "set_bp" => {
                 let addr = req.get("a").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
@@ -171,7 +677,9 @@ impl Debugger {
                 serde_json::json!({"ok": true})
             }
             "pause" => {
-                self.paused.store(true, Ordering::Relaxed);
+                if !self.paused.load(Ordering::Relaxed) {
+                    self.pause("pause");
+                }
                 serde_json::json!({"ok": true})
             }
             "continue" => {
@@ -182,6 +690,126 @@ impl Debugger {
                 self.running.store(false, Ordering::Relaxed);
                 serde_json::json!({"ok": true})
             }
+            "run_frames" => {
+                let frames = req.get("n").and_then(|v| v.as_u64()).unwrap_or(1);
+                if frames == 0 {
+                    return serde_json::json!({"error": "n must be at least 1"});
+                }
+                self.run_frames(frames, None, 0, false).0
+            }
+            "press_for_frames" => {
+                let key_val = req.get("key").and_then(|v| v.as_u64()).unwrap_or(0);
+                if key_val > 0x0F {
+                    return serde_json::json!({"error": "key must be 0x0-0xF"});
+                }
+                if self.keyboard.is_none() {
+                    return serde_json::json!({"error": "keyboard not connected"});
+                }
+                let hold = req.get("hold").and_then(|v| v.as_u64()).unwrap_or(1);
+                let frames = req.get("n").and_then(|v| v.as_u64()).unwrap_or(hold);
+                if hold == 0 || frames < hold {
+                    return serde_json::json!({"error": "hold must be at least 1 and n at least hold"});
+                }
+                self.run_frames(frames, Some(key_val as u8), hold, false).0
+            }
+            "screenshot" => {
+                let (scale, palette) = match capture_options(req) {
+                    Ok(options) => options,
+                    Err(e) => return serde_json::json!({"error": e}),
+                };
+                let pixels = self.state.lock().unwrap().pixels.clone();
+                match capture::png(&pixels, scale, palette) {
+                    Ok(png) => serde_json::json!({
+                        "png": BASE64_STANDARD.encode(png),
+                        "width": pixels[0].len() * scale,
+                        "height": pixels.len() * scale,
+                    }),
+                    Err(e) => serde_json::json!({"error": e}),
+                }
+            }
+            "record_gif" => {
+                let frames = req.get("n").and_then(|v| v.as_u64()).unwrap_or(60);
+                if !(1..=MAX_GIF_FRAMES).contains(&frames) {
+                    return serde_json::json!({"error": format!("n must be 1-{MAX_GIF_FRAMES}")});
+                }
+                let (scale, palette) = match capture_options(req) {
+                    Ok(options) => options,
+                    Err(e) => return serde_json::json!({"error": e}),
+                };
+                let (mut resp, screens) = self.run_frames(frames, None, 0, true);
+                if resp.get("error").is_some() {
+                    return resp;
+                }
+                match capture::gif(&screens, scale, palette) {
+                    Ok(gif) => resp["gif"] = BASE64_STANDARD.encode(gif).into(),
+                    Err(e) => return serde_json::json!({"error": e}),
+                }
+                resp
+            }
+            "search_start" => {
+                let start = req.get("s").and_then(|v| v.as_u64()).unwrap_or(PROGRAM_START as u64);
+                let end = req.get("e").and_then(|v| v.as_u64()).unwrap_or(4096);
+                if start >= end || end > 4096 {
+                    return serde_json::json!({"error": "invalid range, expected s < e <= 0x1000"});
+                }
+                let memory = self.state.lock().unwrap().memory.clone();
+                *self.search.lock().unwrap() = Some(Search::new(&memory, start as u16, end as u16));
+                serde_json::json!({"ok": true, "count": end - start})
+            }
+            "search_filter" | "search_results" => {
+                let mut search = self.search.lock().unwrap();
+                let Some(search) = search.as_mut() else {
+                    return serde_json::json!({"error": "no search in progress, send search_start first"});
+                };
+                let memory = self.state.lock().unwrap().memory.clone();
+                let candidates = if cmd == "search_filter" {
+                    let name = req.get("op").and_then(|v| v.as_str()).unwrap_or("");
+                    let value = req.get("value").and_then(|v| v.as_u64());
+                    let Ok(value) = value.map(u8::try_from).transpose() else {
+                        return serde_json::json!({"error": "value must be 0x00-0xFF"});
+                    };
+                    match Filter::parse(name, value) {
+                        Ok(filter) => search.filter(&memory, filter),
+                        Err(e) => return serde_json::json!({"error": e}),
+                    }
+                } else {
+                    search.candidates(&memory)
+                };
+                let limit = req.get("limit").and_then(|v| v.as_u64()).unwrap_or(100) as usize;
+                serde_json::json!({
+                    "count": candidates.len(),
+                    "candidates": &candidates[..candidates.len().min(limit)],
+                })
+            }
+            "add_cheat" => {
+                let addr = req.get("a").and_then(|v| v.as_u64()).unwrap_or(0);
+                if addr >= 4096 {
+                    return serde_json::json!({"error": "address outside memory"});
+                }
+                // Without a value the byte is frozen at what it holds now
+                let value = match req.get("value").and_then(|v| v.as_u64()) {
+                    Some(value) => match u8::try_from(value) {
+                        Ok(value) => value,
+                        Err(_) => return serde_json::json!({"error": "value must be 0x00-0xFF"}),
+                    },
+                    None => self.state.lock().unwrap().memory[addr as usize],
+                };
+                let name = req.get("name").and_then(|v| v.as_str()).map(str::to_string);
+                let cheat = Cheat { addr: addr as u16, value, name };
+                let resp = serde_json::json!({"ok": true, "cheat": &cheat});
+                self.add_cheat(cheat);
+                resp
+            }
+            "remove_cheat" => {
+                let addr = req.get("a").and_then(|v| v.as_u64()).unwrap_or(0);
+                let mut cheats = self.cheats.lock().unwrap();
+                let before = cheats.len();
+                cheats.retain(|c| c.addr as u64 != addr);
+                serde_json::json!({"ok": true, "removed": cheats.len() < before})
+            }
+            "list_cheats" => {
+                serde_json::json!({"cheats": *self.cheats.lock().unwrap()})
+            }
             "key_press" => {
                 let key_val = req.get("key").and_then(|v| v.as_u64()).unwrap_or(0) as u8;
                 if key_val > 0x0F {
@@ -228,3 +856,100 @@ impl Debugger {
         }
     }
 }
+
+fn blank_screen() -> Vec<Vec<bool>> {
+    vec![vec![false; WIDTH]; HEIGHT]
+}
+
+/// `scale` (default 10, like the window) and `palette` of a capture command.
+fn capture_options(req: &serde_json::Value) -> Result<(usize, Palette), String> {
+    let scale = req.get("scale").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+    if !(1..=MAX_SCALE).contains(&scale) {
+        return Err(format!("scale must be 1-{MAX_SCALE}"));
+    }
+    let palette = match req.get("palette").and_then(|v| v.as_str()) {
+        Some(text) => Palette::parse(text)?,
+        None => Palette::default(),
+    };
+    Ok((scale, palette))
+}
+
+/// Queues `resp` for the client, tagged with the request's `id` if it had one.
+fn reply(tx: &Sender<String>, req: &serde_json::Value, mut resp: serde_json::Value) {
+    if let (Some(id), Some(obj)) = (req.get("id"), resp.as_object_mut()) {
+        obj.insert("id".to_string(), id.clone());
+    }
+    let _ = tx.send(resp.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> (Debugger, Arc<DataKeys>) {
+        let keyboard = Arc::new(DataKeys::new(Arc::new(Mutex::new(false))));
+        (Debugger::new(Some(keyboard.clone())), keyboard)
+    }
+
+    /// Plays the emulator's side of a run queued by `run_frames` on another
+    /// thread: waits for it, then starts it.
+    fn start(debugger: &Debugger) {
+        while debugger.frame_run.lock().unwrap().is_none() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(debugger.start_frame_run());
+        assert!(!debugger.paused.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_frame_run_counts_frames_and_releases_key() {
+        let (debugger, keyboard) = debugger();
+        let key = convert_num_to_key(5);
+        let resp = thread::scope(|s| {
+            let run = s.spawn(|| debugger.run_frames(5, Some(5), 2, false).0);
+            start(&debugger);
+            // Held through frames 1 and 2, paused as soon as frame 5 ends
+            for frame in 1..=5 {
+                debugger.end_frame(false, frame * 100);
+                assert_eq!(keyboard.key_pressed(key), frame < 2, "after frame {frame}");
+                assert_eq!(debugger.paused.load(Ordering::Relaxed), frame == 5, "after frame {frame}");
+            }
+            run.join().unwrap()
+        });
+        assert_eq!(resp["frames"], 5);
+        assert_eq!(resp["frame"], 5);
+        assert!(resp.get("stopped").is_none());
+        assert!(!debugger.frame_run_active());
+    }
+
+    #[test]
+    fn test_interrupted_frame_run_releases_key() {
+        let (debugger, keyboard) = debugger();
+        let key = convert_num_to_key(3);
+        let resp = thread::scope(|s| {
+            let run = s.spawn(|| debugger.run_frames(10, Some(3), 10, false).0);
+            start(&debugger);
+            debugger.end_frame(false, 100);
+            assert!(keyboard.key_pressed(key));
+            debugger.interrupt_frame_run("breakpoint");
+            assert!(!keyboard.key_pressed(key));
+            run.join().unwrap()
+        });
+        assert_eq!(resp["frames"], 1);
+        assert_eq!(resp["stopped"], "breakpoint");
+    }
+
+    #[test]
+    fn test_get_memory_range() {
+        let (debugger, _) = debugger();
+        let get = |s: u64, e: u64| debugger.handle_command("get_memory", &serde_json::json!({"s": s, "e": e}));
+        assert_eq!(get(0x200, 0x204)["data"].as_array().unwrap().len(), 4);
+        assert_eq!(get(4090, 5000)["data"].as_array().unwrap().len(), 6);
+        assert_eq!(get(4096, 4096)["data"], serde_json::json!([]));
+        assert_eq!(get(0x300, 0x200)["error"], "s must not be past e");
+        assert_eq!(get(5000, 6000)["error"], "address out of range");
+        // Bad ranges are refused before the state lock is taken, so it stays usable
+        assert!(!debugger.state.is_poisoned());
+        assert_eq!(get(0, 4096)["data"].as_array().unwrap().len(), 4096);
+    }
+}
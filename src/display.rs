@@ -17,7 +17,9 @@ impl Display {
         self.buf.iter_mut().flatten().for_each(|val| *val = false)
     }
 
-    pub fn add_sprite(&mut self, sprite: Sprite) -> bool {
+    /// XORs a sprite onto the screen and reports whether any pixel was
+    /// turned off. Rows past the right edge wrap around unless `clip`.
+    pub fn add_sprite(&mut self, sprite: Sprite, clip: bool) -> bool {
         let mut collision = false;
         let x = sprite.get_x() as usize;
         let mut y = sprite.get_y() as usize;
@@ -29,6 +31,9 @@ impl Display {
             }
             while bit_mask != 0 {
                 if row_x >= WIDTH {
+                    if clip {
+                        break;
+                    }
                     row_x = 0
                 }
                 let bit = (bit_mask & *byte) != 0;
@@ -92,9 +97,9 @@ impl Sprite {
             .enumerate()
             .for_each(|(i, byte)| bytes[i] = *byte);
         Sprite {
-            bytes: bytes,
-            x: x,
-            y: y,
+            bytes,
+            x,
+            y,
         }
     }
     pub fn as_slice(&self) -> &[u8] {
@@ -0,0 +1,545 @@
+use std::collections::HashSet;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::debugger::{Access, Debugger, Patch, Watchpoint};
+
+/// Register file as gdb sees it, in `g` packet order: V0-VF, I, PC, DT, ST.
+/// Values are big-endian like the rest of CHIP-8.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 20;
+const MEMORY_SIZE: usize = 4096;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/// What the connection reader hands to the session.
+enum Input {
+    Packet(String),
+    BadChecksum,
+    /// Ctrl-C, sent outside a packet while the target runs.
+    Interrupt,
+}
+
+/// Serves the GDB remote serial protocol on `port`, one client at a time,
+/// on top of the same `Debugger` the JSON protocol drives.
+pub fn spawn_listener(debugger: Arc<Debugger>, port: u16) {
+    thread::spawn(move || {
+        let addr = format!("127.0.0.1:{port}");
+        let listener = match TcpListener::bind(&addr) {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("[GDB] failed to bind {addr}: {e}");
+                return;
+            }
+        };
+        eprintln!("[GDB] listening on {addr}");
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    eprintln!("[GDB] client connected");
+                    if let Err(e) = Session::new(&debugger, stream).and_then(|mut s| s.run()) {
+                        eprintln!("[GDB] client error: {e}");
+                    }
+                    eprintln!("[GDB] client disconnected");
+                }
+                Err(e) => {
+                    eprintln!("[GDB] accept error: {e}");
+                }
+            }
+            if !debugger.running.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+    });
+}
+
+struct Session<'a> {
+    debugger: &'a Debugger,
+    stream: TcpStream,
+    input: Receiver<Input>,
+    subscriber: u64,
+    events: Receiver<String>,
+    no_ack: bool,
+    /// Reply to `?`: why the target last stopped.
+    last_stop: String,
+    /// Breakpoints and watchpoints this client set, removed when it leaves.
+    breakpoints: HashSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<'a> Session<'a> {
+    fn new(debugger: &'a Debugger, stream: TcpStream) -> io::Result<Self> {
+        let (tx, input) = mpsc::channel();
+        let reader = stream.try_clone()?;
+        thread::spawn(move || read_packets(reader, tx));
+        let (subscriber, events) = debugger.subscribe(&["watchpoint_hit", "paused"]);
+        Ok(Session {
+            debugger,
+            stream,
+            input,
+            subscriber,
+            events,
+            no_ack: false,
+            last_stop: format!("S{SIGTRAP:02x}"),
+            breakpoints: HashSet::new(),
+            watchpoints: Vec::new(),
+        })
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        // gdb expects to find the target stopped
+        if !self.debugger.paused.load(Ordering::Relaxed) {
+            self.debugger.pause("interrupt");
+        }
+        let result = self.serve();
+        self.detach();
+        result
+    }
+
+    fn serve(&mut self) -> io::Result<()> {
+        while let Ok(input) = self.input.recv() {
+            match input {
+                Input::Packet(packet) => {
+                    if !self.no_ack {
+                        self.stream.write_all(b"+")?;
+                    }
+                    if !self.handle(&packet)? {
+                        break;
+                    }
+                }
+                Input::BadChecksum => self.stream.write_all(b"-")?,
+                // Already stopped
+                Input::Interrupt => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers one packet; false ends the session.
+    fn handle(&mut self, packet: &str) -> io::Result<bool> {
+        let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match cmd {
+            "?" => self.last_stop.clone(),
+            "g" => hex(&self.registers()),
+            "G" => match unhex(args) {
+                Some(bytes) if bytes.len() == REGISTER_COUNT + 2 => {
+                    self.write_registers(&bytes);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16).ok().and_then(register_range) {
+                Some(range) => hex(&self.registers()[range]),
+                None => "E01".to_string(),
+            },
+            "P" => self.write_register(args).unwrap_or_else(|| "E01".to_string()),
+            "m" => self.read_memory(args).unwrap_or_else(|| "E01".to_string()),
+            "M" => self.write_memory(args).unwrap_or_else(|| "E01".to_string()),
+            "c" | "s" => {
+                if let Some(addr) = parse_hex(args) {
+                    self.debugger.patch(Patch::Pc(addr as u16));
+                }
+                return self.resume(cmd == "s").map(|_| true);
+            }
+            "Z" | "z" => self.set_point(cmd == "Z", args).unwrap_or_else(|| "E01".to_string()),
+            "H" | "T" => "OK".to_string(),
+            "q" | "Q" => self.query(packet),
+            "D" => {
+                self.send("OK")?;
+                return Ok(false);
+            }
+            "k" => {
+                self.debugger.running.store(false, Ordering::Relaxed);
+                return Ok(false);
+            }
+            _ => String::new(),
+        };
+        self.send(&reply)?;
+        Ok(true)
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return xfer(TARGET_XML, range).unwrap_or_else(|| "E01".to_string());
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                // The reply itself is still acknowledged
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qHostInfo" | "qProcessInfo" => "endian:big;ptrsize:2;".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Lets the target run (or take one step) and reports where it stops.
+    fn resume(&mut self, step: bool) -> io::Result<()> {
+        // Stops from before this resume are stale
+        while self.events.try_recv().is_ok() {}
+        if step {
+            self.debugger.step_requested.store(true, Ordering::Relaxed);
+            self.debugger.paused.store(true, Ordering::Relaxed);
+        } else {
+            self.debugger.paused.store(false, Ordering::Relaxed);
+        }
+
+        let mut watch = None;
+        loop {
+            match self.input.try_recv() {
+                Ok(Input::Interrupt) => {
+                    if !self.debugger.paused.load(Ordering::Relaxed) {
+                        self.debugger.pause("interrupt");
+                    }
+                }
+                // Nothing else is valid while the target runs
+                Ok(_) | Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+            let line = match self.events.recv_timeout(Duration::from_millis(20)) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    if !self.debugger.running.load(Ordering::Relaxed) {
+                        return self.send("W00");
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(&line) else { continue };
+            match event["event"].as_str() {
+                Some("watchpoint_hit") => {
+                    let access = match event["access"].as_str() {
+                        Some("read") => "rwatch",
+                        Some("write") => "watch",
+                        _ => "awatch",
+                    };
+                    watch = Some(format!("{access}:{:x};", event["addr"].as_u64().unwrap_or(0)));
+                }
+                Some("paused") => {
+                    let signal = match event["reason"].as_str() {
+                        Some("interrupt") => SIGINT,
+                        Some("fault") => SIGILL,
+                        _ => SIGTRAP,
+                    };
+                    self.last_stop = format!("T{signal:02x}{}", watch.take().unwrap_or_default());
+                    let stop = self.last_stop.clone();
+                    return self.send(&stop);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let state = self.debugger.state.lock().unwrap();
+        let mut regs = state.v_regs.to_vec();
+        regs.extend(state.i.to_be_bytes());
+        regs.extend(state.pc.to_be_bytes());
+        regs.extend([state.delay, state.sound]);
+        regs
+    }
+
+    fn write_registers(&self, bytes: &[u8]) {
+        for n in 0..REGISTER_COUNT {
+            if let Some(range) = register_range(n) {
+                self.patch_register(n, &bytes[range]);
+            }
+        }
+    }
+
+    /// `P n=value`
+    fn write_register(&self, args: &str) -> Option<String> {
+        let (n, value) = args.split_once('=')?;
+        let n = usize::from_str_radix(n, 16).ok()?;
+        let value = unhex(value)?;
+        if value.len() != register_range(n)?.len() {
+            return None;
+        }
+        self.patch_register(n, &value);
+        Some("OK".to_string())
+    }
+
+    fn patch_register(&self, n: usize, value: &[u8]) {
+        let word = || u16::from_be_bytes([value[0], value[1]]);
+        let patch = match n {
+            0..=15 => Patch::V(n, value[0]),
+            16 => Patch::I(word()),
+            17 => Patch::Pc(word()),
+            18 => Patch::Delay(value[0]),
+            _ => Patch::Sound(value[0]),
+        };
+        self.debugger.patch(patch);
+    }
+
+    /// `m addr,length`
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = args.split_once(',')?;
+        let (addr, len) = (parse_hex(addr)?, parse_hex(len)?);
+        let end = addr.checked_add(len)?.min(MEMORY_SIZE);
+        if addr >= MEMORY_SIZE {
+            return None;
+        }
+        Some(hex(&self.debugger.state.lock().unwrap().memory[addr..end]))
+    }
+
+    /// `M addr,length:XX...`
+    fn write_memory(&self, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = range.split_once(',')?;
+        let (addr, data) = (parse_hex(addr)?, unhex(data)?);
+        if data.len() != parse_hex(len)? || addr.checked_add(data.len())? > MEMORY_SIZE {
+            return None;
+        }
+        self.debugger.patch(Patch::Memory(addr as u16, data));
+        Some("OK".to_string())
+    }
+
+    /// `Ztype,addr,kind` / `ztype,addr,kind`: breakpoints (type 0) and
+    /// write, read and access watchpoints (types 2, 3, 4). For a watchpoint
+    /// `kind` is the number of bytes watched.
+    fn set_point(&mut self, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let (addr, len) = (parse_hex(fields.next()?)?, parse_hex(fields.next()?)?);
+        if addr >= MEMORY_SIZE || len >= MEMORY_SIZE {
+            return None;
+        }
+        let (addr, len) = (addr as u16, len as u16);
+        let access = match kind {
+            "0" => {
+                let mut breakpoints = self.debugger.breakpoints.lock().unwrap();
+                if insert {
+                    breakpoints.insert(addr);
+                    self.breakpoints.insert(addr);
+                } else {
+                    breakpoints.remove(&addr);
+                    self.breakpoints.remove(&addr);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => Access::Write,
+            "3" => Access::Read,
+            "4" => Access::Any,
+            // Not supported
+            _ => return Some(String::new()),
+        };
+        let watch = Watchpoint { addr, len, access };
+        let mut watchpoints = self.debugger.watchpoints.lock().unwrap();
+        if insert {
+            watchpoints.push(watch);
+            self.watchpoints.push(watch);
+        } else {
+            if let Some(i) = watchpoints.iter().position(|w| *w == watch) {
+                watchpoints.remove(i);
+            }
+            self.watchpoints.retain(|w| *w != watch);
+        }
+        Some("OK".to_string())
+    }
+
+    /// Removes what this client set and lets the target run on.
+    fn detach(&mut self) {
+        self.debugger.unsubscribe(self.subscriber);
+        let mut breakpoints = self.debugger.breakpoints.lock().unwrap();
+        for addr in self.breakpoints.drain() {
+            breakpoints.remove(&addr);
+        }
+        self.debugger
+            .watchpoints
+            .lock()
+            .unwrap()
+            .retain(|w| !self.watchpoints.contains(w));
+        self.watchpoints.clear();
+        self.debugger.paused.store(false, Ordering::Relaxed);
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut escaped = Vec::with_capacity(data.len());
+        for b in data.bytes() {
+            if matches!(b, b'$' | b'#' | b'}' | b'*') {
+                escaped.extend([b'}', b ^ 0x20]);
+            } else {
+                escaped.push(b);
+            }
+        }
+        let sum = escaped.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        self.stream.write_all(b"$")?;
+        self.stream.write_all(&escaped)?;
+        write!(self.stream, "#{sum:02x}")?;
+        self.stream.flush()
+    }
+}
+
+/// Splits the byte stream into packets, checking their checksums. Acks from
+/// the client are dropped.
+fn read_packets(stream: TcpStream, tx: mpsc::Sender<Input>) {
+    let mut bytes = BufReader::new(stream).bytes().map_while(Result::ok);
+    while let Some(b) = bytes.next() {
+        let input = match b {
+            0x03 => Input::Interrupt,
+            b'$' => {
+                let mut data = Vec::new();
+                let mut sum = 0u8;
+                for b in bytes.by_ref() {
+                    if b == b'#' {
+                        break;
+                    }
+                    sum = sum.wrapping_add(b);
+                    data.push(b);
+                }
+                let checksum: Vec<u8> = bytes.by_ref().take(2).collect();
+                let valid = std::str::from_utf8(&checksum)
+                    .ok()
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+                    .is_some_and(|c| c == sum);
+                match String::from_utf8(data) {
+                    Ok(packet) if valid => Input::Packet(packet),
+                    _ => Input::BadChecksum,
+                }
+            }
+            _ => continue,
+        };
+        if tx.send(input).is_err() {
+            break;
+        }
+    }
+}
+
+/// Byte range of register `n` in the `g` packet.
+fn register_range(n: usize) -> Option<std::ops::Range<usize>> {
+    match n {
+        0..=15 => Some(n..n + 1),
+        16 => Some(16..18),
+        17 => Some(18..20),
+        18 | 19 => Some(n + 2..n + 3),
+        _ => None,
+    }
+}
+
+/// `offset,length` of a `qXfer` read: `m` and a chunk, or `l` and the rest.
+fn xfer(document: &str, range: &str) -> Option<String> {
+    let (offset, len) = range.split_once(',')?;
+    let (offset, len) = (parse_hex(offset)?, parse_hex(len)?);
+    let rest = document.get(offset.min(document.len())..)?;
+    Some(if rest.len() > len {
+        format!("m{}", &rest[..len])
+    } else {
+        format!("l{rest}")
+    })
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_roundtrip() {
+        assert_eq!(unhex("00e0ff"), Some(vec![0x00, 0xE0, 0xFF]));
+        assert_eq!(hex(&[0x12, 0xAB]), "12ab");
+        assert_eq!(unhex("abc"), None);
+        assert_eq!(unhex("zz"), None);
+        assert_eq!(unhex(""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_register_range() {
+        assert_eq!(register_range(0), Some(0..1));
+        assert_eq!(register_range(15), Some(15..16));
+        assert_eq!(register_range(16), Some(16..18)); // I
+        assert_eq!(register_range(17), Some(18..20)); // PC
+        assert_eq!(register_range(18), Some(20..21)); // DT
+        assert_eq!(register_range(19), Some(21..22)); // ST
+        assert_eq!(register_range(REGISTER_COUNT), None);
+    }
+
+    #[test]
+    fn test_xfer() {
+        assert_eq!(xfer("abcdef", "0,4").as_deref(), Some("mabcd"));
+        assert_eq!(xfer("abcdef", "4,4").as_deref(), Some("lef"));
+        assert_eq!(xfer("abcdef", "10,4").as_deref(), Some("l"));
+        assert_eq!(xfer("abcdef", "0"), None);
+    }
+
+    #[test]
+    fn test_read_packets_checksums() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        // An ack, a good packet, a corrupted one, then Ctrl-C
+        client.write_all(b"+$g#67$g#00\x03").unwrap();
+        drop(client);
+
+        let (tx, rx) = mpsc::channel();
+        read_packets(server, tx);
+        let inputs: Vec<String> = rx
+            .iter()
+            .map(|input| match input {
+                Input::Packet(packet) => packet,
+                Input::BadChecksum => "bad".to_string(),
+                Input::Interrupt => "^C".to_string(),
+            })
+            .collect();
+        assert_eq!(inputs, ["g", "bad", "^C"]);
+    }
+}
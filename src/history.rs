@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/// Frames kept by default, two seconds at 60 Hz.
+pub const DEFAULT_LEN: usize = 120;
+
+/// The screen at the end of a frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameRecord {
+    pub frame: u64,
+    /// Instructions executed since power-on.
+    pub cycles: u64,
+    /// Whether the frame drew to the display.
+    pub drawn: bool,
+    /// Pixels that differ from the frame before.
+    pub changed: usize,
+    #[serde(skip)]
+    pub pixels: Vec<Vec<bool>>,
+}
+
+/// The last frames, oldest first.
+pub struct History {
+    frames: VecDeque<FrameRecord>,
+    len: usize,
+}
+
+impl History {
+    /// Keeps `len` frames, starting with the blank screen of frame 0.
+    pub fn new(len: usize, pixels: Vec<Vec<bool>>) -> Self {
+        let mut history = History { frames: VecDeque::new(), len };
+        history.push(FrameRecord { frame: 0, cycles: 0, drawn: false, changed: 0, pixels });
+        history
+    }
+
+    pub fn push(&mut self, mut record: FrameRecord) {
+        if self.len == 0 {
+            return;
+        }
+        if let Some(last) = self.frames.back() {
+            record.changed = last
+                .pixels
+                .iter()
+                .flatten()
+                .zip(record.pixels.iter().flatten())
+                .filter(|(was, now)| was != now)
+                .count();
+        }
+        if self.frames.len() == self.len {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(record);
+    }
+
+    pub fn get(&self, frame: u64) -> Option<&FrameRecord> {
+        let first = self.frames.front()?.frame;
+        self.frames.get(frame.checked_sub(first)? as usize).filter(|r| r.frame == frame)
+    }
+
+    pub fn latest(&self) -> Option<&FrameRecord> {
+        self.frames.back()
+    }
+
+    /// The last `n` frames, oldest first.
+    pub fn last(&self, n: usize) -> impl Iterator<Item = &FrameRecord> {
+        self.frames.iter().skip(self.frames.len().saturating_sub(n))
+    }
+
+    /// `first-last` frame numbers held, for error messages.
+    pub fn range(&self) -> String {
+        match (self.frames.front(), self.frames.back()) {
+            (Some(first), Some(last)) => format!("{}-{}", first.frame, last.frame),
+            _ => "none".to_string(),
+        }
+    }
+}
+
+/// A rectangle of the screen, edges included.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    fn around(points: &[(usize, usize)]) -> Option<Self> {
+        let x0 = points.iter().map(|p| p.0).min()?;
+        let y0 = points.iter().map(|p| p.1).min()?;
+        let x1 = points.iter().map(|p| p.0).max()?;
+        let y1 = points.iter().map(|p| p.1).max()?;
+        Some(Rect { x: x0, y: y0, width: x1 - x0 + 1, height: y1 - y0 + 1 })
+    }
+}
+
+/// Changed pixels that touch, diagonals included, like one sprite moving.
+#[derive(Debug, Clone, Serialize)]
+pub struct Region {
+    #[serde(flatten)]
+    pub bounds: Rect,
+    /// Pixels turned on and off.
+    pub on: usize,
+    pub off: usize,
+}
+
+/// How one screen became another.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diff {
+    /// `(x, y, now lit)` of every pixel that changed, row by row.
+    pub changes: Vec<(usize, usize, bool)>,
+    pub bbox: Option<Rect>,
+    pub regions: Vec<Region>,
+}
+
+pub fn diff(before: &[Vec<bool>], after: &[Vec<bool>]) -> Diff {
+    let mut changes = Vec::new();
+    for (y, (old, new)) in before.iter().zip(after).enumerate() {
+        for (x, (&was, &now)) in old.iter().zip(new).enumerate() {
+            if was != now {
+                changes.push((x, y, now));
+            }
+        }
+    }
+    let points: Vec<(usize, usize)> = changes.iter().map(|&(x, y, _)| (x, y)).collect();
+
+    // Flood fill over a grid holding each changed pixel's index in `changes`
+    let width = before.first().map_or(0, Vec::len);
+    let mut grid: Vec<Option<usize>> = vec![None; width * before.len()];
+    for (i, &(x, y)) in points.iter().enumerate() {
+        grid[y * width + x] = Some(i);
+    }
+    let mut regions = Vec::new();
+    for &(x, y) in &points {
+        let Some(first) = grid[y * width + x].take() else {
+            continue; // already in a region
+        };
+        let mut members = vec![first];
+        let mut next = 0;
+        while next < members.len() {
+            let (x, y) = points[members[next]];
+            next += 1;
+            for ny in y.saturating_sub(1)..=y + 1 {
+                for nx in x.saturating_sub(1)..=x + 1 {
+                    if nx < width && ny < before.len() {
+                        members.extend(grid[ny * width + nx].take());
+                    }
+                }
+            }
+        }
+        let cells: Vec<(usize, usize)> = members.iter().map(|&i| points[i]).collect();
+        let on = members.iter().filter(|&&i| changes[i].2).count();
+        regions.push(Region {
+            bounds: Rect::around(&cells).expect("a region has at least one pixel"),
+            on,
+            off: members.len() - on,
+        });
+    }
+
+    Diff { bbox: Rect::around(&points), changes, regions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(lit: &[(usize, usize)]) -> Vec<Vec<bool>> {
+        let mut pixels = vec![vec![false; 64]; 32];
+        for &(x, y) in lit {
+            pixels[y][x] = true;
+        }
+        pixels
+    }
+
+    fn record(frame: u64, pixels: Vec<Vec<bool>>) -> FrameRecord {
+        FrameRecord { frame, cycles: frame * 10, drawn: true, changed: 0, pixels }
+    }
+
+    #[test]
+    fn test_diff_regions_and_bbox() {
+        let before = screen(&[(0, 0), (10, 10)]);
+        // (0, 0) goes off, (1, 1) touches it diagonally; (10, 10) moves far away
+        let after = screen(&[(1, 1), (40, 20)]);
+        let d = diff(&before, &after);
+        assert_eq!(d.changes, [(0, 0, false), (1, 1, true), (10, 10, false), (40, 20, true)]);
+        assert_eq!(d.bbox, Some(Rect { x: 0, y: 0, width: 41, height: 21 }));
+        let regions: Vec<(Rect, usize, usize)> = d.regions.iter().map(|r| (r.bounds, r.on, r.off)).collect();
+        assert_eq!(
+            regions,
+            [
+                (Rect { x: 0, y: 0, width: 2, height: 2 }, 1, 1),
+                (Rect { x: 10, y: 10, width: 1, height: 1 }, 0, 1),
+                (Rect { x: 40, y: 20, width: 1, height: 1 }, 1, 0),
+            ]
+        );
+
+        let same = diff(&before, &before);
+        assert!(same.changes.is_empty() && same.regions.is_empty());
+        assert_eq!(same.bbox, None);
+    }
+
+    #[test]
+    fn test_history_get() {
+        let mut history = History::new(3, screen(&[]));
+        for frame in 1..=4 {
+            history.push(record(frame, screen(&[(frame as usize, 0)])));
+        }
+        // Frames 0 and 1 have rolled off
+        assert_eq!(history.range(), "2-4");
+        assert!(history.get(1).is_none());
+        assert!(history.get(5).is_none());
+        let third = history.get(3).unwrap();
+        assert_eq!((third.frame, third.changed), (3, 2));
+        assert_eq!(history.latest().unwrap().frame, 4);
+        let last: Vec<u64> = history.last(2).map(|r| r.frame).collect();
+        assert_eq!(last, [3, 4]);
+
+        let empty = History::new(0, screen(&[]));
+        assert!(empty.get(0).is_none());
+        assert_eq!(empty.range(), "none");
+    }
+}
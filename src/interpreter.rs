@@ -6,13 +6,14 @@ use std::sync::Arc;
 use std::sync::atomic::Ordering;
 
 use minifb::Key;
-use rand::rngs::ThreadRng;
-use rand::{thread_rng, Rng};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::debugger::Debugger;
+use crate::debugger::{Access, Debugger, Draw, Patch};
 use crate::display::{Display, Sprite};
 use crate::keyboard::DataKeys;
 use crate::memory::Memory;
+use crate::quirks::Quirks;
 use crate::registers::Registers;
 
 #[derive(Clone)]
@@ -94,8 +95,18 @@ pub struct Interpreter {
     mem: Memory,
     disp: Display,
     to_draw: bool,
+    /// Whether the display changed during the current frame.
+    drawn: bool,
+    /// Memory the current instruction read or wrote, for watchpoints.
+    access: Option<(Access, u16, u16)>,
     keyboard: Arc<DataKeys>,
-    r_thread: ThreadRng,
+    rng: StdRng,
+    quirks: Quirks,
+    /// Instructions per frame, and how many of them the current frame has run.
+    speed: usize,
+    cycle: usize,
+    /// Instructions executed since power-on.
+    executed: u64,
     debugger: Option<Arc<Debugger>>,
 }
 impl Interpreter {
@@ -106,18 +117,35 @@ impl Interpreter {
             mem: Default::default(),
             disp: Default::default(),
             to_draw: Default::default(),
-            keyboard: keyboard,
-            r_thread: thread_rng(),
+            drawn: false,
+            access: None,
+            keyboard,
+            rng: StdRng::from_entropy(),
+            quirks: Quirks::default(),
+            speed: 100,
+            cycle: 0,
+            executed: 0,
             debugger,
         }
     }
 
-    pub fn write_rom_on_mem(&mut self, file: File) {
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks
+    }
+
+    /// Sets the instructions per frame; timers tick once per frame.
+    pub fn set_speed(&mut self, speed: usize) {
+        self.speed = speed.max(1)
+    }
+
+    /// Makes `RND` repeat the same sequence on every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed)
+    }
+
+    pub fn write_rom_on_mem(&mut self, mut file: File) {
         let mut data = Vec::new();
-        for byte in file.bytes() {
-            let byte = byte.unwrap();
-            data.push(byte)
-        }
+        file.read_to_end(&mut data).unwrap();
         self.mem.write_slice(0x200, data.as_slice())
     }
 
@@ -133,6 +161,10 @@ impl Interpreter {
         self.to_draw
     }
 
+    pub fn pixels(&self) -> Vec<Vec<bool>> {
+        self.disp.buf()
+    }
+
     fn jump(&mut self, istro: Istruction) {
         self.regs.set_pc(istro.addr)
     }
@@ -189,7 +221,9 @@ impl Interpreter {
         let new_val = self.regs.get_v(x) | self.regs.get_v(y);
 
         self.regs.set_v(x, new_val);
-        self.regs.set_flag(false)
+        if !self.quirks.no_vf_reset {
+            self.regs.set_flag(false)
+        }
     }
 
     fn and_regs(&mut self, istro: Istruction) {
@@ -199,7 +233,9 @@ impl Interpreter {
         let new_val = self.regs.get_v(x) & self.regs.get_v(y);
 
         self.regs.set_v(x, new_val);
-        self.regs.set_flag(false)
+        if !self.quirks.no_vf_reset {
+            self.regs.set_flag(false)
+        }
     }
 
     fn xor_regs(&mut self, istro: Istruction) {
@@ -209,7 +245,9 @@ impl Interpreter {
         let new_val = self.regs.get_v(x) ^ self.regs.get_v(y);
 
         self.regs.set_v(x, new_val);
-        self.regs.set_flag(false)
+        if !self.quirks.no_vf_reset {
+            self.regs.set_flag(false)
+        }
     }
 
     fn add_regs(&mut self, istro: Istruction) {
@@ -242,8 +280,9 @@ impl Interpreter {
 
     fn shift_right_regs(&mut self, istro: Istruction) {
         let x = istro.reg as usize;
-        self.regs
-            .set_v(x, self.regs.get_v(istro.nibbles as usize));
+        if !self.quirks.shift {
+            self.regs.set_v(x, self.regs.get_v(istro.nibbles as usize));
+        }
         let x_value = self.regs.get_v(x);
         self.regs.set_v(x, x_value >> 1);
         self.regs.set_flag((x_value & 0x01) != 0);
@@ -268,7 +307,9 @@ impl Interpreter {
 
     fn shift_left_regs(&mut self, istro: Istruction) {
         let x = istro.reg as usize;
-        self.regs.set_v(x, self.regs.get_v(istro.nibbles as usize));
+        if !self.quirks.shift {
+            self.regs.set_v(x, self.regs.get_v(istro.nibbles as usize));
+        }
         let x_value = self.regs.get_v(x);
         self.regs.set_v(x, x_value << 1);
         self.regs.set_flag((x_value & 0x80) != 0);
@@ -286,11 +327,12 @@ impl Interpreter {
     }
 
     fn jump_rel_to_0(&mut self, istro: Istruction) {
-        self.regs.set_pc(istro.addr + self.regs.get_v(0) as u16)
+        let reg = if self.quirks.jump { istro.reg as usize } else { 0 };
+        self.regs.set_pc(istro.addr + self.regs.get_v(reg) as u16)
     }
 
     fn rand(&mut self, istro: Istruction) {
-        let random_byte = self.r_thread.gen_range(0..256) as u8;
+        let random_byte = self.rng.gen_range(0..256) as u8;
         let bit_mask = istro.byte;
         let x = istro.reg;
         self.regs.set_v(x as usize, random_byte & bit_mask)
@@ -300,10 +342,22 @@ impl Interpreter {
     fn todo_draw(&mut self, istro: Istruction) {
         let mut buff: Vec<u8> = vec![0; istro.func_code as usize];
         self.mem.read_slice(self.regs.get_i(), buff.as_mut_slice());
+        self.access = Some((Access::Read, self.regs.get_i(), buff.len() as u16));
         let x = self.regs.get_v(istro.reg as usize);
         let y = self.regs.get_v(istro.nibbles as usize);
-        let collision = self.disp.add_sprite(Sprite::from_slice(buff.as_slice(), x, y));
+        let collision = self.disp.add_sprite(Sprite::from_slice(buff.as_slice(), x, y), self.quirks.clip);
+        if let Some(debugger) = self.debugger.as_ref().filter(|d| d.recording_draws.load(Ordering::Relaxed)) {
+            debugger.record_draw(Draw {
+                pc: self.regs.get_pc() - 2,
+                i: self.regs.get_i(),
+                x,
+                y,
+                height: istro.func_code,
+                collision,
+            });
+        }
         self.to_draw = true;
+        self.drawn = true;
         self.regs.set_flag(collision)
     }
 
@@ -349,7 +403,8 @@ impl Interpreter {
         buff.push(x_value / 100);
         buff.push(x_value / 10 - buff[0] * 10);
         buff.push(x_value - buff[1] * 10 - buff[0] * 100);
-        self.mem.write_slice(self.regs.get_i(), buff.as_slice())
+        self.mem.write_slice(self.regs.get_i(), buff.as_slice());
+        self.access = Some((Access::Write, self.regs.get_i(), 3));
     }
 
     fn save_regs(&mut self, istro: Istruction) {
@@ -359,6 +414,7 @@ impl Interpreter {
             values.push(self.regs.get_v(r));
         }
         self.mem.write_slice(self.regs.get_i(), values.as_slice());
+        self.access = Some((Access::Write, self.regs.get_i(), values.len() as u16));
         self.regs.set_i(self.regs.get_i() + (x as u16) + 1)
     }
 
@@ -366,8 +422,12 @@ impl Interpreter {
         let x = istro.reg as usize;
         let mut buff: Vec<u8> = vec![0; x + 1];
         self.mem.read_slice(self.regs.get_i(), buff.as_mut_slice());
-        for r in 0..=x {
-            self.regs.set_v(r, buff[r])
+        self.access = Some((Access::Read, self.regs.get_i(), buff.len() as u16));
+        for (r, value) in buff.into_iter().enumerate() {
+            self.regs.set_v(r, value)
+        }
+        if self.quirks.load_store {
+            self.regs.set_i(self.regs.get_i() + (x as u16) + 1)
         }
     }
 
@@ -388,12 +448,27 @@ impl Interpreter {
     }
 
     pub fn next_istr(&mut self) {
+        let mut stepping = false;
         if let Some(ref debugger) = self.debugger {
-            if debugger.paused.load(Ordering::Relaxed)
-                && !debugger.step_requested.swap(false, Ordering::Relaxed)
-            {
-                debugger.update_state(&self.disp, &self.regs, &self.mem);
-                return;
+            for patch in debugger.take_patches() {
+                match patch {
+                    Patch::Memory(addr, data) => self.mem.write_slice(addr, &data),
+                    Patch::V(x, value) => self.regs.set_v(x, value),
+                    Patch::I(value) => self.regs.set_i(value),
+                    Patch::Pc(value) => self.regs.set_pc(value),
+                    Patch::Delay(value) => self.regs.set_delay(value),
+                    Patch::Sound(value) => self.regs.set_sound(value),
+                }
+            }
+            if (self.cycle == 0 || debugger.paused.load(Ordering::Relaxed)) && debugger.start_frame_run() {
+                self.cycle = 0;
+            }
+            if debugger.paused.load(Ordering::Relaxed) {
+                if !debugger.step_requested.swap(false, Ordering::Relaxed) {
+                    debugger.update_state(&self.disp, &self.regs, &self.mem);
+                    return;
+                }
+                stepping = true;
             }
         }
 
@@ -404,12 +479,16 @@ impl Interpreter {
         // Decode and execute
         match istro.opcode {
             0x0 => match istro.func_code {
-                0x0 => self.disp.clear_display(),
-                0xE => self.regs.stack_pop(),
-                _ => {
-                    eprintln!("[PANIC] instruction non-existent\nistro: {istro:?}\nPC: {}", self.regs.get_pc());
-                    exit(1);
-                },
+                0x0 => {
+                    self.disp.clear_display();
+                    self.drawn = true;
+                }
+                0xE => {
+                    if !self.regs.stack_pop() {
+                        return self.fault("stack underflow, the sp can't be < 0".to_string());
+                    }
+                }
+                _ => return self.fault(format!("instruction non-existent\nistro: {istro:?}")),
             },
             0x1 => self.jump(istro),
             0x2 => self.call_subroutine(istro),
@@ -428,10 +507,7 @@ impl Interpreter {
                 0x6 => self.shift_right_regs(istro),
                 0x7 => self.subn_regs(istro),
                 0xE => self.shift_left_regs(istro),
-                _ => {
-                    eprintln!("[PANIC] instruction non-existent\nistro: {istro:?}\nPC: {}", self.regs.get_pc());
-                    exit(1);
-                },
+                _ => return self.fault(format!("instruction non-existent\nistro: {istro:?}")),
             },
             0x9 => self.skip_if_not_equal_regs(istro),
             0xA => self.load_addr(istro),
@@ -441,10 +517,7 @@ impl Interpreter {
             0xE => match istro.func_code {
                 0x1 => self.skip_not_pressed(istro),
                 0xE => self.skip_pressed(istro),
-                _ => {
-                    eprintln!("[PANIC] instruction non-existent\nistro: {istro:?}\nPC: {}", self.regs.get_pc());
-                    exit(1);
-                },
+                _ => return self.fault(format!("instruction non-existent\nistro: {istro:?}")),
             },
             0xF => match istro.byte {
                 0x07 => self.read_dalay(istro),
@@ -456,24 +529,169 @@ impl Interpreter {
                 0x33 => self.convert_binary_to_dec(istro),
                 0x55 => self.save_regs(istro),
                 0x65 => self.load_regs(istro),
-                _ => {
-                    eprintln!("[PANIC] instruction non-existent\nistro: {istro:?}\nPC: {}", self.regs.get_pc());
-                    exit(1);
-                },
-            },
-            _ => {
-                eprintln!("[PANIC] instruction non-existent\nistro: {istro:?}\nPC: {}", self.regs.get_pc());
-                exit(1);
+                _ => return self.fault(format!("instruction non-existent\nistro: {istro:?}")),
             },
+            _ => return self.fault(format!("instruction non-existent\nistro: {istro:?}")),
         };
 
+        self.cycle += 1;
+        self.executed += 1;
+        let frame_ended = self.cycle == self.speed;
+        let drawn = self.drawn;
+        if frame_ended {
+            self.cycle = 0;
+            self.drawn = false;
+            self.regs.tick_timers();
+        }
+
         if let Some(ref debugger) = self.debugger {
             debugger.update_state(&self.disp, &self.regs, &self.mem);
-            if debugger.breakpoints.lock().unwrap().contains(&self.regs.get_pc())
-                || debugger.step_requested.swap(false, Ordering::Relaxed)
-            {
-                debugger.paused.store(true, Ordering::Relaxed);
+            if frame_ended {
+                debugger.end_frame(drawn, self.executed);
+            }
+            let pc = self.regs.get_pc();
+            let watched = self
+                .access
+                .take()
+                .and_then(|(access, addr, len)| debugger.watchpoint_hit(access, addr, len));
+            if let Some(watch) = watched {
+                debugger.emit("watchpoint_hit", serde_json::json!({
+                    "pc": pc,
+                    "addr": watch.addr,
+                    "access": watch.access.name(),
+                }));
+                debugger.pause("watchpoint");
+                debugger.interrupt_frame_run("watchpoint");
+            } else if debugger.breakpoints.lock().unwrap().contains(&pc) {
+                debugger.emit("breakpoint_hit", serde_json::json!({"pc": pc}));
+                debugger.pause("breakpoint");
+                debugger.interrupt_frame_run("breakpoint");
+            } else if stepping || debugger.step_requested.swap(false, Ordering::Relaxed) {
+                debugger.pause("step");
             }
         }
     }
+
+    /// Handles an instruction that can't run. Under the debugger the
+    /// emulator pauses on it and reports a `fault` event; otherwise it exits.
+    fn fault(&mut self, message: String) {
+        let Some(ref debugger) = self.debugger else {
+            eprintln!("[PANIC] {message}\nPC: {}", self.regs.get_pc());
+            exit(1);
+        };
+        let pc = self.regs.get_pc() - 2;
+        self.regs.set_pc(pc);
+        // Say so even when no client is attached, or a windowed run just freezes
+        eprintln!("[DEBUGGER] fault at 0x{pc:03X}: {message}; paused");
+        debugger.update_state(&self.disp, &self.regs, &self.mem);
+        debugger.emit("fault", serde_json::json!({"pc": pc, "message": message}));
+        debugger.pause("fault");
+        debugger.interrupt_frame_run("fault");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Runs `program` from 0x200, one instruction per opcode.
+    fn run(quirks: Quirks, program: &[u16]) -> Interpreter {
+        let keyboard = Arc::new(DataKeys::new(Arc::new(Mutex::new(false))));
+        let mut interpreter = Interpreter::new(keyboard, None);
+        interpreter.set_quirks(quirks);
+        let bytes: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        interpreter.mem.write_slice(0x200, &bytes);
+        interpreter.mem.write_slice(0x300, &[0xFF, 1, 2, 3]);
+        for _ in program {
+            interpreter.next_istr();
+        }
+        interpreter
+    }
+
+    fn on() -> Quirks {
+        Quirks { shift: true, load_store: true, jump: true, clip: true, no_vf_reset: true }
+    }
+
+    #[test]
+    fn test_shift_quirk() {
+        // V0 = 0x04, V1 = 0x81
+        let right = [0x6004, 0x6181, 0x8016];
+        let vip = run(Quirks::default(), &right);
+        assert_eq!((vip.regs.get_v(0), vip.regs.get_v(0xF)), (0x40, 1));
+        let quirk = run(on(), &right);
+        assert_eq!((quirk.regs.get_v(0), quirk.regs.get_v(0xF)), (0x02, 0));
+
+        let left = [0x6004, 0x6181, 0x801E];
+        let vip = run(Quirks::default(), &left);
+        assert_eq!((vip.regs.get_v(0), vip.regs.get_v(0xF)), (0x02, 1));
+        let quirk = run(on(), &left);
+        assert_eq!((quirk.regs.get_v(0), quirk.regs.get_v(0xF)), (0x08, 0));
+    }
+
+    #[test]
+    fn test_load_store_quirk() {
+        // FX55 always advances I
+        let store = [0xA310, 0x6007, 0x6108, 0xF155];
+        for quirks in [Quirks::default(), on()] {
+            let interpreter = run(quirks, &store);
+            assert_eq!(interpreter.regs.get_i(), 0x312);
+            assert_eq!(&interpreter.mem.as_slice()[0x310..0x312], [7, 8]);
+        }
+
+        // FX65 leaves I alone unless the quirk is on
+        let load = [0xA300, 0xF265];
+        let default = run(Quirks::default(), &load);
+        assert_eq!(default.regs.get_i(), 0x300);
+        assert_eq!(default.regs.all_v()[..3], [0xFF, 1, 2]);
+        let quirk = run(on(), &load);
+        assert_eq!(quirk.regs.get_i(), 0x303);
+        assert_eq!(quirk.regs.all_v()[..3], [0xFF, 1, 2]);
+    }
+
+    #[test]
+    fn test_jump_quirk() {
+        // V0 = 5, V2 = 0x10, then B220
+        let program = [0x6005, 0x6210, 0xB220];
+        assert_eq!(run(Quirks::default(), &program).regs.get_pc(), 0x225);
+        assert_eq!(run(on(), &program).regs.get_pc(), 0x230);
+    }
+
+    #[test]
+    fn test_clip_quirk() {
+        // An 8-pixel row drawn at x = 60
+        let program = [0xA300, 0x603C, 0x6100, 0xD011];
+        let wrapped = run(Quirks::default(), &program).pixels();
+        assert!(wrapped[0][60..].iter().all(|&lit| lit));
+        assert!(wrapped[0][..4].iter().all(|&lit| lit));
+        let clipped = run(on(), &program).pixels();
+        assert!(clipped[0][60..].iter().all(|&lit| lit));
+        assert!(clipped[0][..4].iter().all(|&lit| !lit));
+    }
+
+    #[test]
+    fn test_vf_reset_quirk() {
+        for op in [0x8011, 0x8012, 0x8013] {
+            let program = [0x6F05, 0x6003, 0x6105, op];
+            assert_eq!(run(Quirks::default(), &program).regs.get_v(0xF), 0, "{op:04X}");
+            assert_eq!(run(on(), &program).regs.get_v(0xF), 5, "{op:04X}");
+        }
+    }
+
+    #[test]
+    fn test_seed_repeats_rnd() {
+        let values = |seed| {
+            let keyboard = Arc::new(DataKeys::new(Arc::new(Mutex::new(false))));
+            let mut interpreter = Interpreter::new(keyboard, None);
+            interpreter.set_seed(seed);
+            let program: Vec<u8> = (0..8u16).flat_map(|x| (0xC0FF | (x << 8)).to_be_bytes()).collect();
+            interpreter.mem.write_slice(0x200, &program);
+            for _ in 0..8 {
+                interpreter.next_istr();
+            }
+            interpreter.regs.all_v()
+        };
+        assert_eq!(values(42), values(42));
+        assert_ne!(values(42), values(43));
+    }
 }
@@ -22,7 +22,7 @@ impl DataKeys {
         Self {
             buf: Mutex::new(Vec::with_capacity(16)), // because the keyboard has only 16 keys
             last_key_pressed: Mutex::new(None),
-            new_pressed: new_pressed,
+            new_pressed,
             i: 0.into(),
             waiting: AtomicBool::new(false),
         }
@@ -30,7 +30,7 @@ impl DataKeys {
 
     pub fn key_pressed(&self, key: Key) -> bool {
         let buf = self.buf.lock().unwrap();
-        buf.iter().any(|k| *k == key)
+        buf.contains(&key)
     }
 
     pub fn new_press(&self) -> bool {
@@ -38,7 +38,7 @@ impl DataKeys {
     }
 
     pub fn _last_key(&self) -> Option<Key> {
-        self.last_key_pressed.lock().unwrap().clone()
+        *self.last_key_pressed.lock().unwrap()
     }
 
     pub fn reset_new_pressed_flag(&self) {
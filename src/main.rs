@@ -1,22 +1,29 @@
+mod capture;
+mod cheats;
 mod debugger;
 mod display;
+mod gdb;
+mod history;
 mod keyboard;
 mod interpreter;
 mod memory;
+mod quirks;
 mod registers;
+mod sprites;
 
 use std::process::exit;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use std::fs::File;
 use std::path::Path;
 
 use clap::Parser;
 use crate::debugger::Debugger;
 use crate::interpreter::Interpreter;
+use crate::quirks::{Quirk, Quirks};
 
 use keyboard::{DataKeys, KeyboardState, ONEHERTZ};
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use rodio::source::{SineWave, Source};
 use rodio::{OutputStream, Sink};
 
@@ -44,6 +51,34 @@ struct Config {
     /// Debug server port
     #[arg(long = "debug-port", default_value = "9876")]
     debug_port: u16,
+
+    /// Serve the GDB remote serial protocol on this port
+    #[arg(long = "gdb")]
+    gdb: Option<u16>,
+
+    /// Run without window or audio, driven through the debug server
+    #[arg(long = "headless")]
+    headless: bool,
+
+    /// Start paused, waiting for the debugger to resume
+    #[arg(long = "paused")]
+    paused: bool,
+
+    /// Interpreter quirks to enable, comma separated
+    #[arg(long = "quirks", value_enum, value_delimiter = ',')]
+    quirks: Vec<Quirk>,
+
+    /// Seed for RND, to make runs reproducible
+    #[arg(long = "seed")]
+    seed: Option<u64>,
+
+    /// Frames the debugger keeps for `get_frame_history`/`diff_frames`
+    #[arg(long = "history", default_value_t = history::DEFAULT_LEN)]
+    history: usize,
+
+    /// Cheat list to apply every frame, one `ADDR VALUE [name]` per line
+    #[arg(long = "cheats")]
+    cheats: Option<String>,
 }
 
 fn main() {
@@ -54,14 +89,60 @@ fn main() {
         std::process::exit(2);
     }
 
+    let file = File::open(path).unwrap();
+
+    let new_key_press: Arc<Mutex<bool>> = Default::default();
+
+    let data_keys = Arc::new(DataKeys::new(new_key_press.clone()));
+    let keyboard = KeyboardState::new(data_keys.clone());
+
+    let cheats = configuration.cheats.as_ref().map(|path| {
+        std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| cheats::parse_cheats(&text))
+            .unwrap_or_else(|e| {
+                eprintln!("[ERROR] cheats '{path}': {e}");
+                exit(2);
+            })
+    });
+
+    let debugger = if configuration.debug || configuration.gdb.is_some() || cheats.is_some() {
+        let d = Arc::new(Debugger::new(Some(data_keys.clone())));
+        d.paused.store(configuration.paused, std::sync::atomic::Ordering::Relaxed);
+        d.set_history_len(configuration.history);
+        for cheat in cheats.into_iter().flatten() {
+            d.add_cheat(cheat);
+        }
+        if configuration.debug {
+            d.spawn_listener(configuration.debug_port);
+        }
+        if let Some(port) = configuration.gdb {
+            gdb::spawn_listener(d.clone(), port);
+        }
+        Some(d)
+    } else {
+        None
+    };
+
+    let mut interpreter = Interpreter::new(data_keys, debugger.clone());
+    interpreter.set_quirks(Quirks::from_list(&configuration.quirks));
+    interpreter.set_speed(configuration.speed);
+    if let Some(seed) = configuration.seed {
+        interpreter.set_seed(seed);
+    }
+    interpreter.write_rom_on_mem(file);
+
+    if configuration.headless {
+        run_headless(&mut interpreter, debugger.as_deref(), &configuration);
+        return;
+    }
+
     let (_stream, stream_handle) = OutputStream::try_default().unwrap();
     let sink = Sink::try_new(&stream_handle).unwrap();
 
     let source = SineWave::new(700.0).amplify(0.20);
     sink.append(source);
 
-    let file = File::open(path).unwrap();
-
     //Creates vector for initializing window with values of each pixel being 0
     let mut buffer = vec![0; WIDTH * HEIGHT];
     //interpreter.set_debug(true);
@@ -79,24 +160,8 @@ fn main() {
         exit(1)
     });
 
-    let new_key_press: Arc<Mutex<bool>> = Default::default();
-
-    let data_keys = Arc::new(DataKeys::new(new_key_press.clone()));
-    let keyboard = KeyboardState::new(data_keys.clone());
-
-    let debugger = if configuration.debug {
-        let d = Arc::new(Debugger::new(Some(data_keys.clone())));
-        d.spawn_listener(configuration.debug_port);
-        Some(d)
-    } else {
-        None
-    };
-
-    let mut interpreter = Interpreter::new(data_keys, debugger.clone());
     window.set_input_callback(keyboard);
 
-    interpreter.write_rom_on_mem(file);
-
     let mut cycles_count = 0;
 
     let mut fps = 0;
@@ -128,6 +193,12 @@ fn main() {
             if interpreter.to_draw() {
                 interpreter.draw(&mut buffer);
                 window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+            } else {
+                // Still wait out the frame, timers count frames
+                window.update();
+            }
+            if window.is_key_pressed(Key::F12, KeyRepeat::No) {
+                save_screenshot(&interpreter);
             }
             let now = SystemTime::now();
             let duration = now.duration_since(last_time).unwrap();
@@ -141,3 +212,41 @@ fn main() {
         }
     }
 }
+
+/// Runs `speed` instructions per frame at `fps` frames per second until the
+/// debug server receives `stop`. Frames requested with `run_frames` run as
+/// fast as possible.
+fn run_headless(interpreter: &mut Interpreter, debugger: Option<&Debugger>, configuration: &Config) {
+    let frame = Duration::from_secs_f64(1.0 / f64::from(configuration.fps));
+    loop {
+        let running = debugger
+            .map(|d| d.running.load(std::sync::atomic::Ordering::Relaxed))
+            .unwrap_or(true);
+        if !running {
+            break;
+        }
+
+        let start = Instant::now();
+        for _ in 0..configuration.speed {
+            interpreter.next_istr();
+        }
+        if !debugger.is_some_and(Debugger::frame_run_active) {
+            std::thread::sleep(frame.saturating_sub(start.elapsed()));
+        }
+    }
+}
+
+/// Saves the screen as `chip8-<unix time>.png` in the working directory, at
+/// the window's size and colours.
+fn save_screenshot(interpreter: &Interpreter) {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = format!("chip8-{secs}.png");
+    let saved = capture::png(&interpreter.pixels(), WIDTH / display::WIDTH, capture::Palette::default())
+        .and_then(|png| std::fs::write(&path, png).map_err(|e| e.to_string()));
+    match saved {
+        Ok(()) => println!("Screenshot saved to {path}"),
+        Err(e) => eprintln!("[ERROR] screenshot: {e}"),
+    }
+}
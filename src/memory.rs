@@ -48,7 +48,7 @@ impl Memory {
     pub fn write_slice(&mut self, address: u16, slice: &[u8]) {
         let address = address as usize;
         let len = slice.len();
-        if address + len >= self.buf.capacity() {
+        if address + len > self.buf.len() {
             eprintln!("[PANIC] memory overflow");
             exit(1);
         }
@@ -0,0 +1,44 @@
+use clap::ValueEnum;
+
+/// Behaviour that differs between CHIP-8 interpreters. Everything off is how
+/// this emulator has always run ROMs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift VX in place instead of copying VY into it first.
+    pub shift: bool,
+    /// `FX65` advances I past the last register, as `FX55` always does and
+    /// as the COSMAC VIP did, instead of leaving it unchanged.
+    pub load_store: bool,
+    /// `BNNN` jumps to XNN + VX instead of NNN + V0.
+    pub jump: bool,
+    /// Sprites are cut off at the right edge instead of wrapping around.
+    pub clip: bool,
+    /// `8XY1`/`8XY2`/`8XY3` leave VF alone instead of resetting it.
+    pub no_vf_reset: bool,
+}
+
+/// One quirk as named on the command line (`--quirks shift,jump`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Quirk {
+    Shift,
+    LoadStore,
+    Jump,
+    Clip,
+    NoVfReset,
+}
+
+impl Quirks {
+    pub fn from_list(list: &[Quirk]) -> Self {
+        let mut quirks = Quirks::default();
+        for quirk in list {
+            match quirk {
+                Quirk::Shift => quirks.shift = true,
+                Quirk::LoadStore => quirks.load_store = true,
+                Quirk::Jump => quirks.jump = true,
+                Quirk::Clip => quirks.clip = true,
+                Quirk::NoVfReset => quirks.no_vf_reset = true,
+            }
+        }
+        quirks
+    }
+}
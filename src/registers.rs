@@ -1,60 +1,32 @@
-use std::{fmt, process::exit, sync::{Arc, Mutex}, thread, time::{Duration, SystemTime}};
-
-use crate::keyboard::ONEHERTZ;
+use std::fmt;
 
 pub struct Registers {
     v: [u8; 16],
     i: u16,
     pc: u16,
     stack: Vec<u16>,
-    sound_timer: Arc<Mutex<u8>>,
-    delay_timer: Arc<Mutex<u8>>,
-    _decrement_thread: thread::JoinHandle<()>,
+    sound_timer: u8,
+    delay_timer: u8,
 }
 impl Registers {
 
     pub fn new() -> Self {
-        let sound_timer: Arc<Mutex<u8>> = Default::default();
-        let delay_timer: Arc<Mutex<u8>> = Default::default();
-        let sound_t = sound_timer.clone();
-        let delay_t = delay_timer.clone();
-
-        let thread = thread::spawn(move || {
-            let sound_t = sound_timer.clone();
-            let delay_t = delay_timer.clone();
-            let mut last_sys_time = SystemTime::now();
-            loop {
-                thread::sleep(Duration::from_secs_f64(ONEHERTZ));
-                {
-                    let mut sound = sound_t.lock().unwrap();
-                    let mut delay = delay_t.lock().unwrap();
-                    let new_sys_time = SystemTime::now();
-                    let difference = new_sys_time.duration_since(last_sys_time).expect("Clock may have gone backwards");
-                    last_sys_time = new_sys_time;
-                    let val = (difference.as_millis() / 16) as u8;
-                    let s = *sound;
-                    if s != 0 {
-                        *sound = s.checked_sub(val).unwrap_or(0);
-                    }
-                    let d = *delay;
-                    if d != 0 {
-                        *delay = d.checked_sub(val).unwrap_or(0);
-                    }
-                }
-            }
-        });
-
         Self {
             v: Default::default(),
             i: Default::default(),
             pc: 0x200,
             stack: Vec::with_capacity(16),
-            sound_timer: sound_t,
-            delay_timer: delay_t,
-            _decrement_thread: thread,
+            sound_timer: 0,
+            delay_timer: 0,
         }
     }
 
+    /// Counts both timers down by one; called once per emulated frame (60 Hz).
+    pub fn tick_timers(&mut self) {
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+    }
+
     pub fn all_v(&self) -> [u8; 16] {
         self.v
     }
@@ -90,26 +62,27 @@ impl Registers {
     pub fn stack_push(&mut self) {
         self.stack.push(self.pc)
     }
-    pub fn stack_pop(&mut self) {
+    /// Returns false on underflow, leaving PC unchanged.
+    pub fn stack_pop(&mut self) -> bool {
         match self.stack.pop() {
-            Some(pc) => self.pc = pc,
-            None => {
-                eprintln!("[PANIC] stack underflow ,the sp can't be < 0");
-                exit(1);
-            },
+            Some(pc) => {
+                self.pc = pc;
+                true
+            }
+            None => false,
         }
     }
     pub fn set_delay(&mut self, val: u8) {
-        *self.delay_timer.lock().unwrap() = val
+        self.delay_timer = val
     }
     pub fn get_delay(&self) -> u8 {
-        self.delay_timer.lock().unwrap().clone()
+        self.delay_timer
     }
     pub fn set_sound(&mut self, val: u8) {
-        *self.sound_timer.lock().unwrap() = val
+        self.sound_timer = val
     }
     pub fn get_sound(&self) -> u8 {
-        self.sound_timer.lock().unwrap().clone()
+        self.sound_timer
     }
 
 }
@@ -124,3 +97,21 @@ impl fmt::Display for Registers {
         write!(f, "pc: {:X} i:0x{:X}", self.pc, self.i)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_timers() {
+        let mut regs = Registers::new();
+        regs.set_delay(2);
+        regs.set_sound(1);
+        regs.tick_timers();
+        assert_eq!((regs.get_delay(), regs.get_sound()), (1, 0));
+        // Both stop at zero
+        regs.tick_timers();
+        regs.tick_timers();
+        assert_eq!((regs.get_delay(), regs.get_sound()), (0, 0));
+    }
+}
@@ -0,0 +1,43 @@
+/// Width, rows and size in bytes of a sprite `height` rows tall, where 0
+/// means a 16x16 SCHIP sprite as drawn by `DXY0`.
+pub fn size(height: usize) -> (usize, usize, usize) {
+    match height {
+        0 => (16, 16, 32),
+        _ => (8, height, height),
+    }
+}
+
+/// The pixels of the sprite at `addr`, one row per line. Rows of a 16x16
+/// sprite are two bytes, left half first. The caller checks it fits.
+pub fn decode(memory: &[u8], addr: usize, height: usize) -> Vec<Vec<bool>> {
+    let (width, rows, bytes) = size(height);
+    memory[addr..addr + bytes]
+        .chunks(bytes / rows)
+        .map(|row| (0..width).map(|x| row[x / 8] & (0x80 >> (x % 8)) != 0).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // The font's "0": a box 4 pixels wide
+        let memory = [0xF0, 0x90, 0x90, 0x90, 0xF0];
+        let rows = decode(&memory, 0, 5);
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[0], [true, true, true, true, false, false, false, false]);
+        assert_eq!(rows[1], [true, false, false, true, false, false, false, false]);
+
+        // 16x16: each row is two bytes, left half first
+        let mut memory = vec![0u8; 40];
+        memory[8] = 0x80;
+        memory[9] = 0x01;
+        let rows = decode(&memory, 8, 0);
+        assert_eq!((rows.len(), rows[0].len()), (16, 16));
+        assert!(rows[0][0] && rows[0][15]);
+        assert_eq!(rows[0].iter().filter(|&&lit| lit).count(), 2);
+        assert!(rows[1..].iter().flatten().all(|&lit| !lit));
+    }
+}